@@ -0,0 +1,488 @@
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use toml::Value;
+use toml::value::Table;
+use toml_edit::{ArrayOfTables, DocumentMut, Item};
+use rnotifylib::config::{migrate, Config, SerializableRoutedDestination};
+use rnotifylib::config::migrate::{Migration, MigrationChange};
+use rnotifylib::config::validation::Severity;
+use rnotifylib::destination::routed_destination::RoutedDestination;
+use rnotifylib::message_router::MessageRouter;
+
+/// Prints any problems with the config.
+/// Returns the exit code - non-zero if there are any errors.
+pub fn check(config: &Config) -> i32 {
+    let problems = config.validate();
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    let errors = problems.iter()
+        .filter(|p| p.get_severity() == Severity::Error)
+        .count();
+    if errors > 0 {
+        eprintln!("Config has {} error(s)", errors);
+        return 1;
+    }
+    println!("Config OK ({} destination(s), {} warning(s))", config.get_destinations().len(), problems.len());
+    0
+}
+
+/// Prints a table of the configured destinations.
+pub fn list(config: &Config) -> i32 {
+    let destinations = config.get_destinations();
+    let id_width = destinations.iter()
+        .map(|d| d.get_id().len())
+        .max()
+        .unwrap_or(0)
+        .max("ID".len());
+
+    println!("{:<id_width$}  {:<10}  ROUTING", "ID", "TYPE", id_width = id_width);
+    for dest in destinations {
+        let kind = Value::try_from(dest).ok()
+            .and_then(|v| v.get("type").and_then(|t| t.as_str()).map(|t| t.to_owned()))
+            .unwrap_or_else(|| "?".to_owned());
+        println!("{:<id_width$}  {:<10}  {:?}", dest.get_id(), kind, dest.get_routing_type(), id_width = id_width);
+    }
+    0
+}
+
+/// Prints the JSON Schema of the config file.
+pub fn schema() -> i32 {
+    match serde_json::to_string_pretty(&rnotifylib::config::schema::config_schema()) {
+        Ok(schema) => {
+            println!("{}", schema);
+            0
+        }
+        Err(err) => {
+            eprintln!("Failed to serialize schema: {}", err);
+            1
+        }
+    }
+}
+
+/// The kinds of destination that can be added with [`add`]
+#[derive(Clone, Copy, clap::ArgEnum)]
+pub enum DestinationKind {
+    File,
+    #[cfg(feature = "discord")]
+    Discord,
+    #[cfg(feature = "telegram")]
+    Telegram,
+    #[cfg(feature = "mail")]
+    Mail,
+    #[cfg(feature = "desktop")]
+    Desktop,
+}
+
+impl DestinationKind {
+    fn type_name(&self) -> &'static str {
+        match self {
+            DestinationKind::File => "File",
+            #[cfg(feature = "discord")]
+            DestinationKind::Discord => "Discord",
+            #[cfg(feature = "telegram")]
+            DestinationKind::Telegram => "Telegram",
+            #[cfg(feature = "mail")]
+            DestinationKind::Mail => "Mail",
+            #[cfg(feature = "desktop")]
+            DestinationKind::Desktop => "Desktop",
+        }
+    }
+}
+
+/// Prompts for the fields of a new destination, and appends it to the config file at the given path.
+/// Returns the exit code - non-zero if the destination was not added.
+pub fn add(config: &Config, path: &Path, kind: DestinationKind) -> i32 {
+    // Secrets are read without echoing them, which needs a terminal.
+    let hide_secrets = io::stdin().is_terminal();
+    match try_add(config, path, kind, &mut io::stdin().lock(), hide_secrets) {
+        Ok(id) => {
+            println!("Added destination '{}' to {}", id, path.display());
+            0
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+#[cfg_attr(not(any(feature = "discord", feature = "telegram", feature = "mail")), allow(unused_variables))]
+fn try_add(config: &Config, path: &Path, kind: DestinationKind, input: &mut impl BufRead, hide_secrets: bool) -> Result<String, String> {
+    let mut table = Table::new();
+    table.insert("type".to_owned(), Value::String(kind.type_name().to_owned()));
+
+    let default_id = kind.type_name().to_lowercase();
+    let id = loop {
+        let id = prompt(input, "Id", Some(&default_id))?;
+        if config.get_destinations().iter().any(|d| d.get_id() == id) {
+            eprintln!("There is already a destination with id '{}'", id);
+            continue;
+        }
+        break id;
+    };
+    table.insert("id".to_owned(), Value::String(id.clone()));
+
+    let routing_type = loop {
+        let routing_type = prompt(input, "Routing type (Root, Additive or Drain)", Some("Additive"))?;
+        if ["Root", "Additive", "Drain"].contains(&routing_type.as_str()) {
+            break routing_type;
+        }
+        eprintln!("Unknown routing type '{}'", routing_type);
+    };
+    table.insert("routing_type".to_owned(), Value::String(routing_type));
+
+    match kind {
+        DestinationKind::File => prompt_field(input, &mut table, "path", "Path of the log file")?,
+        #[cfg(feature = "discord")]
+        DestinationKind::Discord => prompt_secret_field(input, &mut table, "url", "Webhook url", hide_secrets)?,
+        #[cfg(feature = "telegram")]
+        DestinationKind::Telegram => {
+            prompt_secret_field(input, &mut table, "bot_token", "Bot token", hide_secrets)?;
+            prompt_field(input, &mut table, "chat_id", "Chat id")?;
+        }
+        #[cfg(feature = "mail")]
+        DestinationKind::Mail => {
+            prompt_field(input, &mut table, "from", "From (e.g. Rnotify <rnotify@example.com>)")?;
+            prompt_field(input, &mut table, "to", "To (e.g. Admin <admin@example.com>)")?;
+            let mut relay = Table::new();
+            prompt_field(input, &mut relay, "url", "SMTP relay host")?;
+            let port = prompt(input, "SMTP relay port", Some("25"))?;
+            let port: i64 = port.parse().map_err(|_| format!("Invalid port '{}'", port))?;
+            relay.insert("port".to_owned(), Value::Integer(port));
+            let start_tls = confirm(input, "Use STARTTLS?")?;
+            relay.insert("start_tls_relay".to_owned(), Value::Boolean(start_tls));
+            prompt_field(input, &mut relay, "username", "SMTP username")?;
+            prompt_secret_field(input, &mut relay, "password", "SMTP password", hide_secrets)?;
+            table.insert("relay".to_owned(), Value::Table(relay));
+        }
+        #[cfg(feature = "desktop")]
+        DestinationKind::Desktop => {}
+    }
+
+    let dest: SerializableRoutedDestination = Value::Table(table).try_into()
+        .map_err(|e| format!("Invalid destination: {}", e))?;
+    let problems = dest.validate();
+    if !problems.is_empty() {
+        return Err(format!("Invalid destination:\n  {}", problems.join("\n  ")));
+    }
+    let serialized = serialize_destination(&dest)?;
+    let mut doc = read_document(path)?;
+    append_destination(&mut doc, serialized)?;
+
+    if confirm(input, "Send a test message?")? {
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(dest));
+        for result in router.test_destinations(None, true) {
+            if let Err(err) = result.get_result() {
+                return Err(format!("Failed to send test message, not adding destination: {}", err));
+            }
+        }
+        println!("Sent test message");
+    }
+
+    fs::write(path, doc.to_string()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(id)
+}
+
+/// Removes the destination with the given id from the config file at the given path.
+/// Returns the exit code - non-zero if the destination was not removed.
+pub fn remove(path: &Path, id: &str) -> i32 {
+    let result = read_document(path).and_then(|mut doc| {
+        if !remove_destination(&mut doc, id) {
+            return Err(format!("No destination with id '{}' in {} (is it defined in a different config file?)", id, path.display()));
+        }
+        fs::write(path, doc.to_string()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    });
+    match result {
+        Ok(()) => {
+            println!("Removed destination '{}' from {}", id, path.display());
+            0
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+/// Rewrites the config file at the given path from the legacy format into the current format, keeping a backup.
+/// Returns the exit code - non-zero if the config file could not be migrated.
+pub fn migrate(path: &Path) -> i32 {
+    match try_migrate(path) {
+        Ok(0) => {
+            println!("{} is already up to date", path.display());
+            0
+        }
+        Ok(changes) => {
+            println!("Migrated {} ({} change(s))", path.display(), changes);
+            0
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+fn try_migrate(path: &Path) -> Result<usize, String> {
+    let mut doc = read_document(path)?;
+    let mut value: Value = toml::from_str(&doc.to_string())
+        .map_err(|e| format!("Error parsing config file {}: {}", path.display(), e))?;
+
+    let migrations = migrate::migrate(&mut value);
+    if migrations.is_empty() {
+        return Ok(0);
+    }
+    apply_migrations(&mut doc, &migrations)?;
+
+    let backup = path.with_extension("toml.bak");
+    fs::copy(path, &backup).map_err(|e| format!("Failed to back up config file to {}: {}", backup.display(), e))?;
+    fs::write(path, doc.to_string()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    for migration in &migrations {
+        println!("{}", migration);
+    }
+    println!("The old config file was saved to {}", backup.display());
+    Ok(migrations.len())
+}
+
+/// Makes the same changes as [`migrate::migrate`] to the document, leaving everything else untouched.
+fn apply_migrations(doc: &mut DocumentMut, migrations: &[Migration]) -> Result<(), String> {
+    for migration in migrations {
+        let destinations = match migration.get_profile() {
+            None => doc.get_mut("destinations"),
+            Some(profile) => doc.get_mut("profiles")
+                .and_then(|profiles| profiles.get_mut(profile))
+                .and_then(|profile| profile.get_mut("destinations")),
+        };
+        let destinations = destinations
+            .and_then(|d| d.as_array_of_tables_mut())
+            .ok_or("Can only migrate configs where destinations are written as [[destinations]]")?;
+        let table = destinations.get_mut(migration.get_destination_index())
+            .ok_or("Destination to migrate not found")?;
+        match migration.get_change() {
+            MigrationChange::GeneratedId(id) => {
+                table.insert("id", toml_edit::value(id));
+            }
+            MigrationChange::ConvertedRoot { root } => {
+                table.remove("root");
+                if *root {
+                    table.insert("routing_type", toml_edit::value("Root"));
+                }
+            }
+            MigrationChange::RemovedRoot => {
+                table.remove("root");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_document(path: &Path) -> Result<DocumentMut, String> {
+    let s = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    s.parse().map_err(|e| format!("Error parsing config file {}: {}", path.display(), e))
+}
+
+/// Serializes the destination into a table that can be added to the `destinations` array.
+fn serialize_destination(dest: &SerializableRoutedDestination) -> Result<toml_edit::Table, String> {
+    let value = Value::try_from(dest).map_err(|e| format!("Failed to serialize destination: {}", e))?;
+    let table = value.as_table().ok_or("Destination did not serialize to a table")?;
+
+    // Put the type and id first, like a hand-written config would.
+    let mut result = toml_edit::Table::new();
+    let first = ["type", "id"].iter().filter_map(|key| table.get(*key).map(|value| (key.to_string(), value)));
+    for (key, value) in first.chain(table.iter().map(|(key, value)| (key.clone(), value))) {
+        if !result.contains_key(&key) {
+            result.insert(&key, to_item(value)?);
+        }
+    }
+    Ok(result)
+}
+
+fn to_item(value: &Value) -> Result<Item, String> {
+    let item = match value {
+        Value::Table(table) => {
+            let mut result = toml_edit::Table::new();
+            for (key, value) in table {
+                result.insert(key, to_item(value)?);
+            }
+            Item::Table(result)
+        }
+        Value::Array(array) if !array.is_empty() && array.iter().all(Value::is_table) => {
+            let mut result = ArrayOfTables::new();
+            for value in array {
+                if let Item::Table(table) = to_item(value)? {
+                    result.push(table);
+                }
+            }
+            Item::ArrayOfTables(result)
+        }
+        value => toml_edit::value(value.to_string().parse::<toml_edit::Value>()
+            .map_err(|e| format!("Failed to serialize destination: {}", e))?),
+    };
+    Ok(item)
+}
+
+/// Appends the destination to the end of the `destinations` array, leaving the rest of the document untouched.
+fn append_destination(doc: &mut DocumentMut, dest: toml_edit::Table) -> Result<(), String> {
+    let destinations = doc.entry("destinations")
+        .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .ok_or("Can only add destinations to configs where destinations are written as [[destinations]]")?;
+    destinations.push(dest);
+    Ok(())
+}
+
+/// Removes the destination with the given id, returning whether it was found.
+fn remove_destination(doc: &mut DocumentMut, id: &str) -> bool {
+    let destinations = match doc.get_mut("destinations").and_then(|d| d.as_array_of_tables_mut()) {
+        Some(destinations) => destinations,
+        None => return false,
+    };
+    let index = destinations.iter()
+        .position(|table| table.get("id").and_then(|v| v.as_str()) == Some(id));
+    match index {
+        Some(index) => {
+            destinations.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Asks for a value, repeating the question until one is given.
+/// If a default is given, it is used when nothing is entered.
+fn prompt(input: &mut impl BufRead, question: &str, default: Option<&str>) -> Result<String, String> {
+    loop {
+        match default {
+            Some(default) => print!("{} [{}]: ", question, default),
+            None => print!("{}: ", question),
+        }
+        io::stdout().flush().map_err(|e| e.to_string())?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("Unexpected end of input".to_owned());
+        }
+        let line = line.trim();
+        if !line.is_empty() {
+            return Ok(line.to_owned());
+        }
+        if let Some(default) = default {
+            return Ok(default.to_owned());
+        }
+    }
+}
+
+/// Asks for a value, and sets it as the given key in the table.
+fn prompt_field(input: &mut impl BufRead, table: &mut Table, key: &str, question: &str) -> Result<(), String> {
+    let value = prompt(input, question, None)?;
+    table.insert(key.to_owned(), Value::String(value));
+    Ok(())
+}
+
+/// Asks for a value that shouldn't be shown on screen, e.g. a token, and sets it as the given key in the table.
+/// If `hide` is false, e.g. when the input isn't a terminal, this is the same as [`prompt_field`].
+#[cfg(any(feature = "discord", feature = "telegram", feature = "mail"))]
+fn prompt_secret_field(input: &mut impl BufRead, table: &mut Table, key: &str, question: &str, hide: bool) -> Result<(), String> {
+    if !hide {
+        return prompt_field(input, table, key, question);
+    }
+    loop {
+        let value = rpassword::prompt_password(format!("{}: ", question)).map_err(|e| e.to_string())?;
+        let value = value.trim();
+        if !value.is_empty() {
+            table.insert(key.to_owned(), Value::String(value.to_owned()));
+            return Ok(());
+        }
+    }
+}
+
+/// Asks a yes / no question, defaulting to no.
+fn confirm(input: &mut impl BufRead, question: &str) -> Result<bool, String> {
+    let answer = prompt(input, &format!("{} (y/N)", question), Some("n"))?;
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use rnotifylib::destination::kinds::file::FileDestination;
+    use rnotifylib::destination::routed_destination::MessageRoutingBehaviour;
+    use rnotifylib::message_router::RoutingInfo;
+    use super::*;
+
+    const CONFIG: &str = r#"# My config
+[[destinations]]
+routing_type = "Root" # Log everything
+type = "File"
+id = "log_file"
+path = "/var/log/rnotify.log"
+"#;
+
+    #[test]
+    fn test_append_remove() {
+        let mut doc: DocumentMut = CONFIG.parse().unwrap();
+        let dest = SerializableRoutedDestination::create("other_log".to_owned(), FileDestination::new(PathBuf::from("/tmp/rnotify.log")), RoutingInfo::root());
+        append_destination(&mut doc, serialize_destination(&dest).unwrap()).expect("Should append");
+
+        let s = doc.to_string();
+        assert!(s.starts_with(CONFIG), "Existing config should be untouched:\n{}", s);
+        let config: Config = toml::from_str(&s).expect("Should still be a valid config");
+        assert_eq!(config.get_destinations()[1].get_id(), "other_log");
+        assert!(config.get_destinations()[1].is_root());
+
+        assert!(remove_destination(&mut doc, "other_log"));
+        assert!(!remove_destination(&mut doc, "other_log"));
+        assert_eq!(doc.to_string(), CONFIG);
+    }
+
+    #[test]
+    fn test_append_inline() {
+        let inline = "destinations = [{ type = \"File\", id = \"log_file\", path = \"rnotify.log\" }]\n";
+        let mut doc: DocumentMut = inline.parse().unwrap();
+        let dest = SerializableRoutedDestination::create("other_log".to_owned(), FileDestination::new(PathBuf::from("/tmp/rnotify.log")), RoutingInfo::root());
+        assert!(append_destination(&mut doc, serialize_destination(&dest).unwrap()).is_err(), "Should not silently drop the destination");
+        assert_eq!(doc.to_string(), inline);
+    }
+
+    #[test]
+    fn test_add() {
+        let dir = std::env::temp_dir().join("rnotify_test_add");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, CONFIG).unwrap();
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        // Taken id, then the default id, an unknown routing type, then the default routing type.
+        let mut input = io::Cursor::new("log_file\n\nSideways\n\n/tmp/other.log\nn\n");
+        let id = try_add(&config, &path, DestinationKind::File, &mut input, false);
+        assert_eq!(id.as_deref(), Ok("file"));
+
+        let s = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(s.starts_with(CONFIG), "Existing config should be untouched:\n{}", s);
+        let config: Config = toml::from_str(&s).expect("Should still be a valid config");
+        let dest = &config.get_destinations()[1];
+        assert_eq!(dest.get_id(), "file");
+        assert_eq!(dest.get_routing_type(), &MessageRoutingBehaviour::Additive);
+
+        let mut input = io::Cursor::new("other\n");
+        assert_eq!(try_add(&config, &path, DestinationKind::File, &mut input, false), Err("Unexpected end of input".to_owned()));
+    }
+
+    #[test]
+    fn test_migrate() {
+        let s = fs::read_to_string("test/legacy.toml").unwrap();
+        let mut doc: DocumentMut = s.parse().unwrap();
+        let mut value: Value = toml::from_str(&s).unwrap();
+        apply_migrations(&mut doc, &migrate::migrate(&mut value)).expect("Should migrate");
+
+        let migrated = doc.to_string();
+        assert!(migrated.starts_with("# Config written for rnotify 0.1"), "Comments should be kept");
+        let migrated: Value = toml::from_str(&migrated).unwrap();
+        assert_eq!(migrated, value, "Should make the same changes as the config loader");
+    }
+}
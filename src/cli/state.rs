@@ -0,0 +1,130 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use rnotifylib::config::Config;
+use rnotifylib::message_router::{DeferredMessage, MessageRouter};
+use rnotifylib::send_error::SendError;
+
+pub const DEFERRED_FILE_NAME: &str = "deferred.json";
+pub const ALERTS_FILE_NAME: &str = "alerts.json";
+pub const PROGRESS_FILE_NAME: &str = "progress.json";
+
+const LOCK_FILE_NAME: &str = "state.lock";
+
+/// The files kept between runs, e.g. deferred messages, in `rnotify/` in the state directory.
+///
+/// The directory is locked for as long as this exists, so that runs at the same time
+/// wait for each other rather than overwriting each other's changes.
+pub struct State {
+    dir: Option<(PathBuf, File)>,
+}
+
+impl State {
+    /// Locks the state directory, waiting for any other run to finish with it.
+    /// If it can't be locked, a warning is printed, nothing is read and nothing can be written.
+    pub fn lock() -> Self {
+        match Self::try_lock() {
+            Ok(dir) => Self { dir: Some(dir) },
+            Err(err) => {
+                eprintln!("Failed to lock the state directory, deferred messages, alerts and progress messages won't be saved: {}", err);
+                Self { dir: None }
+            }
+        }
+    }
+
+    fn try_lock() -> Result<(PathBuf, File), String> {
+        let mut dir = dirs::state_dir().or_else(dirs::data_local_dir)
+            .ok_or("Failed to find state directory - if you're on linux, is $HOME set?")?;
+        dir.push("rnotify");
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let lock_file = OpenOptions::new().create(true).write(true).truncate(false)
+            .open(dir.join(LOCK_FILE_NAME))
+            .map_err(|e| e.to_string())?;
+        lock_file.lock().map_err(|e| e.to_string())?;
+        Ok((dir, lock_file))
+    }
+
+    /// Reads the list stored in the state file, e.g. the deferred messages.
+    pub fn read<T: DeserializeOwned>(&self, file_name: &str, description: &str) -> Vec<T> {
+        let path = match &self.dir {
+            Some((dir, _)) => dir.join(file_name),
+            None => return vec![],
+        };
+        if !path.exists() {
+            return vec![];
+        }
+        match fs::read_to_string(&path).map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string())) {
+            Ok(items) => items,
+            Err(err) => {
+                eprintln!("Failed to read {} from {}: {}", description, path.display(), err);
+                vec![]
+            }
+        }
+    }
+
+    /// Writes the list to the state file, removing the file if there is nothing in it.
+    /// The list is written to a temporary file first, so the state file is never left half written.
+    pub fn write<T: Serialize>(&self, file_name: &str, items: &[T]) -> Result<(), String> {
+        let dir = match &self.dir {
+            Some((dir, _)) => dir,
+            None => return Err("The state directory isn't locked".to_owned()),
+        };
+        let path = dir.join(file_name);
+        if items.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
+            }
+            return Ok(());
+        }
+        let temp_path = dir.join(format!("{}.tmp", file_name));
+        let s = serde_json::to_string(items).map_err(|e| e.to_string())?;
+        fs::write(&temp_path, s).map_err(|e| e.to_string())?;
+        fs::rename(&temp_path, &path).map_err(|e| e.to_string())
+    }
+}
+
+/// Sends the stored deferred messages whose quiet hours have ended.
+/// Those still in their quiet hours are kept by the router, see [`save_deferred`]
+pub fn send_deferred(state: &State, router: &MessageRouter, unix_timestamp_millis: i64, verbose: bool) -> usize {
+    let deferred: Vec<DeferredMessage> = state.read(DEFERRED_FILE_NAME, "deferred messages");
+    if deferred.is_empty() {
+        return 0;
+    }
+    if verbose {
+        println!("Attempting to send {} deferred message(s)", deferred.len());
+    }
+    let errors = router.send_deferred(deferred, unix_timestamp_millis);
+    for err in &errors {
+        eprintln!("Failed to send deferred message to destination '{}': {}", err.get_failed_destination_id(), err.get_error());
+    }
+    errors.len()
+}
+
+/// Stores the messages the router has deferred, so they can be sent by a later run.
+pub fn save_deferred(state: &State, router: &MessageRouter, verbose: bool) {
+    let deferred = router.take_deferred();
+    if verbose && !deferred.is_empty() {
+        println!("{} message(s) are deferred until quiet hours end", deferred.len());
+    }
+    if let Err(err) = state.write(DEFERRED_FILE_NAME, &deferred) {
+        eprintln!("Failed to save deferred messages: {}", err);
+    }
+}
+
+/// Sends the deferred messages whose quiet hours have ended, without sending a new message,
+/// e.g. from a cron job or systemd timer.
+/// Returns the exit code - non-zero if any deferred message failed to send.
+pub fn flush(config: Config, verbose: bool) -> i32 {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch!")
+        .as_millis();
+    let router = MessageRouter::from_config(config);
+    let state = State::lock();
+    let failed = send_deferred(&state, &router, timestamp as i64, verbose);
+    save_deferred(&state, &router, verbose);
+    if failed > 0 { 1 } else { 0 }
+}
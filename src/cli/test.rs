@@ -0,0 +1,48 @@
+use rnotifylib::config::Config;
+use rnotifylib::message_router::{MessageRouter, TestMethod};
+
+/// Tests each destination, printing a table of the results.
+/// Returns the exit code - non-zero if any destination failed.
+pub fn test_destinations(config: Config, id: Option<String>, always_send: bool) -> i32 {
+    let router = MessageRouter::from_config(config);
+    let results = router.test_destinations(id.as_deref(), always_send);
+
+    if results.is_empty() {
+        match id {
+            Some(id) => eprintln!("No destination with id '{}'", id),
+            None => eprintln!("No destinations configured"),
+        }
+        return 1;
+    }
+
+    let id_width = results.iter()
+        .map(|r| r.get_destination_id().len())
+        .max()
+        .unwrap_or(0)
+        .max("ID".len());
+
+    println!("{:<id_width$}  {:<7}  {:<10}  {:>9}  ERROR", "ID", "RESULT", "CHECK", "LATENCY", id_width = id_width);
+    let mut failed = 0;
+    for result in &results {
+        let method = match result.get_method() {
+            TestMethod::HealthCheck => "health",
+            TestMethod::TestMessage => "message",
+        };
+        let (status, error) = match result.get_result() {
+            Ok(()) => ("ok", String::new()),
+            Err(err) => {
+                failed += 1;
+                ("FAILED", err.to_string())
+            }
+        };
+        let line = format!("{:<id_width$}  {:<7}  {:<10}  {:>7}ms  {}", result.get_destination_id(), status, method,
+                           result.get_duration().as_millis(), error, id_width = id_width);
+        println!("{}", line.trim_end());
+    }
+
+    if failed > 0 {
+        eprintln!("{} of {} destination(s) failed", failed, results.len());
+        return 1;
+    }
+    0
+}
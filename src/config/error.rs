@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+use crate::config::validation::ValidationProblem;
+
+/// An error finding, reading or parsing the configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The directory that the config file should be in could not be found.
+    NoConfigDir,
+    /// A config file could not be read or created.
+    Io {
+        path: Option<PathBuf>,
+        error: io::Error,
+    },
+    /// A config file is not valid toml, or does not match the config format.
+    Parse {
+        path: Option<PathBuf>,
+        message: String,
+        line_col: Option<(usize, usize)>,
+    },
+    /// An environment variable or file referenced by the config could not be read,
+    /// see [`interpolate`](crate::config::interpolate)
+    Interpolation {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    /// The default config could not be created.
+    Default(String),
+    /// The selected profile could not be applied, see [`Config::apply_profile`](crate::config::Config::apply_profile)
+    Profile(String),
+    /// The config has errors found by [`Config::validate`](crate::config::Config::validate)
+    Invalid(Vec<ValidationProblem>),
+}
+
+impl ConfigError {
+    pub(crate) fn io(path: Option<PathBuf>, error: io::Error) -> Self {
+        Self::Io { path, error }
+    }
+
+    pub(crate) fn parse(path: Option<PathBuf>, error: toml::de::Error) -> Self {
+        Self::Parse {
+            path,
+            line_col: error.line_col(),
+            message: error.to_string(),
+        }
+    }
+
+    /// The line and column (both starting at 1) in the config file that the error occurred at, if known.
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::Parse { line_col, .. } => line_col.map(|(line, col)| (line + 1, col + 1)),
+            _ => None,
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::NoConfigDir => write!(f, "Failed to find config directory - if you're on linux, is $HOME set?"),
+            ConfigError::Io { path: Some(path), error } => write!(f, "Failed to read config file {}: {}", path.display(), error),
+            ConfigError::Io { path: None, error } => write!(f, "Failed to read config file: {}", error),
+            ConfigError::Parse { path: Some(path), message, .. } => write!(f, "Error parsing config file {}: {}", path.display(), message),
+            ConfigError::Parse { path: None, message, .. } => write!(f, "Error parsing config file: {}", message),
+            ConfigError::Interpolation { path: Some(path), message } => write!(f, "Error in config file {}: {}", path.display(), message),
+            ConfigError::Interpolation { path: None, message } => write!(f, "Error in config file: {}", message),
+            ConfigError::Default(message) => write!(f, "Failed to create default config: {}", message),
+            ConfigError::Profile(message) => write!(f, "{}", message),
+            ConfigError::Invalid(problems) => {
+                write!(f, "Config is invalid:")?;
+                for problem in problems {
+                    write!(f, "\n- {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Resolves references to environment variables and files in the config,
+//! so that secrets do not need to be stored in the config file itself.
+//!
+//! References can only be used for [`Secret`](crate::util::secret::Secret) fields:
+//! - `${VAR}` in the value is replaced with the value of the environment variable `VAR`. Use `$${` for a literal `${`.
+//! - `key = { "$file" = "/path" }` is replaced with the contents of `/path`
+//! - `key = { "$env" = "VAR" }` is replaced with the value of `VAR`
+//!
+//! The keys start with `$` so that they can't be confused with other tables in the config,
+//! e.g. the field condition `fields = { env = "prod" }`.
+//!
+//! Relative file paths are relative to the directory containing the config file.
+//! A single trailing newline is removed from file contents.
+use std::env;
+use std::fs;
+use std::path::Path;
+use toml::Value;
+
+pub(crate) const FILE_KEY: &str = "$file";
+pub(crate) const ENV_KEY: &str = "$env";
+/// Added to `$file` and `$env` tables with the value they refer to, so that it can be read without the config's directory.
+pub(crate) const VALUE_KEY: &str = "$value";
+
+/// Where the value of a [`Secret`](crate::util::secret::Secret) was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reference {
+    /// A string containing `${VAR}`, before the environment variables were replaced.
+    Template(String),
+    /// `key = { "$file" = "/path" }`
+    File(String),
+    /// `key = { "$env" = "VAR" }`
+    Env(String),
+}
+
+/// Reads the files and environment variables referred to by `$file` and `$env` tables, recursively.
+///
+/// The tables are kept, with the value added, so that each [`Secret`](crate::util::secret::Secret)
+/// knows which reference it was read from.
+pub fn interpolate(value: &mut Value, base_dir: Option<&Path>) -> Result<(), String> {
+    match value {
+        Value::Array(array) => {
+            for item in array {
+                interpolate(item, base_dir)?;
+            }
+        }
+        Value::Table(table) => {
+            if let Some(reference) = as_reference(table) {
+                let resolved = resolve(&reference, base_dir)?;
+                table.insert(VALUE_KEY.to_owned(), Value::String(resolved));
+                return Ok(());
+            }
+            for (_, item) in table.iter_mut() {
+                interpolate(item, base_dir)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The reference the table is, if it only has a `$file` or `$env` key.
+fn as_reference(table: &toml::value::Table) -> Option<Reference> {
+    if table.len() != 1 {
+        return None;
+    }
+    match table.iter().next()? {
+        (key, Value::String(path)) if key == FILE_KEY => Some(Reference::File(path.clone())),
+        (key, Value::String(var)) if key == ENV_KEY => Some(Reference::Env(var.clone())),
+        _ => None,
+    }
+}
+
+/// Reads the value of the reference. Relative file paths are relative to `base_dir`, if given.
+pub(crate) fn resolve(reference: &Reference, base_dir: Option<&Path>) -> Result<String, String> {
+    match reference {
+        Reference::Template(template) => expand_env(template),
+        Reference::File(path) => read_file(&expand_env(path)?, base_dir),
+        Reference::Env(var) => get_env(var),
+    }
+}
+
+fn read_file(path: &str, base_dir: Option<&Path>) -> Result<String, String> {
+    let path = match base_dir {
+        Some(base_dir) => base_dir.join(path),
+        None => Path::new(path).to_owned(),
+    };
+    let mut contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if contents.ends_with('\n') {
+        contents.pop();
+        if contents.ends_with('\r') {
+            contents.pop();
+        }
+    }
+    Ok(contents)
+}
+
+fn get_env(var: &str) -> Result<String, String> {
+    env::var(var).map_err(|e| format!("Environment variable '{}': {}", var, e))
+}
+
+pub(crate) fn expand_env(s: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with("$${") {
+            result.push_str("${");
+            rest = &rest[3..];
+        } else if let Some(var_start) = rest.strip_prefix("${") {
+            let end = var_start.find('}')
+                .ok_or_else(|| format!("Unclosed '${{' in '{}'", s))?;
+            result.push_str(&get_env(&var_start[..end])?);
+            rest = &var_start[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_env() {
+        env::set_var("RNOTIFY_TEST_TOKEN", "abc123");
+        assert_eq!(expand_env("bot${RNOTIFY_TEST_TOKEN}/x"), Ok("botabc123/x".to_owned()));
+        assert_eq!(expand_env("$${RNOTIFY_TEST_TOKEN} costs $5"), Ok("${RNOTIFY_TEST_TOKEN} costs $5".to_owned()));
+        assert!(expand_env("${RNOTIFY_TEST_DOES_NOT_EXIST}").is_err());
+        assert!(expand_env("${RNOTIFY_TEST_TOKEN").is_err());
+    }
+
+    #[test]
+    fn test_interpolate() {
+        env::set_var("RNOTIFY_TEST_CHAT", "1234");
+        let mut value: Value = toml::from_str(r#"
+            [[destinations]]
+            type = "Telegram"
+            bot_token = { "$file" = "secret.txt" }
+            chat_id = "${RNOTIFY_TEST_CHAT}"
+            fields = { env = "prod", file = "x" }
+            [destinations.relay]
+            username = { "$env" = "RNOTIFY_TEST_CHAT" }
+        "#).unwrap();
+
+        interpolate(&mut value, Some(Path::new("test"))).expect("Should interpolate");
+
+        let expected: Value = toml::from_str(r#"
+            [[destinations]]
+            type = "Telegram"
+            bot_token = { "$file" = "secret.txt", "$value" = "123456:abcdef" }
+            chat_id = "${RNOTIFY_TEST_CHAT}"
+            fields = { env = "prod", file = "x" }
+            [destinations.relay]
+            username = { "$env" = "RNOTIFY_TEST_CHAT", "$value" = "1234" }
+        "#).unwrap();
+        assert_eq!(value, expected);
+
+        let mut missing: Value = toml::from_str(r#"token = { "$env" = "RNOTIFY_TEST_DOES_NOT_EXIST" }"#).unwrap();
+        assert!(interpolate(&mut missing, None).is_err());
+    }
+}
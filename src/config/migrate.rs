@@ -0,0 +1,173 @@
+//! Converts configs written for rnotify 0.1 into the current format.
+//!
+//! In 0.1, destinations did not have an `id`, and root destinations were marked with `root = true`:
+//! ```toml
+//! [[destinations]]
+//! root = true
+//! type = "File"
+//! path = "/var/log/rnotify.log"
+//! ```
+//! Now every destination needs an `id`, and root destinations use `routing_type = "Root"`.
+//! Destinations added by [profiles](crate::config::profile::Profile) are converted in the same way.
+use std::fmt::{Display, Formatter};
+use toml::Value;
+use toml::value::Table;
+
+const LEGACY_ROOT_KEY: &str = "root";
+
+/// A change made to a destination in a legacy config, see [`migrate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    profile: Option<String>,
+    destination_index: usize,
+    change: MigrationChange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationChange {
+    /// The destination did not have an id, so it was given this one.
+    GeneratedId(String),
+    /// `root` was removed. If it was `true`, `routing_type` was set to `"Root"`.
+    ConvertedRoot {
+        root: bool,
+    },
+    /// `root` was removed, because `routing_type` was also set.
+    RemovedRoot,
+}
+
+impl Migration {
+    /// The name of the profile the destination is in, or `None` if it is in the top level `destinations` array.
+    pub fn get_profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// The index of the destination in the `destinations` array of the config file, or of the profile.
+    pub fn get_destination_index(&self) -> usize {
+        self.destination_index
+    }
+
+    pub fn get_change(&self) -> &MigrationChange {
+        &self.change
+    }
+}
+
+impl Display for Migration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.profile {
+            Some(profile) => write!(f, "Profile '{}' destination {}: ", profile, self.destination_index + 1)?,
+            None => write!(f, "Destination {}: ", self.destination_index + 1)?,
+        }
+        match &self.change {
+            MigrationChange::GeneratedId(id) => write!(f, "missing id, using '{}'", id),
+            MigrationChange::ConvertedRoot { root: true } => write!(f, "'root = true' is now 'routing_type = \"Root\"'"),
+            MigrationChange::ConvertedRoot { root: false } => write!(f, "'root = false' is no longer needed"),
+            MigrationChange::RemovedRoot => write!(f, "'root' is ignored because 'routing_type' is set"),
+        }
+    }
+}
+
+/// Converts any destinations in the legacy format into the current format, returning the changes made.
+///
+/// Generated ids are the destination's type in lowercase, followed by a number if needed to make them unique,
+/// e.g. `file`, `discord`, `discord_2`. Ids generated for destinations in profiles are also unique, so that
+/// they don't replace a destination in the top level `destinations` array.
+pub fn migrate(value: &mut Value) -> Vec<Migration> {
+    let mut used_ids: Vec<String> = destination_tables(value)
+        .into_iter()
+        .flat_map(|(_, destinations)| destinations.iter())
+        .filter_map(|d| d.get("id").and_then(|id| id.as_str()))
+        .map(|id| id.to_owned())
+        .collect();
+
+    let mut migrations = vec![];
+    for (profile, destinations) in destination_tables(value) {
+        for (destination_index, dest) in destinations.iter_mut().enumerate() {
+            if let Some(table) = dest.as_table_mut() {
+                migrations.extend(migrate_destination(table, &mut used_ids).into_iter()
+                    .map(|change| Migration { profile: profile.clone(), destination_index, change }));
+            }
+        }
+    }
+    migrations
+}
+
+/// The `destinations` arrays of the config and each of its profiles, with the name of the profile.
+fn destination_tables(value: &mut Value) -> Vec<(Option<String>, &mut Vec<Value>)> {
+    let table = match value.as_table_mut() {
+        Some(table) => table,
+        None => return vec![],
+    };
+    let mut result = vec![];
+    for (key, value) in table.iter_mut() {
+        match (key.as_str(), value) {
+            ("destinations", Value::Array(destinations)) => result.insert(0, (None, destinations)),
+            ("profiles", Value::Table(profiles)) => {
+                for (name, profile) in profiles.iter_mut() {
+                    if let Some(Value::Array(destinations)) = profile.get_mut("destinations") {
+                        result.push((Some(name.clone()), destinations));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+fn migrate_destination(table: &mut Table, used_ids: &mut Vec<String>) -> Vec<MigrationChange> {
+    let mut changes = vec![];
+    if let Some(Value::Boolean(root)) = table.get(LEGACY_ROOT_KEY).cloned() {
+        table.remove(LEGACY_ROOT_KEY);
+        let change = if table.contains_key("routing_type") {
+            MigrationChange::RemovedRoot
+        } else {
+            if root {
+                table.insert("routing_type".to_owned(), Value::String("Root".to_owned()));
+            }
+            MigrationChange::ConvertedRoot { root }
+        };
+        changes.push(change);
+    }
+
+    if !table.contains_key("id") {
+        let base = table.get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or("destination")
+            .to_lowercase();
+        let id = (1..)
+            .map(|n| if n == 1 { base.clone() } else { format!("{}_{}", base, n) })
+            .find(|id| !used_ids.contains(id))
+            .unwrap();
+        used_ids.push(id.clone());
+        table.insert("id".to_owned(), Value::String(id.clone()));
+        changes.push(MigrationChange::GeneratedId(id));
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let s = fs::read_to_string("test/legacy.toml").expect("Failed to read file");
+        let mut value: Value = toml::from_str(&s).unwrap();
+
+        let changes: Vec<(Option<String>, usize, MigrationChange)> = migrate(&mut value).into_iter()
+            .map(|m| (m.profile, m.destination_index, m.change))
+            .collect();
+        let debug = Some("debug".to_owned());
+        assert_eq!(changes, vec![
+            (None, 0, MigrationChange::ConvertedRoot { root: true }),
+            (None, 0, MigrationChange::GeneratedId("file".to_owned())),
+            (None, 1, MigrationChange::GeneratedId("file_2".to_owned())),
+            (None, 2, MigrationChange::ConvertedRoot { root: false }),
+            (debug.clone(), 0, MigrationChange::ConvertedRoot { root: true }),
+            (debug, 0, MigrationChange::GeneratedId("file_3".to_owned())),
+        ]);
+
+        assert!(migrate(&mut value).is_empty(), "Should not change an already migrated config");
+    }
+}
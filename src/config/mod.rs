@@ -0,0 +1,510 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Write};
+use crate::destination::routed_destination::RoutedDestination;
+use crate::destination::kinds::file::FileDestination;
+use crate::destination::{MessageDestination, SerializableDestination};
+use crate::message_router::RoutingInfo;
+use crate::config::error::ConfigError;
+use crate::config::migrate::Migration;
+use crate::config::profile::Profile;
+use crate::util::time_format::{self, TimeFormat};
+use crate::util::timezone::Timezone;
+
+pub mod error;
+pub mod interpolate;
+pub mod migrate;
+pub mod profile;
+#[cfg(feature = "schema")]
+#[cfg_attr(docsrs, doc(cfg(feature = "schema")))]
+pub mod schema;
+pub mod validation;
+
+const CONFIG_FILE_NAME: &str = "rnotify.toml";
+/// Environment variable containing the path of a config file, which is merged on top of the others.
+pub const CONFIG_ENV_VAR: &str = "RNOTIFY_CONFIG";
+#[cfg(unix)]
+const SYSTEM_CONFIG_FILE: &str = "/etc/rnotify.toml";
+#[cfg(unix)]
+const SYSTEM_CONFIG_DIR: &str = "/etc/rnotify.d";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The time zone destinations show timestamps in, unless they set their own. Defaults to the local time zone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timezone: Option<Timezone>,
+    /// The [`strftime`](chrono::format::strftime) format destinations show timestamps in, unless they set their own.
+    /// Defaults to RFC 3339.
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "time_format::deserialize_format")]
+    timestamp_format: Option<String>,
+    #[serde(default)]
+    destinations: Vec<SerializableRoutedDestination>,
+    /// Named groups of destination ids, which can be targeted together.
+    /// ```toml
+    /// [groups]
+    /// ops = ["discord_ops", "telegram_oncall"]
+    /// ```
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    groups: BTreeMap<String, Vec<String>>,
+    /// Named changes to the config that can be selected when running rnotify, see [`Profile`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, Profile>,
+    /// Changes made when loading config files in the legacy format, see [`migrate`]
+    #[serde(skip)]
+    migrations: Vec<(Option<PathBuf>, Migration)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializableRoutedDestination {
+    id: String,
+    // Whether errors with sending notifications will be reported to this destination.
+    #[serde(flatten)]
+    destination: Box<dyn SerializableDestination>,
+    #[serde(flatten)]
+    routing_info: RoutingInfo,
+}
+
+impl SerializableRoutedDestination {
+
+    pub fn new(id: String, destination: Box<dyn SerializableDestination>, routing_info: RoutingInfo) -> Self {
+        Self {
+            id,
+            destination,
+            routing_info,
+        }
+    }
+
+    pub fn create<D: SerializableDestination + 'static>(id: String, destination: D, routing_info: RoutingInfo) -> Self {
+        Self {
+            id,
+            destination: Box::new(destination),
+            routing_info
+        }
+    }
+
+    /// Checks the destination and its routing for problems, see [`Config::validate`]
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = self.destination.validate();
+        problems.extend(self.routing_info.validate());
+        problems
+    }
+}
+
+impl RoutedDestination for SerializableRoutedDestination {
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn get_destination(&self) -> &dyn MessageDestination {
+        self.destination.as_message_destination()
+    }
+
+    fn get_routing_info(&self) -> &RoutingInfo {
+        &self.routing_info
+    }
+}
+
+impl Config {
+    pub fn get_destinations(&self) -> &Vec<SerializableRoutedDestination> {
+        &self.destinations
+    }
+
+    /// Takes the destinations, with the [time format](Self::get_time_format) applied to those that don't set their own.
+    pub fn take_destinations(self) -> Vec<SerializableRoutedDestination> {
+        let time_format = self.get_time_format();
+        let mut destinations = self.destinations;
+        for dest in &mut destinations {
+            dest.destination.apply_time_defaults(&time_format);
+        }
+        destinations
+    }
+
+    /// The time zone and timestamp format for destinations that don't set their own.
+    pub fn get_time_format(&self) -> TimeFormat {
+        TimeFormat::new(self.timezone, self.timestamp_format.clone())
+            .expect("Timestamp format should have been checked when deserializing")
+    }
+
+    pub fn get_groups(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.groups
+    }
+
+    /// The changes made to convert legacy config files into the current format, along with the path
+    /// of the file they were made to.
+    /// If this isn't empty, the config files should be migrated, see [`migrate`]
+    pub fn get_migrations(&self) -> &Vec<(Option<PathBuf>, Migration)> {
+        &self.migrations
+    }
+
+    /// Resolves a list of destination ids and group names into the ids of the destinations
+    /// they refer to, without duplicates.
+    /// Returns an error if any are neither a destination id nor a group name.
+    pub fn resolve_targets(&self, targets: &[String]) -> Result<Vec<String>, String> {
+        let mut ids: Vec<String> = vec![];
+        let mut add = |id: &str| -> Result<(), String> {
+            if !self.destinations.iter().any(|d| d.get_id() == id) {
+                return Err(format!("Unknown destination '{}'", id));
+            }
+            if !ids.iter().any(|existing| existing == id) {
+                ids.push(id.to_owned());
+            }
+            Ok(())
+        };
+        for target in targets {
+            match self.groups.get(target) {
+                Some(group) => {
+                    for id in group {
+                        add(id).map_err(|e| format!("{} in group '{}'", e, target))?;
+                    }
+                }
+                None => add(target)?,
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Merges another config on top of this one.
+    ///
+    /// Destinations in `other` replace the destinations in this config with the same id,
+    /// and are otherwise added to the end. Groups and profiles with the same name are replaced,
+    /// as are the time zone and timestamp format if `other` sets them.
+    pub fn merge(&mut self, other: Config) {
+        if other.timezone.is_some() {
+            self.timezone = other.timezone;
+        }
+        if other.timestamp_format.is_some() {
+            self.timestamp_format = other.timestamp_format;
+        }
+        let existing = self.destinations.len();
+        for dest in other.destinations {
+            match self.destinations[..existing].iter().position(|d| d.get_id() == dest.get_id()) {
+                Some(i) => self.destinations[i] = dest,
+                None => self.destinations.push(dest),
+            }
+        }
+        self.groups.extend(other.groups);
+        self.profiles.extend(other.profiles);
+        self.migrations.extend(other.migrations);
+    }
+
+    fn try_default() -> Result<Self, String> {
+        let log_path = dirs::state_dir()
+            .or_else(dirs::home_dir);
+
+        if log_path.is_none() {
+            return Err("Failed to get state directory - if you're on linux, is $HOME set?".to_owned());
+        }
+        let mut log_path = log_path.unwrap();
+        log_path.push("rnotify.log");
+
+        Ok(Self {
+            timezone: None,
+            timestamp_format: None,
+            destinations: vec![
+                SerializableRoutedDestination::create("file_log".to_owned(), FileDestination::new(log_path), RoutingInfo::root()),
+            ],
+            groups: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            migrations: vec![],
+        })
+    }
+}
+
+/// Reads and parses the config from the given file.
+///
+/// Legacy configs are converted to the current format, see [`migrate`], and
+/// references to environment variables and files are resolved, see [`interpolate`]
+pub fn read_config_file(mut file: File) -> Result<Config, ConfigError> {
+    let mut s = String::new();
+    file.read_to_string(&mut s).map_err(|e| ConfigError::io(None, e))?;
+    parse_config(&s, None)
+}
+
+/// Reads and parses the config file at the given path.
+///
+/// Legacy configs are converted to the current format, see [`migrate`], and
+/// references to environment variables and files are resolved, see [`interpolate`]
+pub fn load_config_file(path: &Path) -> Result<Config, ConfigError> {
+    let s = fs::read_to_string(path).map_err(|e| ConfigError::io(Some(path.to_owned()), e))?;
+    parse_config(&s, Some(path))
+}
+
+/// Loads each of the given config files and [merges](Config::merge) them in order.
+pub fn load_config_files(paths: &[PathBuf]) -> Result<Config, ConfigError> {
+    let mut config = Config {
+        timezone: None,
+        timestamp_format: None,
+        destinations: vec![],
+        groups: BTreeMap::new(),
+        profiles: BTreeMap::new(),
+        migrations: vec![],
+    };
+    for path in paths {
+        config.merge(load_config_file(path)?);
+    }
+    Ok(config)
+}
+
+fn parse_config(s: &str, path: Option<&Path>) -> Result<Config, ConfigError> {
+    let parse_err = |e| ConfigError::parse(path.map(|p| p.to_owned()), e);
+    let mut value: toml::Value = toml::from_str(s).map_err(parse_err)?;
+    let migrations = migrate::migrate(&mut value);
+    interpolate::interpolate(&mut value, path.and_then(|p| p.parent()))
+        .map_err(|message| ConfigError::Interpolation { path: path.map(|p| p.to_owned()), message })?;
+    let mut config: Config = value.try_into().map_err(parse_err)?;
+    config.migrations = migrations.into_iter()
+        .map(|migration| (path.map(|p| p.to_owned()), migration))
+        .collect();
+    Ok(config)
+}
+
+/// Gets the path of the config file to use, which is either the given override or
+/// the rnotify.toml file in the user's config directory.
+///
+/// If the user's config file does not exist, it is created with a default config.
+pub fn fetch_config_path(verbose: bool, config_file_path_override: &Option<PathBuf>) -> Result<PathBuf, ConfigError> {
+    if let Some(path) = config_file_path_override {
+        return Ok(path.to_owned());
+    }
+
+    let mut path_buf = dirs::config_dir().ok_or(ConfigError::NoConfigDir)?;
+    path_buf.push(CONFIG_FILE_NAME);
+
+    if verbose {
+        println!("Using config file path: {}", &path_buf.display());
+    }
+
+    if !path_buf.exists() {
+        create_default_config(&path_buf)?;
+    }
+
+    Ok(path_buf)
+}
+
+/// Gets the paths of all the config files to use, in the order they should be [merged](load_config_files).
+///
+/// If a config file override is given, only that is used. Otherwise, the following are used if they exist:
+/// 1. `/etc/rnotify.toml`
+/// 2. `/etc/rnotify.d/*.toml`, in alphabetical order
+/// 3. The rnotify.toml file in the user's config directory
+/// 4. The file given by the `RNOTIFY_CONFIG` environment variable, which must exist if set.
+///
+/// If none of these exist, the user's config file is created with a default config.
+pub fn fetch_config_paths(verbose: bool, config_file_path_override: &Option<PathBuf>) -> Result<Vec<PathBuf>, ConfigError> {
+    if let Some(path) = config_file_path_override {
+        return Ok(vec![path.to_owned()]);
+    }
+
+    let mut paths = system_config_paths()?;
+
+    let mut user_path = dirs::config_dir().ok_or(ConfigError::NoConfigDir)?;
+    user_path.push(CONFIG_FILE_NAME);
+    if user_path.exists() {
+        paths.push(user_path.clone());
+    }
+
+    if let Some(env_path) = std::env::var_os(CONFIG_ENV_VAR) {
+        let env_path = PathBuf::from(env_path);
+        if !env_path.exists() {
+            return Err(ConfigError::io(Some(env_path), std::io::Error::new(std::io::ErrorKind::NotFound,
+                                                                          format!("File given by ${} does not exist", CONFIG_ENV_VAR))));
+        }
+        paths.push(env_path);
+    }
+
+    if paths.is_empty() {
+        create_default_config(&user_path)?;
+        paths.push(user_path);
+    }
+
+    if verbose {
+        for path in &paths {
+            println!("Using config file path: {}", path.display());
+        }
+    }
+
+    Ok(paths)
+}
+
+#[cfg(unix)]
+fn system_config_paths() -> Result<Vec<PathBuf>, ConfigError> {
+    let mut paths = vec![];
+    let system_file = PathBuf::from(SYSTEM_CONFIG_FILE);
+    if system_file.exists() {
+        paths.push(system_file);
+    }
+
+    let dir = Path::new(SYSTEM_CONFIG_DIR);
+    if dir.is_dir() {
+        paths.extend(drop_in_paths(dir)?);
+    }
+    Ok(paths)
+}
+
+/// Gets the paths of the config files in a drop-in directory such as `/etc/rnotify.d`,
+/// which are its `*.toml` files in alphabetical order.
+pub fn drop_in_paths(dir: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+    let entries = fs::read_dir(dir).map_err(|e| ConfigError::io(Some(dir.to_owned()), e))?;
+    let mut drop_ins = vec![];
+    for entry in entries {
+        let path = entry.map_err(|e| ConfigError::io(Some(dir.to_owned()), e))?.path();
+        if is_drop_in(&path) && path.is_file() {
+            drop_ins.push(path);
+        }
+    }
+    drop_ins.sort();
+    Ok(drop_ins)
+}
+
+/// Whether the file would be loaded if it was in a drop-in directory, see [`drop_in_paths`]
+pub fn is_drop_in(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+#[cfg(not(unix))]
+fn system_config_paths() -> Result<Vec<PathBuf>, ConfigError> {
+    Ok(vec![])
+}
+
+fn create_default_config(path: &Path) -> Result<(), ConfigError> {
+    println!("Config file doesn't exist, creating it ({})", path.display());
+    let mut file = File::options()
+        .create_new(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| ConfigError::io(Some(path.to_owned()), e))?;
+
+    let default_config = Config::try_default().map_err(ConfigError::Default)?;
+    let string = toml::to_string(&default_config)
+        .map_err(|e| ConfigError::Default(e.to_string()))?;
+    file.write_all(string.as_bytes()).map_err(|e| ConfigError::io(Some(path.to_owned()), e))?;
+    println!("Created default config file");
+    Ok(())
+}
+
+pub fn fetch_config_file(verbose: bool, config_file_path_override: &Option<PathBuf>) -> Result<File, ConfigError> {
+    let path = fetch_config_path(verbose, config_file_path_override)?;
+    File::options()
+        .read(true)
+        .open(&path)
+        .map_err(|e| ConfigError::io(Some(path), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::destination::kinds::discord::DiscordDestination;
+    use crate::destination::routed_destination::{MessageRoutingBehaviour, RoutedDestinationBase};
+    use crate::message::builder::MessageBuilder;
+    use super::*;
+
+    #[test]
+    fn test_mixed() {
+        let s = fs::read_to_string("test/mixed.toml").expect("Failed to read file");
+        let config: Config = toml::from_str(&s).expect("Failed to deserialize.");
+
+        let file_dest = FileDestination::new(PathBuf::from("/var/log/rnotify.log"));
+        let dsc_dest = DiscordDestination::new("https://discord.com/api/webhooks/11111111111111/2aaaaaaaaaaaaaaaaa".to_owned());
+
+
+        let log_file = RoutedDestinationBase::create("log_file".to_owned(), file_dest, RoutingInfo::root());
+        let dsc = RoutedDestinationBase::create("discord_destination".to_owned(), dsc_dest, RoutingInfo::of(MessageRoutingBehaviour::Additive));
+
+        assert_eq!(config.destinations[0].get_id(), log_file.get_id());
+        assert_eq!(config.destinations[0].get_routing_info(), log_file.get_routing_info());
+
+        assert_eq!(config.destinations[1].get_id(), dsc.get_id());
+        assert_eq!(config.destinations[1].get_routing_info(), dsc.get_routing_info());
+    }
+
+    #[test]
+    #[cfg(feature = "telegram")]
+    fn test_quiet_hours() {
+        use crate::destination::schedule::QuietHoursAction;
+        use crate::message::builder::MessageBuilder;
+        use crate::message::Level;
+
+        let s = fs::read_to_string("test/quiet_hours.toml").expect("Failed to read file");
+        let config: Config = toml::from_str(&s).expect("Failed to deserialize.");
+
+        let telegram = &config.destinations[1];
+        let mut builder = MessageBuilder::new();
+        // 2022-07-01T23:30:00Z
+        builder.timestamp(1656718200000);
+
+        builder.level(Level::Warn);
+        assert_eq!(telegram.get_routing_info().get_quiet_hours_action(&builder.build_clone(), 1656718200000), Some(&QuietHoursAction::Defer));
+
+        builder.level(Level::Error);
+        assert_eq!(telegram.get_routing_info().get_quiet_hours_action(&builder.build_clone(), 1656718200000), None);
+    }
+
+    #[test]
+    fn test_groups() {
+        let s = fs::read_to_string("test/groups.toml").expect("Failed to read file");
+        let config: Config = toml::from_str(&s).expect("Failed to deserialize.");
+
+        let to = |targets: &[&str]| {
+            let targets: Vec<String> = targets.iter().map(|s| s.to_string()).collect();
+            config.resolve_targets(&targets)
+        };
+
+        assert_eq!(to(&["ops"]), Ok(vec!["log_ops".to_owned(), "log_oncall".to_owned()]));
+        assert_eq!(to(&["log_file", "ops", "log_ops"]), Ok(vec!["log_file".to_owned(), "log_ops".to_owned(), "log_oncall".to_owned()]));
+        assert!(to(&["nonexistent"]).is_err());
+        assert!(to(&["broken"]).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let paths = vec![PathBuf::from("test/groups.toml"), PathBuf::from("test/drop_in.toml")];
+        let config = load_config_files(&paths).expect("Should load");
+
+        let ids: Vec<&str> = config.destinations.iter().map(|d| d.get_id()).collect();
+        assert_eq!(ids, vec!["log_file", "log_ops", "log_oncall", "log_host"]);
+        assert!(!config.destinations[1].is_root());
+        assert!(config.destinations[2].is_root(), "log_oncall should be overridden");
+
+        assert_eq!(config.groups["ops"], vec!["log_ops".to_owned(), "log_host".to_owned()]);
+        assert!(config.groups.contains_key("broken"));
+        assert_eq!(config.get_time_format().get_timezone(), Some(Timezone::Utc));
+    }
+
+    #[test]
+    fn test_legacy() {
+        let config = load_config_file(Path::new("test/legacy.toml")).expect("Should load legacy config");
+        let ids: Vec<&str> = config.destinations.iter().map(|d| d.get_id()).collect();
+        assert_eq!(ids, vec!["file", "file_2", "archive"]);
+        assert!(config.destinations[0].is_root());
+        assert!(!config.destinations[2].is_root());
+        assert_eq!(config.get_migrations().len(), 6);
+        assert_eq!(config.profiles["debug"].get_destinations()[0].get_id(), "file_3");
+        assert!(config.profiles["debug"].get_destinations()[0].is_root());
+    }
+
+    #[test]
+    fn test_field_condition() {
+        // Tables with an `env` or `file` key aren't secret references.
+        let config = load_config_file(Path::new("test/fields.toml")).expect("Should load config with field conditions");
+        let prod_log = config.destinations[1].get_routing_info();
+
+        let mut builder = MessageBuilder::new();
+        builder.field("env", "prod");
+        assert!(!prod_log.applies_to(&builder.build_clone()), "Should need every field");
+        builder.field("file", "backup.sh");
+        assert!(prod_log.applies_to(&builder.build_clone()));
+        builder.field("env", "staging");
+        assert!(!prod_log.applies_to(&builder.build()), "Should need the same value");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = load_config_file(Path::new("test/malformed.toml")).expect_err("Should fail to parse");
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        assert_eq!(err.line_col(), Some((4, 8)));
+    }
+}
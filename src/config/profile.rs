@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::config::{Config, SerializableRoutedDestination};
+use crate::destination::routed_destination::RoutedDestination;
+use crate::message_router::RoutingInfo;
+
+/// A named set of changes to the config, which can be selected when running rnotify.
+///
+/// ```toml
+/// [profiles.work]
+/// disable = ["discord_home"]
+/// [profiles.work.routing.log_file]
+/// routing_type = "Root"
+/// [[profiles.work.destinations]]
+/// type = "Discord"
+/// id = "discord_work"
+/// url = "https://discord.com/api/webhooks/.../..."
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Destinations to add, or to replace the destination with the same id.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    destinations: Vec<SerializableRoutedDestination>,
+    /// Replaces the routing of the destination with the given id.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    routing: BTreeMap<String, RoutingInfo>,
+    /// Ids of destinations that should not be used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    disable: Vec<String>,
+    /// Groups to add, or to replace the group with the same name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    groups: BTreeMap<String, Vec<String>>,
+}
+
+impl Profile {
+    pub fn get_destinations(&self) -> &Vec<SerializableRoutedDestination> {
+        &self.destinations
+    }
+
+    pub fn get_routing(&self) -> &BTreeMap<String, RoutingInfo> {
+        &self.routing
+    }
+
+    pub fn get_disable(&self) -> &Vec<String> {
+        &self.disable
+    }
+
+    pub fn get_groups(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.groups
+    }
+}
+
+impl Config {
+    pub fn get_profiles(&self) -> &BTreeMap<String, Profile> {
+        &self.profiles
+    }
+
+    /// Applies the profile with the given name to this config.
+    ///
+    /// The profile's destinations and groups are [merged](Config::merge) into the config, then its routing is
+    /// applied and the disabled destinations are removed. All profiles are removed from the config afterwards.
+    /// Returns an error if there is no such profile, or it refers to destinations that don't exist.
+    pub fn apply_profile(&mut self, name: &str) -> Result<(), String> {
+        let profile = self.profiles.remove(name)
+            .ok_or_else(|| format!("Unknown profile '{}'", name))?;
+        self.profiles.clear();
+
+        self.merge(Config {
+            timezone: None,
+            timestamp_format: None,
+            destinations: profile.destinations,
+            groups: profile.groups,
+            profiles: BTreeMap::new(),
+            migrations: vec![],
+        });
+
+        for (id, routing_info) in profile.routing {
+            let dest = self.destinations.iter_mut()
+                .find(|d| d.get_id() == id)
+                .ok_or_else(|| format!("Profile '{}' changes routing of destination '{}' which does not exist", name, id))?;
+            dest.routing_info = routing_info;
+        }
+
+        for id in &profile.disable {
+            if !self.destinations.iter().any(|d| d.get_id() == id) {
+                return Err(format!("Profile '{}' disables destination '{}' which does not exist", name, id));
+            }
+        }
+        self.destinations.retain(|d| !profile.disable.iter().any(|id| id == d.get_id()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::config::load_config_file;
+    use crate::destination::routed_destination::MessageRoutingBehaviour;
+    use super::*;
+
+    fn ids(config: &Config) -> Vec<&str> {
+        config.get_destinations().iter().map(|d| d.get_id()).collect()
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let config = load_config_file(Path::new("test/profiles.toml")).expect("Should load");
+        assert_eq!(ids(&config), vec!["log_file", "log_home"]);
+        assert_eq!(config.get_profiles().len(), 2);
+
+        let mut work = load_config_file(Path::new("test/profiles.toml")).unwrap();
+        work.apply_profile("work").expect("Should apply");
+        assert_eq!(ids(&work), vec!["log_file", "log_work"]);
+        assert_eq!(work.get_destinations()[0].get_routing_info(), &RoutingInfo::of(MessageRoutingBehaviour::Additive));
+        assert_eq!(work.get_groups()["all"], vec!["log_file".to_owned(), "log_work".to_owned()]);
+        assert!(work.get_profiles().is_empty());
+
+        let mut broken = load_config_file(Path::new("test/profiles.toml")).unwrap();
+        assert!(broken.apply_profile("broken").is_err());
+
+        let mut unknown = load_config_file(Path::new("test/profiles.toml")).unwrap();
+        assert!(unknown.apply_profile("unknown").is_err());
+    }
+}
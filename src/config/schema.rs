@@ -0,0 +1,103 @@
+//! A [JSON Schema](https://json-schema.org/) for the config file, which editors can use to
+//! autocomplete and check `rnotify.toml`.
+use schemars::gen::SchemaGenerator;
+use schemars::JsonSchema;
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, SubschemaValidation};
+use crate::config::{Config, SerializableRoutedDestination};
+use crate::message_router::RoutingInfo;
+
+/// Generates the JSON Schema for the [`Config`]
+pub fn config_schema() -> RootSchema {
+    schemars::schema_for!(Config)
+}
+
+impl JsonSchema for SerializableRoutedDestination {
+    fn schema_name() -> String {
+        "Destination".to_owned()
+    }
+
+    /// One of the destination kinds, each with the routing fields and the `type` of destination.
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        use crate::destination::kinds::file::FileDestination;
+
+        let kinds = vec![
+            destination_schema::<FileDestination>(gen, "File"),
+            #[cfg(feature = "discord")]
+            destination_schema::<crate::destination::kinds::discord::DiscordDestination>(gen, "Discord"),
+            #[cfg(feature = "telegram")]
+            destination_schema::<crate::destination::kinds::telegram::TelegramDestination>(gen, "Telegram"),
+            #[cfg(feature = "mail")]
+            destination_schema::<crate::destination::kinds::mail::MailDestination>(gen, "Mail"),
+            #[cfg(feature = "desktop")]
+            destination_schema::<crate::destination::kinds::desktop::DesktopNotificationReceiver>(gen, "Desktop"),
+        ];
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(kinds),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
+/// Creates the schema of a destination of the given kind, with the `type` set to the given name.
+fn destination_schema<T: JsonSchema>(gen: &mut SchemaGenerator, type_name: &str) -> Schema {
+    let mut schema = T::json_schema(gen).into_object();
+    let routing_info = RoutingInfo::json_schema(gen).into_object();
+    let string = gen.subschema_for::<String>();
+
+    let type_schema = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        const_value: Some(type_name.into()),
+        ..Default::default()
+    };
+
+    let object = schema.object();
+
+    object.properties.insert("type".to_owned(), type_schema.into());
+    object.required.insert("type".to_owned());
+    object.properties.insert("id".to_owned(), string);
+    object.required.insert("id".to_owned());
+    if let Some(routing_object) = routing_info.object {
+        object.properties.extend(routing_object.properties);
+    }
+    object.additional_properties = Some(Box::new(Schema::Bool(false)));
+
+    schema.metadata().title = Some(type_name.to_owned());
+    schema.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "discord")]
+    fn test_schema() {
+        let schema = serde_json::to_value(config_schema()).expect("Should serialize");
+        let destinations = &schema["definitions"]["Destination"]["oneOf"];
+        let file = destinations.as_array().unwrap().iter()
+            .find(|d| d["title"] == "File")
+            .expect("Should have a schema for file destinations");
+
+        assert_eq!(file["properties"]["type"]["const"], "File");
+        assert!(file["properties"]["routing_type"].is_object());
+        assert_eq!(file["additionalProperties"], false);
+
+        // Every field used in the example config should be in the schema.
+        let s = fs::read_to_string("test/mixed.toml").expect("Failed to read file");
+        let config: toml::Value = toml::from_str(&s).unwrap();
+        for dest in config["destinations"].as_array().unwrap() {
+            let kind = dest["type"].as_str().unwrap();
+            let kind_schema = destinations.as_array().unwrap().iter()
+                .find(|d| d["title"] == kind)
+                .unwrap_or_else(|| panic!("No schema for {}", kind));
+            for key in dest.as_table().unwrap().keys() {
+                assert!(kind_schema["properties"].get(key).is_some(), "Schema for {} is missing {}", kind, key);
+            }
+        }
+    }
+}
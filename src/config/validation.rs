@@ -0,0 +1,175 @@
+use std::fmt::{Display, Formatter};
+use crate::config::{Config, SerializableRoutedDestination};
+use crate::destination::routed_destination::RoutedDestination;
+
+/// How serious a [`ValidationProblem`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The config works, but is probably not what was intended.
+    Warning,
+    /// The config will not work as intended.
+    Error,
+}
+
+/// A problem with a config that was not caught when parsing it, see [`Config::validate`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationProblem {
+    severity: Severity,
+    destination_id: Option<String>,
+    message: String,
+}
+
+impl ValidationProblem {
+    pub fn new(severity: Severity, destination_id: Option<String>, message: String) -> Self {
+        Self {
+            severity,
+            destination_id,
+            message,
+        }
+    }
+
+    pub fn get_severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// The id of the destination that this problem is with, if it is specific to one.
+    pub fn get_destination_id(&self) -> Option<&str> {
+        self.destination_id.as_deref()
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for ValidationProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "Warning",
+            Severity::Error => "Error",
+        };
+        match &self.destination_id {
+            Some(id) => write!(f, "{} in destination '{}': {}", severity, id, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+impl Config {
+    /// Checks the config for problems that cannot be caught when parsing it, such as
+    /// duplicate destination ids or invalid webhook urls.
+    pub fn validate(&self) -> Vec<ValidationProblem> {
+        let mut problems = vec![];
+        let exists = |id: &str| self.destinations.iter().any(|d| d.get_id() == id);
+
+        for (i, dest) in self.destinations.iter().enumerate() {
+            problems.extend(validate_destination(dest, &self.destinations[..i], &exists, None));
+        }
+
+        if !self.destinations.iter().any(|d| d.is_root()) {
+            problems.push(ValidationProblem::new(Severity::Warning, None,
+                                                 "No Root destination, so errors sending messages will not be reported anywhere".to_owned()));
+        }
+
+        for (group, ids) in &self.groups {
+            for id in ids.iter().filter(|id| !exists(id)) {
+                problems.push(ValidationProblem::new(Severity::Error, None, format!("Group '{}' contains destination '{}' which does not exist", group, id)));
+            }
+            if exists(group) {
+                problems.push(ValidationProblem::new(Severity::Error, None, format!("Group '{}' has the same name as a destination, so it is ambiguous which to target", group)));
+            }
+        }
+
+        for (path, migration) in &self.migrations {
+            let message = match path {
+                Some(path) => format!("{} (in {}), run `rnotify config migrate --config-file {}` to update the config file",
+                                      migration, path.display(), path.display()),
+                None => format!("{}, the config file should be updated", migration),
+            };
+            problems.push(ValidationProblem::new(Severity::Warning, None, message));
+        }
+
+        for (name, profile) in &self.profiles {
+            let added = |id: &str| profile.get_destinations().iter().any(|d| d.get_id() == id);
+            let destinations = profile.get_destinations();
+            for (i, dest) in destinations.iter().enumerate() {
+                problems.extend(validate_destination(dest, &destinations[..i], &|id| exists(id) || added(id), Some(name)));
+            }
+            for id in profile.get_routing().keys().filter(|id| !exists(id) && !added(id)) {
+                problems.push(ValidationProblem::new(Severity::Error, None, format!("Profile '{}' changes routing of destination '{}' which does not exist", name, id)));
+            }
+            for id in profile.get_disable().iter().filter(|id| !exists(id) && !added(id)) {
+                problems.push(ValidationProblem::new(Severity::Error, None, format!("Profile '{}' disables destination '{}' which does not exist", name, id)));
+            }
+        }
+
+        problems
+    }
+}
+
+/// Checks a single destination, given the destinations before it in the same list.
+/// `profile` is the name of the profile the destination is added by, if any.
+fn validate_destination(dest: &SerializableRoutedDestination, previous: &[SerializableRoutedDestination],
+                        exists: &dyn Fn(&str) -> bool, profile: Option<&str>) -> Vec<ValidationProblem> {
+    let id = dest.get_id();
+    let error = |message: String| {
+        let message = match profile {
+            Some(profile) => format!("{} (in profile '{}')", message, profile),
+            None => message,
+        };
+        ValidationProblem::new(Severity::Error, Some(id.to_owned()), message)
+    };
+
+    let mut problems = vec![];
+    if previous.iter().any(|other| other.get_id() == id) {
+        problems.push(error("Duplicate destination id".to_owned()));
+    }
+    problems.extend(dest.validate().into_iter().map(error));
+
+    for fallback in dest.routing_info.get_fallback() {
+        if fallback == id {
+            problems.push(error("Destination cannot fall back to itself".to_owned()));
+        } else if !exists(fallback) {
+            problems.push(error(format!("Fallback destination '{}' does not exist", fallback)));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn test_valid() {
+        let s = fs::read_to_string("test/valid.toml").expect("Failed to read file");
+        let config: Config = toml::from_str(&s).expect("Failed to deserialize.");
+        assert_eq!(config.validate(), vec![]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "discord", feature = "telegram"))]
+    fn test_invalid() {
+        let s = fs::read_to_string("test/invalid.toml").expect("Failed to read file");
+        let config: Config = toml::from_str(&s).expect("Failed to deserialize.");
+
+        let problems = config.validate();
+        let problems: Vec<(Severity, Option<&str>)> = problems.iter()
+            .map(|p| (p.get_severity(), p.get_destination_id()))
+            .collect();
+
+        assert_eq!(problems, vec![
+            (Severity::Error, Some("log_file")), // Duplicate id
+            (Severity::Error, Some("log_file")), // Fallback doesn't exist
+            (Severity::Error, Some("discord")), // Invalid url
+            (Severity::Error, Some("discord")), // min_level > max_level
+            (Severity::Error, Some("telegram")), // Empty chat id
+            (Severity::Warning, None), // No root
+            (Severity::Error, None), // Group has nonexistent destination
+            (Severity::Error, None), // Group has the same name as a destination
+            (Severity::Error, Some("profile_telegram")), // Empty chat id in profile
+            (Severity::Error, None), // Profile disables nonexistent destination
+        ]);
+    }
+}
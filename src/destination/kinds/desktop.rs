@@ -4,6 +4,8 @@ use notify_rust::{Notification, Urgency};
 use serde::{Serialize, Deserialize};
 use crate::destination::{MessageDestination, SerializableDestination};
 use crate::message::{Level, Message};
+use crate::util::template::{self, Template};
+use crate::util::time_format::TimeFormat;
 
 /// Receive a notification to the desktop
 ///
@@ -12,7 +14,14 @@ use crate::message::{Level, Message};
 /// if you want these to persist - at least when running with `cargo run` / without
 /// an installation.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct DesktopNotificationReceiver {}
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DesktopNotificationReceiver {
+    /// Replaces the default layout of the message, see [`template`](crate::util::template)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<Template>,
+    #[serde(flatten)]
+    time_format: TimeFormat,
+}
 
 impl MessageDestination for DesktopNotificationReceiver {
     fn send(&self, message: &Message) -> Result<(), Box<dyn Error>> {
@@ -30,16 +39,42 @@ impl MessageDestination for DesktopNotificationReceiver {
         notification.summary(&title)
             .auto_icon();
 
-        let body = format!("{}\nFrom: {}", message.get_message_detail().raw(), message.get_author());
+        let body = match &self.template {
+            Some(template) => template.render(message, message.get_message_detail().raw(), &self.time_format, template::no_escape),
+            None => {
+                let mut body = message.get_message_detail().raw().to_owned();
+                for (key, value) in message.get_fields() {
+                    body.push_str(&format!("\n{}: {}", key, value));
+                }
+                if let Some(alert) = message.get_alert() {
+                    body.push_str(&format!("\n{}: {}", alert.get_key(), alert.describe(message.get_unix_timestamp_millis())));
+                }
+                if !message.get_attachments().is_empty() {
+                    let attachments: Vec<String> = message.get_attachments().iter()
+                        .map(|attachment| attachment.to_string())
+                        .collect();
+                    body.push_str(&format!("\nAttached: {}", attachments.join(", ")));
+                }
+                for action in message.get_actions() {
+                    body.push_str(&format!("\n{}", action));
+                }
+                body.push_str(&format!("\nFrom: {}", message.get_author()));
+                body
+            }
+        };
 
         notification.body(&body);
 
         #[cfg(all(unix, not(target_os = "macos")))]
         {
             let urgency = match message.get_level() {
+                Level::Debug => Urgency::Low,
                 Level::Info => Urgency::Normal,
+                Level::Success => Urgency::Normal,
+                Level::Notice => Urgency::Normal,
                 Level::Warn => Urgency::Normal,
                 Level::Error => Urgency::Critical,
+                Level::Critical => Urgency::Critical,
                 Level::SelfError => Urgency::Critical,
             };
 
@@ -47,9 +82,13 @@ impl MessageDestination for DesktopNotificationReceiver {
 
             // https://specifications.freedesktop.org/sound-naming-spec/sound-naming-spec-latest.html
             let sound = match message.get_level() {
+                Level::Debug => "message",
                 Level::Info => "message-new-instant",
+                Level::Success => "complete",
+                Level::Notice => "dialog-information",
                 Level::Warn => "dialog-warning",
                 Level::Error => "dialog-error",
+                Level::Critical => "alarm-clock-elapsed",
                 Level::SelfError => "dialog-error",
             };
             notification.sound_name(sound);
@@ -58,9 +97,13 @@ impl MessageDestination for DesktopNotificationReceiver {
             // https://wiki.archlinux.org/title/Desktop_notifications#Bash
             // https://specifications.freedesktop.org/icon-naming-spec/icon-naming-spec-latest.html
             let icon = match message.get_level() {
+                Level::Debug =>     "dialog-information",
                 Level::Info =>      "dialog-information",
+                Level::Success =>   "emblem-default",
+                Level::Notice =>    "dialog-information",
                 Level::Warn =>      "dialog-warning",
                 Level::Error =>     "dialog-error",
+                Level::Critical =>  "dialog-error",
                 Level::SelfError => "dialog-error",
             };
             notification.icon(icon);
@@ -78,4 +121,8 @@ impl SerializableDestination for DesktopNotificationReceiver {
     fn as_message_destination(&self) -> &dyn MessageDestination {
         self
     }
+
+    fn apply_time_defaults(&mut self, defaults: &TimeFormat) {
+        self.time_format.apply_defaults(defaults);
+    }
 }
\ No newline at end of file
@@ -1,37 +1,80 @@
+use std::borrow::Cow;
 use std::error::Error;
-use chrono::{SecondsFormat, TimeZone, Utc};
+use chrono::Utc;
 use serde::{Serialize, Deserialize};
-use crate::util::http_util;
+use crate::util::http_util::{self, FormPart};
+use crate::util::markdown::{self, MarkdownDialect, MarkdownMessageDetail};
+use crate::util::overflow::{self, Overflow};
+use crate::util::secret::Secret;
+use crate::util::template::Template;
+use crate::util::time_format::{self, TimeFormat};
+use crate::util::timezone::Timezone;
 use crate::destination::message_condition::MessageNotifyConditionConfigEntry;
 use crate::destination::{MessageDestination, SerializableDestination};
-use crate::message::formatted_detail::{FormattedMessageComponent, FormattedString, Style};
+use crate::message::formatted_detail::FormattedMessageComponent;
 use crate::message::{Level, Message, MessageDetail};
+use crate::message::action::Action;
+use crate::send_error::partial::PartialSendError;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct DiscordDestination {
-    url: String,
+    url: Secret,
     username: Option<String>,
-    #[serde(default = "Vec::new")]
+    #[serde(default)]
     notify: Vec<MessageNotifyConditionConfigEntry<String>>,
+    /// Sends the message as plain text laid out by the template, instead of as an embed,
+    /// see [`template`](crate::util::template)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<Template>,
+    /// What to do with messages that are too long for Discord, e.g. more than 4096 characters.
+    #[serde(default)]
+    overflow: Overflow,
+    /// Defaults to UTC, since the people reading the messages may be in different time zones.
+    #[serde(flatten)]
+    time_format: TimeFormat,
 }
 
 impl DiscordDestination {
     pub fn new(url: String) -> Self {
         Self {
-            url,
+            url: Secret::new(url),
             username: None,
-            notify: vec![]
+            notify: vec![],
+            template: None,
+            overflow: Overflow::default(),
+            time_format: TimeFormat::default(),
+        }
+    }
+
+    fn to_discord_message(&self, message: &Message, silent: bool) -> DiscordMessage {
+        DiscordMessage {
+            message: self.to_webhook_message(message, silent),
+            components: to_action_rows(message.get_actions()),
         }
     }
 
-    fn to_discord_message(&self, message: &Message) -> discord_webhook::models::Message {
+    fn to_webhook_message(&self, message: &Message, silent: bool) -> discord_webhook::models::Message {
         let mut discord_msg = discord_webhook::models::Message::new();
+        let time_format = self.time_format.or_timezone(Timezone::Utc);
 
-        let notify_receivers: Vec<String> = self.notify.iter().filter(|n| n.matches(message))
+        let notify_receivers: Vec<String> = self.notify.iter()
+            .filter(|_| !silent)
+            .filter(|n| n.matches(message))
             .map(|n| n.get_notify())
             .map(|s| s.to_owned())
             .collect();
 
+        if let Some(template) = &self.template {
+            let mut content = notify_receivers.join(" ");
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            content.push_str(&template.render(message, &format_detail(message.get_message_detail()), &time_format, |s| markdown::escape_markdown(s, MarkdownDialect::Discord)));
+            discord_msg.content(&content);
+            return discord_msg;
+        }
+
         if !notify_receivers.is_empty() {
             let content = notify_receivers.join(" ");
             discord_msg.content(&content);
@@ -40,13 +83,21 @@ impl DiscordDestination {
         discord_msg.embed(|embed| {
             embed.title(message.get_title().as_deref().unwrap_or("Rnotify Notification"));
 
-            let timestamp = Utc::timestamp_millis(&Utc, message.get_unix_timestamp_millis());
+            let timestamp = message.get_unix_timestamp_millis();
+            let mut timestamp_string = time_format.format(timestamp);
+            let now = Utc::now().timestamp_millis();
+            if time_format::is_late(timestamp, now) {
+                timestamp_string.push_str(&format!(" ({})", time_format::relative(timestamp, now)));
+            }
             let footer_str = format!("{} @ {}\n{} v{}",
-                                     timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+                                     timestamp_string,
                                      message.get_author(),
                                      env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
             embed.footer(&footer_str, None);
-            let color = get_color_from_level(message.get_level());
+            let color = match message.get_alert() {
+                Some(alert) if alert.is_resolved() => get_color_from_level(&Level::Success),
+                _ => get_color_from_level(message.get_level()),
+            };
             embed.color(&format!("{}", color));
 
             if let Some(component) = message.get_component() {
@@ -56,55 +107,259 @@ impl DiscordDestination {
             match message.get_message_detail() {
                 MessageDetail::Raw(raw) => { embed.description(raw); },
                 MessageDetail::Formatted(formatted) => {
+                    // Sections and tables become fields, everything else goes in the description.
+                    let mut description = String::new();
                     for component in formatted.components() {
                         match component {
                             FormattedMessageComponent::Section(title, contents) => {
-                                let string: String = contents.iter().map(|s| to_discord_format(s)).collect();
-                                embed.field(title, &string, false);
+                                embed.field(title, field_value(&markdown::to_markdown_string(contents, MarkdownDialect::Discord)), false);
                             }
-                            FormattedMessageComponent::Text(text) => {
-                                let string: String = text.iter().map(|s| to_discord_format(s)).collect();
-                                embed.description(&string);
+                            FormattedMessageComponent::Table(rows) => {
+                                for (key, value) in rows {
+                                    embed.field(key, field_value(&markdown::to_markdown_string(value, MarkdownDialect::Discord)), true);
+                                }
+                            }
+                            _ => {
+                                if !description.is_empty() && !description.ends_with('\n') {
+                                    description.push('\n');
+                                }
+                                description.push_str(&markdown::component_to_markdown(component, MarkdownDialect::Discord));
                             }
                         }
                     }
+                    if !description.is_empty() {
+                        embed.description(&description);
+                    }
                 }
             }
 
-            return embed;
+            for (key, value) in message.get_fields() {
+                embed.field(key, field_value(value), true);
+            }
+
+            if let Some(alert) = message.get_alert() {
+                embed.field(alert.get_key(), &alert.describe(timestamp), false);
+            }
+
+            embed
         });
 
         discord_msg
     }
 }
 
-fn to_discord_format(formatted_string: &FormattedString) -> String {
-    let mut result = String::from(formatted_string.get_string());
-    for style in formatted_string.get_styles() {
-        result = apply_style(&result, style);
+/// A webhook message, with the components that `discord_webhook` doesn't support.
+#[derive(Serialize, Debug)]
+struct DiscordMessage {
+    #[serde(flatten)]
+    message: discord_webhook::models::Message,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    components: Vec<ActionRow>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct ActionRow {
+    /// Always 1, an action row.
+    #[serde(rename = "type")]
+    kind: u8,
+    components: Vec<LinkButton>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct LinkButton {
+    /// Always 2, a button.
+    #[serde(rename = "type")]
+    kind: u8,
+    /// Always 5, a link button.
+    style: u8,
+    label: String,
+    url: String,
+}
+
+/// Discord rejects embed fields with an empty value, so they are shown as `-` instead.
+fn field_value(value: &str) -> &str {
+    if value.trim().is_empty() {
+        "-"
+    } else {
+        value
     }
-    result
 }
 
-fn apply_style(s: &str, style: &Style) -> String {
-    match style {
-        Style::Bold => format!("**{}**", s),
-        Style::Italics => format!("_{}_", s),
-        Style::Monospace => {
-            if s.is_empty() || s.contains('\n') {
-                format!("```\n{}\n```", s);
+/// Discord allows 5 action rows of 5 buttons, with labels up to 80 characters.
+fn to_action_rows(actions: &[Action]) -> Vec<ActionRow> {
+    actions.chunks(5)
+        .take(5)
+        .map(|actions| ActionRow {
+            kind: 1,
+            components: actions.iter()
+                .map(|action| LinkButton {
+                    kind: 2,
+                    style: 5,
+                    label: action.get_label().chars().take(80).collect(),
+                    url: action.get_url().to_owned(),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// The message detail as discord markdown, for use in templates.
+fn format_detail(detail: &MessageDetail) -> String {
+    match detail {
+        MessageDetail::Raw(raw) => raw.to_owned(),
+        MessageDetail::Formatted(formatted) => formatted.create_markdown(MarkdownDialect::Discord),
+    }
+}
+
+/// The part of the message discord returns when posting with `wait=true` that we need.
+#[derive(Deserialize)]
+struct SentMessage {
+    id: String,
+}
+
+impl DiscordDestination {
+    /// Posts the message to the webhook, uploading the message's attachments with it.
+    /// If `wait` is true, returns the id of the message that was sent.
+    fn post(&self, discord_msg: &DiscordMessage, message: &Message, wait: bool) -> Result<Option<String>, Box<dyn Error>> {
+        let mut params = vec![];
+        if wait {
+            params.push("wait=true");
+        }
+        let url = with_params(self.url.expose(), discord_msg, params);
+        if message.get_attachments().is_empty() {
+            if !wait {
+                return http_util::post_as_json_to(&url, discord_msg).map(|_| None);
             }
-            format!("`{}`", s)
-        },
-        Style::Code { lang} => format!("```{}\n{}```", lang, s),
+            let sent: SentMessage = http_util::post_as_json_for(&url, discord_msg)?;
+            return Ok(Some(sent.id));
+        }
+
+        let payload = serde_json::to_string(discord_msg)?;
+        let files = message.get_attachments().iter()
+            .map(|attachment| attachment.read())
+            .collect::<Result<Vec<_>, _>>()?;
+        let names: Vec<String> = (0..files.len()).map(|i| format!("files[{}]", i)).collect();
+
+        let mut parts = vec![FormPart::text("payload_json", &payload)];
+        for ((attachment, data), name) in message.get_attachments().iter().zip(&files).zip(&names) {
+            parts.push(FormPart::file(name, attachment.get_name(), attachment.get_mime_type(), data));
+        }
+        if !wait {
+            return http_util::post_multipart_to(&url, &parts).map(|_| None);
+        }
+        let sent: SentMessage = http_util::post_multipart_for(&url, &parts)?;
+        Ok(Some(sent.id))
     }
 }
 
+impl DiscordDestination {
+    /// Sends the message, split or truncated to fit Discord's limits. Only the first message mentions anyone.
+    /// If `wait` is true, returns the id of the first message.
+    ///
+    /// If a part fails after the first was sent, a [`PartialSendError`] is returned, so that the parts aren't sent again.
+    fn send_fitted(&self, message: &Message, silent: bool, wait: bool) -> Result<Option<String>, Box<dyn Error>> {
+        let mut id = None;
+        for (i, part) in self.fit(message, self.overflow).iter().enumerate() {
+            let sent = match self.post(&self.to_discord_message(part, silent || i > 0), part, wait && i == 0) {
+                Ok(sent) => sent,
+                Err(err) if i > 0 => return Err(Box::new(PartialSendError::new(id, err))),
+                Err(err) => return Err(err),
+            };
+            if i == 0 {
+                id = sent;
+            }
+        }
+        Ok(id)
+    }
+
+    fn fit<'a>(&self, message: &'a Message, overflow: Overflow) -> Vec<Cow<'a, Message>> {
+        overflow::fit(message, overflow, |message| self.fits(message))
+    }
+
+    /// Whether the message is within Discord's limits,
+    /// see <https://discord.com/developers/docs/resources/message#embed-object-embed-limits>
+    fn fits(&self, message: &Message) -> bool {
+        let discord_msg = self.to_webhook_message(message, false);
+        let len = |s: &str| s.chars().count();
+        let option_len = |s: &Option<String>| s.as_deref().map_or(0, len);
+        if option_len(&discord_msg.content) > 2000 {
+            return false;
+        }
+        discord_msg.embeds.iter().all(|embed| {
+            let fields_len: usize = embed.fields.iter().map(|field| len(&field.name) + len(&field.value)).sum();
+            let total = option_len(&embed.title) + option_len(&embed.description) + fields_len
+                + embed.footer.as_ref().map_or(0, |footer| len(&footer.text))
+                + embed.author.as_ref().map_or(0, |author| len(&author.name));
+            option_len(&embed.title) <= 256
+                && option_len(&embed.description) <= 4096
+                && embed.fields.len() <= 25
+                && embed.fields.iter().all(|field| len(&field.name) <= 256 && len(&field.value) <= 1024)
+                && total <= 6000
+        })
+    }
+}
+
+/// Adds the query parameters to the webhook url, including `with_components`
+/// so that webhooks not owned by an application can send link buttons.
+fn with_params(url: &str, discord_msg: &DiscordMessage, mut params: Vec<&str>) -> String {
+    if !discord_msg.components.is_empty() {
+        params.push("with_components=true");
+    }
+    if params.is_empty() {
+        return url.to_owned();
+    }
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", url, separator, params.join("&"))
+}
+
 impl MessageDestination for DiscordDestination {
     fn send(&self, message: &Message) -> Result<(), Box<dyn Error>> {
-        let discord_msg = self.to_discord_message(message);
-        //let payload = serde_json::to_string(&discord_msg)?;
-        http_util::post_as_json_to(&self.url, &discord_msg)
+        self.send_fitted(message, false, false).map(|_| ())
+    }
+
+    /// Sends the message without mentioning anyone.
+    fn send_silent(&self, message: &Message) -> Result<(), Box<dyn Error>> {
+        self.send_fitted(message, true, false).map(|_| ())
+    }
+
+    /// While the alert is firing, edits its original message if there is one, so that repeats don't flood the channel.
+    /// Webhooks can't reply to messages, so the message resolving the alert is posted as a new message,
+    /// notifying the channel that its state changed.
+    fn send_alert(&self, message: &Message, silent: bool, original: Option<&str>) -> Result<Option<String>, Box<dyn Error>> {
+        let resolved = message.get_alert().as_ref().is_some_and(|alert| alert.is_resolved());
+        match original {
+            Some(id) if !resolved => {
+                self.edit(id, message)?;
+                Ok(Some(id.to_owned()))
+            }
+            _ => self.send_editable(message, silent),
+        }
+    }
+
+    /// If the message is split, only the first message can be edited.
+    fn send_editable(&self, message: &Message, silent: bool) -> Result<Option<String>, Box<dyn Error>> {
+        self.send_fitted(message, silent, true)
+    }
+
+    /// Edits the message using the webhook, truncating it if it is too long. Attachments are not changed.
+    fn edit(&self, id: &str, message: &Message) -> Result<(), Box<dyn Error>> {
+        let message = overflow::truncate(message, |message| self.fits(message));
+        let discord_msg = self.to_discord_message(&message, true);
+        let (url, query) = match self.url.expose().split_once('?') {
+            Some((url, query)) => (url, format!("?{}", query)),
+            None => (self.url.expose(), String::new()),
+        };
+        let url = with_params(&format!("{}/messages/{}{}", url, id, query), &discord_msg, vec![]);
+        http_util::patch_as_json_to(&url, &discord_msg)
+    }
+
+    /// Checks the webhook exists, by getting its information
+    fn health_check(&self) -> Option<Result<(), Box<dyn Error>>> {
+        Some(http_util::get(self.url.expose()))
+    }
+
+    fn secrets(&self) -> Vec<&Secret> {
+        vec![&self.url]
     }
 }
 
@@ -113,13 +368,47 @@ impl SerializableDestination for DiscordDestination {
     fn as_message_destination(&self) -> &dyn MessageDestination {
         self
     }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if !is_webhook_url(self.url.expose()) {
+            problems.push("url is not a discord webhook url, expected https://discord.com/api/webhooks/<id>/<token>".to_owned());
+        }
+        problems.extend(self.notify.iter().filter_map(|n| n.validate().err()));
+        problems
+    }
+
+    fn apply_time_defaults(&mut self, defaults: &TimeFormat) {
+        self.time_format.apply_defaults(defaults);
+    }
+}
+
+fn is_webhook_url(url: &str) -> bool {
+    let path = ["https://discord.com/api/webhooks/", "https://discordapp.com/api/webhooks/",
+                "https://ptb.discord.com/api/webhooks/", "https://canary.discord.com/api/webhooks/"]
+        .iter()
+        .find_map(|prefix| url.strip_prefix(prefix));
+
+    let path = match path {
+        Some(path) => path.trim_end_matches('/'),
+        None => return false,
+    };
+    match path.split_once('/') {
+        Some((id, token)) => !id.is_empty() && id.chars().all(|c| c.is_ascii_digit())
+            && !token.is_empty() && !token.contains('/'),
+        None => false,
+    }
 }
 
 fn get_color_from_level(level: &Level) -> u32 {
     match level {
+        Level::Debug => 0x95A5A6,
         Level::Info => 0x00F4D0,
+        Level::Success => 0x2ECC71,
+        Level::Notice => 0x3498DB,
         Level::Warn => 0xFFFF00,
         Level::Error => 0xFF0000,
+        Level::Critical => 0x9B00FF,
         Level::SelfError => 0xB30000,
     }
 }
@@ -128,6 +417,61 @@ fn get_color_from_level(level: &Level) -> u32 {
 mod tests {
     use std::fs;
     use crate::destination::kinds::discord::DiscordDestination;
+    use crate::message::action::Action;
+    use crate::message::author::Author;
+    use crate::message::builder::MessageBuilder;
+    use crate::message::{Level, Message, MessageDetail};
+    use crate::util::overflow::Overflow;
+
+    #[test]
+    fn test_webhook_url() {
+        assert!(super::is_webhook_url("https://discord.com/api/webhooks/11111111111111/2aaaaaaaaaaaaaaaaa"));
+        assert!(super::is_webhook_url("https://discordapp.com/api/webhooks/11111111111111/2aaaaaaaaaaaaaaaaa"));
+        assert!(!super::is_webhook_url("https://discord.com/api/webhooks/11111111111111"));
+        assert!(!super::is_webhook_url("https://discord.com/api/webhooks/abc/2aaaaaaaaaaaaaaaaa"));
+        assert!(!super::is_webhook_url("http://discord.com/api/webhooks/11111111111111/2aaaaaaaaaaaaaaaaa"));
+        assert!(!super::is_webhook_url("https://example.com/api/webhooks/11111111111111/2aaaaaaaaaaaaaaaaa"));
+    }
+
+    #[test]
+    fn test_action_rows() {
+        let actions: Vec<Action> = (0..27).map(|i| Action::new(i, "https://example.com")).collect();
+        let rows = super::to_action_rows(&actions);
+        assert_eq!(rows.len(), 5, "Discord allows at most 5 rows");
+        assert!(rows.iter().all(|row| row.components.len() == 5));
+        assert_eq!(rows[1].components[0].label, "5");
+
+        let json = serde_json::to_value(&rows[0].components[0]).unwrap();
+        assert_eq!(json, serde_json::json!({"type": 2, "style": 5, "label": "0", "url": "https://example.com"}));
+    }
+
+    #[test]
+    fn test_empty_field() {
+        let dest = DiscordDestination::new("https://discord.com/api/webhooks/11111111111111/2aaaaaaaaaaaaaaaaa".to_string());
+        let mut builder = MessageBuilder::new();
+        builder.field("env", "");
+        let json = serde_json::to_value(dest.to_discord_message(&builder.build(), false)).unwrap();
+        assert_eq!(json["embeds"][0]["fields"][0]["value"], "-", "Discord rejects empty field values");
+    }
+
+    #[test]
+    fn test_fit() {
+        let mut dest = DiscordDestination::new("https://discord.com/api/webhooks/11111111111111/2aaaaaaaaaaaaaaaaa".to_string());
+        let line = "a".repeat(99) + "\n";
+        let message = Message::new(Level::Info, None, MessageDetail::Raw(line.repeat(50)), None, Author::parse("test".to_owned()), 0);
+        assert!(!dest.fits(&message), "Description is longer than 4096 characters");
+
+        let truncated = dest.fit(&message, dest.overflow);
+        assert_eq!(truncated.len(), 1);
+        assert!(dest.fits(&truncated[0]));
+        assert!(truncated[0].get_message_detail().raw().ends_with("\n... (1000 more bytes in message.txt)"));
+
+        dest.overflow = Overflow::Split;
+        let parts = dest.fit(&message, dest.overflow);
+        assert_eq!(parts.len(), 2);
+        assert!(parts.iter().all(|part| dest.fits(part)));
+        assert_eq!(parts[0].get_message_detail().raw(), line.repeat(40));
+    }
 
     #[test]
     fn test_deserialize() {
@@ -1,17 +1,27 @@
 use std::error::Error;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 use std::fmt::{Debug, Write};
-use std::fs;
+use std::{fs, io, process};
 use std::io::Write as IoWrite;
-use chrono::{Local, SecondsFormat, TimeZone};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use crate::destination::{MessageDestination, SerializableDestination};
-use crate::message::Message;
+use crate::message::{Message, MessageDetail};
+use crate::message::ansi::strip_ansi;
+use crate::util::plain_text::PlainTextMessageDetail;
+use crate::util::template::{self, Template};
+use crate::util::time_format::TimeFormat;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct FileDestination {
     path: PathBuf,
+    /// Replaces the default layout of the message, see [`template`](crate::util::template)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<Template>,
+    #[serde(flatten)]
+    time_format: TimeFormat,
 }
 
 impl MessageDestination for FileDestination {
@@ -21,7 +31,7 @@ impl MessageDestination for FileDestination {
                 fs::create_dir_all(parent)?;
             }
         }
-        let s = self.format_message(message);
+        let s = strip_control_characters(&self.format_message(message));
         let mut file = File::options()
             .create(true)
             .append(true)
@@ -30,6 +40,17 @@ impl MessageDestination for FileDestination {
         writeln!(&mut file, "{}", s)?;
         Ok(())
     }
+
+    /// Checks the file can be opened for writing
+    fn health_check(&self) -> Option<Result<(), Box<dyn Error>>> {
+        let result = File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map(|_| ())
+            .map_err(|e| format!("Cannot open {} for writing: {}", self.path.display(), e).into());
+        Some(result)
+    }
 }
 
 #[typetag::serde(name = "File")]
@@ -37,33 +58,178 @@ impl SerializableDestination for FileDestination {
     fn as_message_destination(&self) -> &dyn MessageDestination {
         self
     }
+
+    /// Checks that the file could be written to, without creating it.
+    fn validate(&self) -> Vec<String> {
+        if self.path.is_dir() {
+            return vec![format!("{} is a directory", self.path.display())];
+        }
+        if self.path.exists() {
+            return match File::options().append(true).open(&self.path) {
+                Ok(_) => vec![],
+                Err(err) => vec![format!("Cannot open {} for writing: {}", self.path.display(), err)],
+            };
+        }
+        // The parent directories are created if they do not exist, so find the closest one that does.
+        let existing_parent = self.path.ancestors()
+            .skip(1)
+            .map(|p| if p.as_os_str().is_empty() { Path::new(".") } else { p })
+            .find(|p| p.exists());
+        match existing_parent {
+            Some(parent) if !parent.is_dir() => vec![format!("{} is not a directory", parent.display())],
+            Some(parent) => match check_writable(parent) {
+                Ok(()) => vec![],
+                Err(err) => vec![format!("Cannot write to directory {}: {}", parent.display(), err)],
+            },
+            None => vec![format!("No parent directory of {} exists", self.path.display())],
+        }
+    }
+
+    fn apply_time_defaults(&mut self, defaults: &TimeFormat) {
+        self.time_format.apply_defaults(defaults);
+    }
 }
 
 impl FileDestination {
     pub fn new(path: PathBuf) -> Self {
         Self {
-            path
+            path,
+            template: None,
+            time_format: TimeFormat::default(),
         }
     }
 
-    // TODO: Allow custom format.
+    pub fn with_template(mut self, template: Template) -> Self {
+        self.template = Some(template);
+        self
+    }
+
     fn format_message(&self, message: &Message) -> String {
+        if let Some(template) = &self.template {
+            return template.render(message, &format_detail(message.get_message_detail()), &self.time_format, template::no_escape);
+        }
         let mut s = String::new();
-        let timestamp = Local::timestamp_millis(&Local, message.get_unix_timestamp_millis());
-        write!(s, "{} - {:?}: ", timestamp.to_rfc3339_opts(SecondsFormat::Millis, true), message.get_level()).unwrap();
+        write!(s, "{} - {:?}: ", self.time_format.format(message.get_unix_timestamp_millis()), message.get_level()).unwrap();
         if message.get_component().is_some() {
             write!(s, "[{}] ", message.get_component().as_ref().unwrap()).unwrap();
         }
         if message.get_title().is_some() {
             write!(s, "{} - ", message.get_title().as_ref().unwrap()).unwrap();
         }
-        write!(s, "'{}'", inline(message.get_message_detail().raw())).unwrap();
+        write!(s, "'{}'", inline(&format_detail(message.get_message_detail()))).unwrap();
         write!(s, " @ {}", message.get_author()).unwrap();
+        if !message.get_fields().is_empty() {
+            let fields: Vec<String> = message.get_fields().iter()
+                .map(|(key, value)| format!("{}={}", key, inline(value)))
+                .collect();
+            write!(s, " {{{}}}", fields.join(", ")).unwrap();
+        }
+        if !message.get_attachments().is_empty() {
+            let attachments: Vec<String> = message.get_attachments().iter()
+                .map(|attachment| attachment.to_string())
+                .collect();
+            write!(s, " [attachments: {}]", attachments.join(", ")).unwrap();
+        }
+        if !message.get_actions().is_empty() {
+            let actions: Vec<String> = message.get_actions().iter()
+                .map(|action| action.to_string())
+                .collect();
+            write!(s, " [actions: {}]", actions.join(", ")).unwrap();
+        }
+        if let Some(alert) = message.get_alert() {
+            write!(s, " [alert {}: {}]", alert.get_key(), alert.describe(message.get_unix_timestamp_millis())).unwrap();
+        }
         s
     }
 }
 
+/// The message detail as plain text.
+fn format_detail(detail: &MessageDetail) -> String {
+    match detail {
+        MessageDetail::Raw(raw) => raw.to_owned(),
+        MessageDetail::Formatted(formatted) => formatted.create_plain_text(),
+    }
+}
+
+/// Checks files can be created in the directory, by creating and removing an empty file,
+/// since its permissions alone don't say whether the current user can write to it.
+fn check_writable(dir: &Path) -> io::Result<()> {
+    static PROBES: AtomicUsize = AtomicUsize::new(0);
+    let probe = dir.join(format!(".rnotify-probe-{}-{}", process::id(), PROBES.fetch_add(1, Ordering::Relaxed)));
+    File::options().write(true).create_new(true).open(&probe)?;
+    fs::remove_file(&probe)
+}
+
 fn inline(s: &str) -> String {
     let vec: Vec<_> = s.lines().collect();
     vec.join("\\n")
-}
\ No newline at end of file
+}
+
+/// Removes escape sequences (e.g. colours) and other control characters, except newlines and tabs,
+/// so that they don't mess up the log file.
+fn strip_control_characters(s: &str) -> String {
+    strip_ansi(s).chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use crate::message::attachment::Attachment;
+    use crate::message::builder::MessageBuilder;
+    use crate::util::timezone::Timezone;
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let dest: FileDestination = toml::from_str("path = \"rnotify.log\"\ntemplate = \"{level}: {raw:inline}\"")
+            .expect("Should deserialize");
+        let mut builder = MessageBuilder::new();
+        builder.body(|body| {
+            body.raw("Two\nlines".to_owned());
+        });
+        assert_eq!(dest.format_message(&builder.build()), "Info: Two\\nlines");
+
+        assert!(toml::from_str::<FileDestination>("path = \"rnotify.log\"\ntemplate = \"{nonexistent}\"").is_err());
+    }
+
+    #[test]
+    fn test_time_format() {
+        let mut dest: FileDestination = toml::from_str("path = \"rnotify.log\"\ntimestamp_format = \"%H:%M\"")
+            .expect("Should deserialize");
+        dest.apply_time_defaults(&TimeFormat::new(Some(Timezone::Named(chrono_tz::Asia::Tokyo)), Some("%Y".to_owned())).unwrap());
+        let mut builder = MessageBuilder::new();
+        // 2022-07-01T12:00:00Z
+        builder.timestamp(1656676800000)
+            .body(|body| {
+                body.raw("Hello".to_owned());
+            });
+        assert!(dest.format_message(&builder.build_clone()).starts_with("21:00 - Info: "));
+
+        builder.field("env", "prod").field("job_id", 123);
+        assert!(dest.format_message(&builder.build_clone()).ends_with(" {env=prod, job_id=123}"));
+
+        builder.attach(Attachment::from_bytes("build.log", vec![b'a'; 20]));
+        assert!(dest.format_message(&builder.build_clone()).ends_with(" {env=prod, job_id=123} [attachments: build.log (20 B)]"));
+
+        builder.action("Runbook", "https://wiki.example.com/backup");
+        assert!(dest.format_message(&builder.build()).ends_with(" [attachments: build.log (20 B)] [actions: Runbook: https://wiki.example.com/backup]"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let dir = std::env::temp_dir().join("rnotify_test_file_validate");
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(FileDestination::new(dir.join("logs/rnotify.log")).validate(), Vec::<String>::new());
+        assert_eq!(FileDestination::new(dir.clone()).validate().len(), 1, "Should not be a directory");
+        fs::write(dir.join("file"), "").unwrap();
+        assert_eq!(FileDestination::new(dir.join("file/rnotify.log")).validate().len(), 1, "Parent should not be a file");
+        assert!(!dir.join("logs").exists(), "Should not create anything");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "Should clean up after checking");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strip_control_characters() {
+        assert_eq!(strip_control_characters("\u{1b}[31mred\u{1b}[0m\tdone\r\u{7}\nnext"), "red\tdone\nnext");
+    }
+}
@@ -1,21 +1,37 @@
+use std::collections::BTreeMap;
 use std::error::Error;
-use lettre::message::{Mailbox, SinglePart};
+use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
 use lettre::{SmtpTransport, Transport};
 use lettre::transport::smtp::authentication;
 use serde::{Serialize, Deserialize};
 use crate::destination::{MessageDestination, SerializableDestination};
 use crate::message::{Message, MessageDetail};
-use crate::util::html::HtmlMessageDetail;
+use crate::message::formatted_detail::{FormattedMessageComponent, FormattedMessageDetail, FormattedString, Style};
+use crate::util::html::{HtmlDialect, HtmlMessageDetail};
+use crate::util::secret::Secret;
+use crate::util::template::{self, Template};
+use crate::util::time_format::TimeFormat;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MailDestination {
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     from: Mailbox,
     relay: Relay,
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     to: Mailbox,
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
     reply_to: Option<Mailbox>,
+    /// Replaces the default layout of the message, see [`template`](crate::util::template)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<Template>,
+    #[serde(flatten)]
+    time_format: TimeFormat,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Relay {
     url: String,
     #[serde(default = "default_port")]
@@ -23,11 +39,11 @@ pub struct Relay {
     #[serde(default = "bool::default")]
     start_tls_relay: bool,
     username: String,
-    password: String,
+    password: Secret,
 }
 
 fn default_port() -> u16 {
-    return lettre::transport::smtp::SMTP_PORT;
+    lettre::transport::smtp::SMTP_PORT
 }
 
 impl MessageDestination for MailDestination {
@@ -43,19 +59,56 @@ impl MessageDestination for MailDestination {
         if let Some(title) = message.get_title() {
             message_builder = message_builder.subject(title);
         }
+        if !message.get_fields().is_empty() {
+            message_builder = message_builder.header(FieldsHeader(message.get_fields().clone()));
+        }
 
-        let email = message_builder.singlepart(create_body(message.get_message_detail()))?;
+        let body = match &self.template {
+            Some(template) => SinglePart::plain(template.render(message, message.get_message_detail().raw(), &self.time_format, template::no_escape)),
+            None => create_body(message),
+        };
+        let email = if message.get_attachments().is_empty() {
+            message_builder.singlepart(body)?
+        } else {
+            let mut multipart = MultiPart::mixed().singlepart(body);
+            for attachment in message.get_attachments() {
+                let content_type = ContentType::parse(attachment.get_mime_type())?;
+                multipart = multipart.singlepart(lettre::message::Attachment::new(attachment.get_name().to_owned())
+                    .body(attachment.read()?.into_owned(), content_type));
+            }
+            message_builder.multipart(multipart)?
+        };
 
-        let creds = authentication::Credentials::new(self.relay.username.clone(), self.relay.password.clone());
-        let mailer =
-            if self.relay.start_tls_relay { SmtpTransport::starttls_relay(&self.relay.url) } else { SmtpTransport::relay(&self.relay.url) }?.port(self.relay.port)
-                .credentials(creds)
-                .build();
+        let mailer = self.relay.create_transport()?;
 
         mailer.send(&email)?;
         println!("Mail successfully sent.");
         Ok(())
     }
+
+    /// Connects to the relay, and checks it responds to `NOOP`
+    fn health_check(&self) -> Option<Result<(), Box<dyn Error>>> {
+        let result = self.relay.create_transport()
+            .and_then(|mailer| mailer.test_connection())
+            .map_err(|e| e.into())
+            .and_then(|connected| if connected { Ok(()) } else { Err("Failed to connect to relay".into()) });
+        Some(result)
+    }
+
+    fn secrets(&self) -> Vec<&Secret> {
+        vec![&self.relay.password]
+    }
+}
+
+impl Relay {
+    fn create_transport(&self) -> Result<SmtpTransport, lettre::transport::smtp::Error> {
+        let creds = authentication::Credentials::new(self.username.clone(), self.password.expose().to_owned());
+        let mailer =
+            if self.start_tls_relay { SmtpTransport::starttls_relay(&self.url) } else { SmtpTransport::relay(&self.url) }?.port(self.port)
+                .credentials(creds)
+                .build();
+        Ok(mailer)
+    }
 }
 
 #[typetag::serde(name = "Mail")]
@@ -63,15 +116,71 @@ impl SerializableDestination for MailDestination {
     fn as_message_destination(&self) -> &dyn MessageDestination {
         self
     }
+
+    fn apply_time_defaults(&mut self, defaults: &TimeFormat) {
+        self.time_format.apply_defaults(defaults);
+    }
 }
 
-fn create_body(detail: &MessageDetail) -> SinglePart {
-    match detail {
+fn create_body(message: &Message) -> SinglePart {
+    let fields = message.get_fields();
+    match message.get_message_detail() {
         MessageDetail::Raw(raw) => {
-            SinglePart::plain(raw.to_owned())
+            let mut body = raw.to_owned();
+            if !fields.is_empty() {
+                body.push('\n');
+            }
+            for (key, value) in fields {
+                body.push_str(&format!("\n{}: {}", key, value));
+            }
+            if !message.get_actions().is_empty() {
+                body.push('\n');
+            }
+            for action in message.get_actions() {
+                body.push_str(&format!("\n{}", action));
+            }
+            SinglePart::plain(body)
         }
         MessageDetail::Formatted(formatted) => {
-            SinglePart::html(formatted.create_html())
+            let mut html = formatted.create_html(HtmlDialect::Standard);
+            if !fields.is_empty() {
+                let rows = fields.iter()
+                    .map(|(key, value)| (key.to_owned(), vec![FormattedString::plain(value)]))
+                    .collect();
+                html.push_str(&FormattedMessageDetail::new(String::new(), vec![FormattedMessageComponent::Table(rows)]).create_html(HtmlDialect::Standard));
+            }
+            if !message.get_actions().is_empty() {
+                let links = message.get_actions().iter()
+                    .map(|action| vec![FormattedString::styled(action.get_label(), Style::Link { url: action.get_url().to_owned() })])
+                    .collect();
+                html.push_str(&FormattedMessageDetail::new(String::new(), vec![FormattedMessageComponent::List(links)]).create_html(HtmlDialect::Standard));
+            }
+            SinglePart::html(html)
         }
     }
+}
+
+/// The message's fields, as `X-Rnotify-Fields: env=prod; job_id=123`
+#[derive(Debug, Clone)]
+struct FieldsHeader(BTreeMap<String, String>);
+
+impl Header for FieldsHeader {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("X-Rnotify-Fields")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let fields = s.split(';')
+            .filter_map(|field| field.trim().split_once('='))
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        Ok(Self(fields))
+    }
+
+    fn display(&self) -> HeaderValue {
+        let value: Vec<String> = self.0.iter()
+            .map(|(key, value)| format!("{}={}", key, value.replace(['\r', '\n'], " ")))
+            .collect();
+        HeaderValue::new(Self::name(), value.join("; "))
+    }
 }
\ No newline at end of file
@@ -39,6 +39,6 @@ impl<'de> Deserialize<'de> for RustReceiverDestination {
 
 impl PartialEq for RustReceiverDestination {
     fn eq(&self, _other: &Self) -> bool {
-        return false;
+        false
     }
 }
\ No newline at end of file
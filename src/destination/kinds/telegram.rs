@@ -1,19 +1,35 @@
+use std::borrow::Cow;
 use std::error::Error;
 use std::fmt::Debug;
-use chrono::{Local, SecondsFormat, TimeZone};
+use chrono::Utc;
 use serde::{Serialize, Deserialize};
-use crate::util::http_util;
+use crate::util::html::{escape_html, HtmlDialect, HtmlMessageDetail};
+use crate::util::http_util::{self, FormPart};
+use crate::util::overflow::{self, Overflow};
+use crate::util::secret::Secret;
+use crate::util::template::Template;
+use crate::util::time_format::{self, TimeFormat};
 use crate::destination::message_condition::MessageNotifyConditionConfigEntry;
 use crate::destination::{MessageDestination, SerializableDestination};
-use crate::message::formatted_detail::{FormattedMessageComponent, FormattedString, Style};
 use crate::message::{Message, MessageDetail};
+use crate::message::action::Action;
+use crate::send_error::partial::PartialSendError;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TelegramDestination {
-    bot_token: String,
+    bot_token: Secret,
     chat_id: String,
-    #[serde(default = "Vec::new")]
+    #[serde(default)]
     notify: Vec<MessageNotifyConditionConfigEntry<bool>>,
+    /// Replaces the default layout of the message, see [`template`](crate::util::template)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<Template>,
+    /// What to do with messages that are longer than Telegram's limit of 4096 characters.
+    #[serde(default)]
+    overflow: Overflow,
+    #[serde(flatten)]
+    time_format: TimeFormat,
 }
 
 #[derive(Serialize, Debug)]
@@ -23,11 +39,65 @@ struct TelegramMessage {
     disable_notification: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     parse_mode: Option<ParseMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to_message_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_markup: Option<InlineKeyboardMarkup>,
+}
+
+#[derive(Serialize, Debug)]
+struct EditMessageText {
+    chat_id: String,
+    message_id: i64,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<ParseMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_markup: Option<InlineKeyboardMarkup>,
+}
+
+/// The message's actions, as url buttons below it, one per row.
+#[derive(Serialize, Debug, PartialEq)]
+struct InlineKeyboardMarkup {
+    inline_keyboard: Vec<Vec<InlineKeyboardButton>>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct InlineKeyboardButton {
+    text: String,
+    url: String,
+}
+
+impl InlineKeyboardMarkup {
+    fn from_actions(actions: &[Action]) -> Option<Self> {
+        if actions.is_empty() {
+            return None;
+        }
+        let inline_keyboard = actions.iter()
+            .map(|action| vec![InlineKeyboardButton {
+                text: action.get_label().to_owned(),
+                url: action.get_url().to_owned(),
+            }])
+            .collect();
+        Some(Self { inline_keyboard })
+    }
+}
+
+/// The response from `sendMessage`
+#[derive(Deserialize)]
+struct SendMessageResponse {
+    result: SentMessage,
+}
+
+#[derive(Deserialize)]
+struct SentMessage {
+    message_id: i64,
 }
 
 #[derive(Serialize, Debug)]
 enum ParseMode {
-    HTML,
+    #[serde(rename = "HTML")]
+    Html,
 }
 
 impl TelegramMessage {
@@ -37,76 +107,198 @@ impl TelegramMessage {
             text: message,
             disable_notification: !notify,
             parse_mode,
+            reply_to_message_id: None,
+            reply_markup: None,
         }
     }
 }
 
 impl TelegramDestination {
-    fn to_tg_message(&self, message: &Message) -> TelegramMessage {
-        let html_formatting = message.get_message_detail().has_formatting();
+    fn to_tg_message(&self, message: &Message, silent: bool) -> TelegramMessage {
+        // Templates always use HTML, since their fields are escaped for it.
+        let html_formatting = self.template.is_some() || message.get_message_detail().has_formatting();
+        let content = match &self.template {
+            Some(template) => template.render(message, &format_detail(message.get_message_detail()), &self.time_format, escape_html),
+            None => format_default(message, html_formatting, &self.time_format),
+        };
+
+        let parse_mode = if html_formatting { Some(ParseMode::Html) } else { None };
+        let notify = !silent && self.notify.iter()
+            .filter(|n| n.matches(message))
+            .map(|n| n.get_notify())
+            .any(|b| *b);
+        let mut tg_message = TelegramMessage::new(self.chat_id.clone(), content, notify, parse_mode);
+        tg_message.reply_markup = InlineKeyboardMarkup::from_actions(message.get_actions());
+        tg_message
+    }
+}
 
-        let mut content = String::new();
-        content.push_str(&format!("{:?}", message.get_level()));
-        if let Some(title) = message.get_title() {
+fn format_default(message: &Message, html_formatting: bool, time_format: &TimeFormat) -> String {
+    let mut content = String::new();
+    content.push_str(&format!("{:?}", message.get_level()));
+    if let Some(title) = message.get_title() {
+        if html_formatting {
+            content.push_str(&format!(": <b>{}</b>", escape_html(title)));
+        } else {
+            content.push_str(&format!(": {}", title));
+        }
+    }
+    content.push('\n');
+    if let Some(alert) = message.get_alert() {
+        let state = alert.describe(message.get_unix_timestamp_millis());
+        if html_formatting {
+            content.push_str(&format!("<b>{}</b>: {}\n", escape_html(alert.get_key()), state));
+        } else {
+            content.push_str(&format!("{}: {}\n", alert.get_key(), state));
+        }
+    }
+    match message.get_message_detail() {
+        MessageDetail::Raw(raw) if !html_formatting => content.push_str(raw),
+        detail => content.push_str(&format_detail(detail)),
+    }
+
+    if !message.get_fields().is_empty() {
+        content.push('\n');
+        for (key, value) in message.get_fields() {
             if html_formatting {
-                content.push_str(&format!(": <b>{}</b>", title));
+                content.push_str(&format!("<b>{}</b>: {}\n", escape_html(key), escape_html(value)));
             } else {
-                content.push_str(&format!(": {}", title));
+                content.push_str(&format!("{}: {}\n", key, value));
             }
         }
-        content.push('\n');
+    }
+
+    content.push('\n');
+    content.push_str("-----\n");
+    let timestamp = message.get_unix_timestamp_millis();
+    let mut timestamp_string = time_format.format(timestamp);
+    let now = Utc::now().timestamp_millis();
+    if time_format::is_late(timestamp, now) {
+        timestamp_string.push_str(&format!(" ({})", time_format::relative(timestamp, now)));
+    }
+    if html_formatting {
+        content.push_str(&format!("<pre>{}</pre>", timestamp_string));
+    } else {
+        content.push_str(&timestamp_string);
+    }
+
+    content.push('\n');
+    content.push_str(&format!("@ {}", message.get_author()));
+    content
+}
+
+fn format_detail(detail: &MessageDetail) -> String {
+    match detail {
+        MessageDetail::Raw(raw) => escape_html(raw),
+        MessageDetail::Formatted(formatted) => formatted.create_html(HtmlDialect::Telegram),
+    }
+}
 
-        match message.get_message_detail() {
-            MessageDetail::Raw(raw) => content.push_str(raw),
-            MessageDetail::Formatted(formatted) => {
-                for component in formatted.components() {
-                    match component {
-                        FormattedMessageComponent::Section(title, section_content) => {
-                            content.push_str(&format!("<b><u>{}</u></b>\n", title));
-                            for part in section_content {
-                                content.push_str(&to_tg_format(part))
-                            }
-                        }
-                        FormattedMessageComponent::Text(parts) => {
-                            for part in parts {
-                                content.push_str(&to_tg_format(part))
-                            }
-                        }
-                    }
-                }
+impl TelegramDestination {
+    /// Sends the message, returning its id.
+    fn send_tg_message(&self, message: &TelegramMessage) -> Result<i64, Box<dyn Error>> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token.expose());
+
+        let response: SendMessageResponse = http_util::post_as_json_for(&url, message)?;
+        Ok(response.result.message_id)
+    }
+
+    /// Sends the message, split or truncated to fit in a Telegram message, with its attachments,
+    /// returning the id of the first message. Only the first message notifies anyone, and replies to `reply_to`.
+    ///
+    /// The attachments are read before anything is sent. If a part or attachment fails after the first part was sent,
+    /// a [`PartialSendError`] is returned, so that the parts that were delivered aren't sent again.
+    fn send_fitted(&self, message: &Message, silent: bool, reply_to: Option<i64>) -> Result<i64, Box<dyn Error>> {
+        let parts = overflow::fit(message, self.overflow, |message| self.fits(message));
+        let files = parts.iter()
+            .map(|part| part.get_attachments().iter().map(|attachment| attachment.read()).collect::<Result<Vec<_>, _>>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut first_id = None;
+        for (part, files) in parts.iter().zip(&files) {
+            let mut tg_message = self.to_tg_message(part, silent || first_id.is_some());
+            if first_id.is_none() {
+                tg_message.reply_to_message_id = reply_to;
+            }
+            let result = self.send_tg_message(&tg_message)
+                .map(|message_id| *first_id.get_or_insert(message_id))
+                .and_then(|_| self.send_attachments(part, files));
+            if let Err(err) = result {
+                return match first_id {
+                    Some(first_id) => Err(Box::new(PartialSendError::new(Some(first_id.to_string()), err))),
+                    None => Err(err),
+                };
             }
         }
+        first_id.ok_or_else(|| "No message was sent".into())
+    }
 
+    /// Whether the message is within Telegram's limit of 4096 characters.
+    /// This counts formatting tags, which Telegram doesn't, so is on the safe side.
+    fn fits(&self, message: &Message) -> bool {
+        self.to_tg_message(message, true).text.chars().count() <= 4096
+    }
 
-        content.push('\n');
-        content.push_str("-----\n");
-        let timestamp = Local::timestamp_millis(&Local, message.get_unix_timestamp_millis());
-        let timestamp_string = timestamp.to_rfc3339_opts(SecondsFormat::Millis, true);
-        if html_formatting {
-            content.push_str(&format!("<pre>{}</pre>", timestamp_string));
-        } else {
-            content.push_str(&timestamp_string);
+    /// Sends each attachment after the message, as a photo if it is an image, otherwise as a document.
+    /// These never notify, since the message itself already has.
+    /// `files` is the contents of each attachment.
+    fn send_attachments(&self, message: &Message, files: &[Cow<[u8]>]) -> Result<(), Box<dyn Error>> {
+        for (attachment, data) in message.get_attachments().iter().zip(files) {
+            let (method, field) = if attachment.is_image() { ("sendPhoto", "photo") } else { ("sendDocument", "document") };
+            let url = format!("https://api.telegram.org/bot{}/{}", self.bot_token.expose(), method);
+            http_util::post_multipart_to(&url, &[
+                FormPart::text("chat_id", &self.chat_id),
+                FormPart::text("disable_notification", "true"),
+                FormPart::file(field, attachment.get_name(), attachment.get_mime_type(), data),
+            ])?;
         }
-
-        content.push('\n');
-        content.push_str(&format!("@ {}", message.get_author()));
-        let parse_mode = if html_formatting { Some(ParseMode::HTML) } else { None };
-        let notify = self.notify.iter()
-            .filter(|n| n.matches(&message))
-            .map(|n| n.get_notify())
-            .any(|b| *b);
-        TelegramMessage::new(self.chat_id.clone(), content, notify, parse_mode)
+        Ok(())
     }
 }
 
 impl MessageDestination for TelegramDestination {
     fn send(&self, message: &Message) -> Result<(), Box<dyn Error>> {
         // TODO: Add component and pretty up.
-        let message = self.to_tg_message(message);
+        self.send_fitted(message, false, None).map(|_| ())
+    }
 
-        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
+    fn send_silent(&self, message: &Message) -> Result<(), Box<dyn Error>> {
+        self.send_fitted(message, true, None).map(|_| ())
+    }
+
+    /// Replies to the original message about the alert if there is one, so that they are shown together.
+    fn send_alert(&self, message: &Message, silent: bool, original: Option<&str>) -> Result<Option<String>, Box<dyn Error>> {
+        let message_id = self.send_fitted(message, silent, original.and_then(|id| id.parse().ok()))?;
+        Ok(Some(message_id.to_string()))
+    }
+
+    /// If the message is split, only the first message can be edited.
+    fn send_editable(&self, message: &Message, silent: bool) -> Result<Option<String>, Box<dyn Error>> {
+        let message_id = self.send_fitted(message, silent, None)?;
+        Ok(Some(message_id.to_string()))
+    }
 
-        http_util::post_as_json_to(&url, &message)
+    /// Edits the text of the message using `editMessageText`, truncating it if it is too long. Attachments are not changed.
+    fn edit(&self, id: &str, message: &Message) -> Result<(), Box<dyn Error>> {
+        let message = overflow::truncate(message, |message| self.fits(message));
+        let tg_message = self.to_tg_message(&message, true);
+        let edit = EditMessageText {
+            chat_id: tg_message.chat_id,
+            message_id: id.parse().map_err(|_| format!("Invalid telegram message id '{}'", id))?,
+            text: tg_message.text,
+            parse_mode: tg_message.parse_mode,
+            reply_markup: tg_message.reply_markup,
+        };
+        let url = format!("https://api.telegram.org/bot{}/editMessageText", self.bot_token.expose());
+        http_util::post_as_json_to(&url, &edit)
+    }
+
+    /// Checks the bot token is valid using `getMe`
+    fn health_check(&self) -> Option<Result<(), Box<dyn Error>>> {
+        Some(http_util::get(&format!("https://api.telegram.org/bot{}/getMe", self.bot_token.expose())))
+    }
+
+    fn secrets(&self) -> Vec<&Secret> {
+        vec![&self.bot_token]
     }
 }
 
@@ -115,21 +307,20 @@ impl SerializableDestination for TelegramDestination {
     fn as_message_destination(&self) -> &dyn MessageDestination {
         self
     }
-}
 
-fn to_tg_format(formatted_string: &FormattedString) -> String {
-    let mut result = String::from(formatted_string.get_string());
-    for style in formatted_string.get_styles() {
-        result = apply_style(&result, style);
+    fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.bot_token.expose().trim().is_empty() {
+            problems.push("bot_token is empty".to_owned());
+        }
+        if self.chat_id.trim().is_empty() {
+            problems.push("chat_id is empty".to_owned());
+        }
+        problems.extend(self.notify.iter().filter_map(|n| n.validate().err()));
+        problems
     }
-    result
-}
 
-fn apply_style(s: &str, style: &Style) -> String {
-    match style {
-        Style::Bold => format!("*{}*", s),
-        Style::Italics => format!("_{}_", s),
-        Style::Monospace => format!("`{}`", s),
-        Style::Code { lang: _ } => format!("```{}```", s),
+    fn apply_time_defaults(&mut self, defaults: &TimeFormat) {
+        self.time_format.apply_defaults(defaults);
     }
-}
\ No newline at end of file
+}
@@ -1,9 +1,12 @@
+use std::collections::BTreeMap;
 use serde::{Serialize, Deserialize};
 use crate::message::component::Component;
+use crate::destination::schedule::Schedule;
 use crate::message::{Level, Message};
 
 /// A filter for a [`Message`]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct MessageCondition {
     component: Option<Component>,
@@ -11,6 +14,12 @@ pub struct MessageCondition {
     min_level: Level,
     #[serde(default = "Level::max")]
     max_level: Level,
+    /// Messages must have each of these [fields](Message::get_fields), with the same value.
+    /// ```toml
+    /// fields = { env = "prod" }
+    /// ```
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<String, String>,
 }
 
 impl MessageCondition {
@@ -24,6 +33,7 @@ impl MessageCondition {
             component,
             min_level,
             max_level,
+            fields: BTreeMap::new(),
         }
     }
 
@@ -118,12 +128,46 @@ impl MessageCondition {
         }
     }
 
+    /// Messages must have the field with this value
+    /// ```rust
+    /// use rnotifylib::destination::message_condition::MessageCondition;
+    /// use rnotifylib::message::builder::MessageBuilder;
+    ///
+    /// let condition = MessageCondition::of_field("env", "prod");
+    ///
+    /// let mut message_builder = MessageBuilder::new();
+    /// assert!(!condition.matches(&message_builder.build_clone()), "Should not match - no env field");
+    ///
+    /// message_builder.field("env", "staging");
+    /// assert!(!condition.matches(&message_builder.build_clone()), "Should not match - different env");
+    ///
+    /// message_builder.field("env", "prod");
+    /// assert!(condition.matches(&message_builder.build_clone()), "Should match - same env");
+    /// ```
+    pub fn of_field<K: ToString, V: ToString>(key: K, value: V) -> Self {
+        Self {
+            fields: BTreeMap::from([(key.to_string(), value.to_string())]),
+            ..Default::default()
+        }
+    }
+
+    /// Checks that this condition is able to match messages, returning a description of the problem if not.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_level > self.max_level {
+            return Err(format!("min_level {:?} is greater than max_level {:?}, so no messages will match", self.min_level, self.max_level));
+        }
+        Ok(())
+    }
+
     pub fn matches(&self, m: &Message) -> bool {
         if let Some(c) = &self.component {
             if m.get_component().is_none() || !m.get_component().as_ref().unwrap().is_child_of(c) {
                 return false;
             }
         }
+        if !self.fields.iter().all(|(key, value)| m.get_fields().get(key) == Some(value)) {
+            return false;
+        }
         &self.min_level <= m.get_level() && m.get_level() <= &self.max_level
     }
 }
@@ -135,6 +179,7 @@ impl Default for MessageCondition {
             component: None,
             min_level: Level::min(),
             max_level: Level::max(),
+            fields: BTreeMap::new(),
         }
     }
 }
@@ -143,18 +188,48 @@ impl Default for MessageCondition {
 ///
 /// Who this notifies is and the format of that is up to the specific implementation of
 /// the [`MessageDestination`](crate::destination::MessageDestination).
+///
+/// If a [Schedule] is given, then only messages sent within it will notify,
+/// e.g. to avoid mentioning people at night:
+/// ```toml
+/// [[destinations.notify]]
+/// min_level = "Error"
+/// notify = "<@309009265732091904>"
+/// schedule = { start = "08:00", end = "22:00" }
+/// ```
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MessageNotifyConditionConfigEntry<T> {
     #[serde(flatten)]
     message_condition: MessageCondition,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<Schedule>,
     notify: T,
 }
 
 impl<T> MessageNotifyConditionConfigEntry<T> {
+    pub fn new(message_condition: MessageCondition, schedule: Option<Schedule>, notify: T) -> Self {
+        Self {
+            message_condition,
+            schedule,
+            notify,
+        }
+    }
+
     pub fn matches(&self, m: &Message) -> bool {
+        if let Some(schedule) = &self.schedule {
+            if !schedule.contains(m.get_unix_timestamp_millis()) {
+                return false;
+            }
+        }
         self.message_condition.matches(m)
     }
 
+    pub fn validate(&self) -> Result<(), String> {
+        self.message_condition.validate()
+            .map_err(|e| format!("In notify: {}", e))
+    }
+
     pub fn get_notify(&self) -> &T {
         &self.notify
     }
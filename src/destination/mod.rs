@@ -1,15 +1,87 @@
 use std::fmt::Debug;
 use crate::message::Message;
+use crate::util::secret::Secret;
+use crate::util::time_format::TimeFormat;
 
 pub mod routed_destination;
 pub mod kinds;
 pub mod message_condition;
+pub mod schedule;
 
-pub trait MessageDestination: Debug {
+/// Destinations are `Send + Sync` so that a `ReloadingMessageRouter` can be shared between threads.
+pub trait MessageDestination: Debug + Send + Sync {
     fn send(&self, message: &Message) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Sends the message without alerting anyone, e.g. without sound or mentions.
+    /// Used for messages that arrive during [`QuietHours`](schedule::QuietHours).
+    ///
+    /// Destinations that have no concept of this simply [`send`](Self::send) the message.
+    fn send_silent(&self, message: &Message) -> Result<(), Box<dyn std::error::Error>> {
+        self.send(message)
+    }
+
+    /// Sends a message that is part of an [`Alert`](crate::message::alert::Alert), without alerting anyone if `silent`.
+    ///
+    /// `original` is the id this returned for the first message about the alert, if any, so that the destination
+    /// can group the messages about the alert together, e.g. by editing or replying to the first message.
+    /// Returns the id of the message that was sent, if the destination supports this.
+    ///
+    /// Destinations that have no concept of this simply send the message.
+    fn send_alert(&self, message: &Message, silent: bool, _original: Option<&str>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        if silent {
+            self.send_silent(message)?;
+        } else {
+            self.send(message)?;
+        }
+        Ok(None)
+    }
+
+    /// Sends a message that can be changed later with [`edit`](Self::edit), e.g. to show the progress of a job,
+    /// without alerting anyone if `silent`.
+    ///
+    /// Returns the id of the message that was sent, or `None` if the destination can't edit messages,
+    /// in which case each change is sent as a new message.
+    fn send_editable(&self, message: &Message, silent: bool) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        if silent {
+            self.send_silent(message)?;
+        } else {
+            self.send(message)?;
+        }
+        Ok(None)
+    }
+
+    /// Replaces the message with the given id, which was returned by [`send_editable`](Self::send_editable).
+    fn edit(&self, _id: &str, message: &Message) -> Result<(), Box<dyn std::error::Error>> {
+        self.send(message)
+    }
+
+    /// Checks whether the destination is able to receive messages, without sending
+    /// anything that a user would see, e.g. by checking that credentials are valid.
+    ///
+    /// Returns `None` if the destination has no way to do this.
+    fn health_check(&self) -> Option<Result<(), Box<dyn std::error::Error>>> {
+        None
+    }
+
+    /// The secrets in the destination's config, such as tokens and passwords, which are removed from
+    /// its errors before they are reported, see [`redact_error`](crate::util::secret::redact_error).
+    fn secrets(&self) -> Vec<&Secret> {
+        vec![]
+    }
 }
 
 #[typetag::serde(tag = "type")]
 pub trait SerializableDestination: MessageDestination {
     fn as_message_destination(&self) -> &dyn MessageDestination;
-}
\ No newline at end of file
+
+    /// Checks the configuration of this destination for problems that are not caught
+    /// when deserializing it, such as an invalid url.
+    /// Returns a description of each problem found.
+    fn validate(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Fills in the time zone and timestamp format given at the top of the config file,
+    /// for any that this destination doesn't set itself.
+    fn apply_time_defaults(&mut self, _defaults: &TimeFormat) {}
+}
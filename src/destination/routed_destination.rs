@@ -4,9 +4,10 @@ use serde::{Deserialize, Serialize};
 use crate::destination::MessageDestination;
 use crate::message::Message;
 use crate::message_router::RoutingInfo;
+use crate::util::secret;
 
 /// A [Message] that also contains [RoutingInfo].
-pub trait RoutedDestination {
+pub trait RoutedDestination: Send + Sync {
     /// The id provides an identifier
     /// for error reporting.
     fn get_id(&self) -> &str;
@@ -19,7 +20,32 @@ pub trait RoutedDestination {
     fn get_routing_info(&self) -> &RoutingInfo;
 
     fn send(&self, message: &Message) -> Result<(), Box<dyn Error>> {
-        self.get_destination().send(message)
+        self.get_destination().send(message).map_err(|err| self.redact(err))
+    }
+
+    fn send_silent(&self, message: &Message) -> Result<(), Box<dyn Error>> {
+        self.get_destination().send_silent(message).map_err(|err| self.redact(err))
+    }
+
+    fn send_alert(&self, message: &Message, silent: bool, original: Option<&str>) -> Result<Option<String>, Box<dyn Error>> {
+        self.get_destination().send_alert(message, silent, original).map_err(|err| self.redact(err))
+    }
+
+    fn send_editable(&self, message: &Message, silent: bool) -> Result<Option<String>, Box<dyn Error>> {
+        self.get_destination().send_editable(message, silent).map_err(|err| self.redact(err))
+    }
+
+    fn edit(&self, id: &str, message: &Message) -> Result<(), Box<dyn Error>> {
+        self.get_destination().edit(id, message).map_err(|err| self.redact(err))
+    }
+
+    fn health_check(&self) -> Option<Result<(), Box<dyn Error>>> {
+        self.get_destination().health_check().map(|result| result.map_err(|err| self.redact(err)))
+    }
+
+    /// Removes the destination's [secrets](MessageDestination::secrets) from an error it returned.
+    fn redact(&self, err: Box<dyn Error>) -> Box<dyn Error> {
+        secret::redact_error(err, &self.get_destination().secrets())
     }
 
     fn is_root(&self) -> bool {
@@ -27,7 +53,7 @@ pub trait RoutedDestination {
     }
 
     fn get_routing_type(&self) -> &MessageRoutingBehaviour {
-        &self.get_routing_info().get_routing_behaviour()
+        self.get_routing_info().get_routing_behaviour()
     }
 
     fn should_receive(&self, m: &Message) -> bool {
@@ -36,7 +62,8 @@ pub trait RoutedDestination {
 }
 
 /// Handles whether messages are routed here / if they will be routed to other destinations.
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum MessageRoutingBehaviour {
     /// [`SelfError`] messages in addition to all messages will be sent here.
     ///
@@ -51,6 +78,7 @@ pub enum MessageRoutingBehaviour {
     /// Useful if you want to route "unsorted" messages. A "lazy" destination - checks everything else first.
     Drain,
     /// The default option - Messages will be sent here under normal circumstances.
+    #[default]
     Additive
 }
 
@@ -73,12 +101,6 @@ impl MessageRoutingBehaviour {
     }
 }
 
-impl Default for MessageRoutingBehaviour {
-    fn default() -> Self {
-        MessageRoutingBehaviour::Additive
-    }
-}
-
 // Implementations //
 
 #[derive(Debug)]
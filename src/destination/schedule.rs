@@ -0,0 +1,244 @@
+use std::fmt::{Display, Formatter};
+use chrono::{Datelike, NaiveTime, Weekday};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::destination::message_condition::MessageCondition;
+use crate::message::Message;
+use crate::util::timezone::Timezone;
+
+/// A recurring window of time on certain days of the week.
+///
+/// If `end` is before `start`, then the window continues past midnight into the next day,
+/// e.g. `start = "22:00"` and `end = "08:00"`. In this case, the window belongs to the day that
+/// it starts on.
+///
+/// ```toml
+/// days = ["Sat", "Sun"] # Optional, defaults to every day
+/// start = "22:00"
+/// end = "08:00"
+/// timezone = "Europe/London" # Optional, defaults to the local time zone
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Schedule {
+    #[serde(default = "all_days")]
+    days: Vec<Weekday>,
+    start: TimeOfDay,
+    end: TimeOfDay,
+    #[serde(default)]
+    timezone: Timezone,
+}
+
+fn all_days() -> Vec<Weekday> {
+    vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun]
+}
+
+impl Schedule {
+    /// Creates a schedule that is active every day between start and end.
+    pub fn daily(start: NaiveTime, end: NaiveTime, timezone: Timezone) -> Self {
+        Self::new(all_days(), start, end, timezone)
+    }
+
+    pub fn new(days: Vec<Weekday>, start: NaiveTime, end: NaiveTime, timezone: Timezone) -> Self {
+        Self {
+            days,
+            start: TimeOfDay(start),
+            end: TimeOfDay(end),
+            timezone,
+        }
+    }
+
+    /// Whether the given unix timestamp falls within this schedule.
+    /// ```rust
+    /// use chrono::NaiveTime;
+    /// use rnotifylib::destination::schedule::Schedule;
+    /// use rnotifylib::util::timezone::Timezone;
+    ///
+    /// let night = Schedule::daily(NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+    ///                             NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+    ///                             Timezone::Utc);
+    ///
+    /// // 2022-07-01T23:30:00Z
+    /// assert!(night.contains(1656718200000));
+    /// // 2022-07-01T12:00:00Z
+    /// assert!(!night.contains(1656676800000));
+    /// ```
+    pub fn contains(&self, unix_timestamp_millis: i64) -> bool {
+        let local = self.timezone.naive_local(unix_timestamp_millis);
+        let time = local.time();
+        let day = local.weekday();
+
+        if self.start.0 <= self.end.0 {
+            return self.days.contains(&day) && self.start.0 <= time && time < self.end.0;
+        }
+        // Wraps around midnight.
+        if time >= self.start.0 {
+            return self.days.contains(&day);
+        }
+        time < self.end.0 && self.days.contains(&day.pred())
+    }
+}
+
+/// A time of day, serialized in the form `HH:MM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOfDay(NaiveTime);
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.format("%H:%M"))
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for TimeOfDay {
+    fn schema_name() -> String {
+        "TimeOfDay".to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = gen.subschema_for::<String>().into_object();
+        schema.metadata().description = Some("A time of day, in the form HH:MM".to_owned());
+        schema.string().pattern = Some("^[0-9]{2}:[0-9]{2}$".to_owned());
+        schema.into()
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let s = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&s, "%H:%M")
+            .map(TimeOfDay)
+            .map_err(|e| serde::de::Error::custom(format!("Invalid time '{}', expected HH:MM: {}", s, e)))
+    }
+}
+
+/// A period of time in which a destination should not disturb anyone.
+///
+/// Only messages matching any of the `affects` conditions are affected (or all messages if none given),
+/// and what happens to them is determined by the [`QuietHoursAction`].
+///
+/// For example, to only send non-error messages to a destination between 08:00 and 22:00:
+/// ```toml
+/// [[destinations.quiet_hours]]
+/// start = "22:00"
+/// end = "08:00"
+/// action = "Drop"
+/// [[destinations.quiet_hours.affects]]
+/// max_level = "Warn"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct QuietHours {
+    #[serde(flatten)]
+    schedule: Schedule,
+    #[serde(default)]
+    action: QuietHoursAction,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    affects: Vec<MessageCondition>,
+}
+
+impl QuietHours {
+    pub fn new(schedule: Schedule, action: QuietHoursAction, affects: Vec<MessageCondition>) -> Self {
+        Self {
+            schedule,
+            action,
+            affects,
+        }
+    }
+
+    pub fn get_schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    pub fn get_action(&self) -> &QuietHoursAction {
+        &self.action
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        self.affects.iter()
+            .filter_map(|condition| condition.validate().err())
+            .map(|e| format!("In quiet_hours affects: {}", e))
+            .collect()
+    }
+
+    /// Whether these quiet hours apply to the given message at the given time.
+    pub fn applies_to(&self, message: &Message, unix_timestamp_millis: i64) -> bool {
+        if !self.affects.is_empty() && !self.affects.iter().any(|c| c.matches(message)) {
+            return false;
+        }
+        self.schedule.contains(unix_timestamp_millis)
+    }
+}
+
+/// What to do with a message that arrives during [`QuietHours`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum QuietHoursAction {
+    /// The message is not sent to the destination.
+    #[default]
+    Drop,
+    /// The message is sent without notifying anyone,
+    /// see [`MessageDestination::send_silent`](crate::destination::MessageDestination::send_silent)
+    Silent,
+    /// The message is held back and sent once the quiet hours are over.
+    /// See [`MessageRouter::send_deferred`](crate::message_router::MessageRouter::send_deferred)
+    Defer,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2022-07-01 was a Friday.
+    const FRI_1200: i64 = 1656676800000;
+    const FRI_2330: i64 = 1656718200000;
+    const SAT_0130: i64 = 1656725400000;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_same_day() {
+        let work_hours = Schedule::new(vec![Weekday::Fri], time(9, 0), time(17, 30), Timezone::Utc);
+        assert!(work_hours.contains(FRI_1200));
+        assert!(!work_hours.contains(FRI_2330));
+        assert!(!work_hours.contains(SAT_0130));
+    }
+
+    #[test]
+    fn test_wrap_midnight() {
+        let friday_night = Schedule::new(vec![Weekday::Fri], time(22, 0), time(8, 0), Timezone::Utc);
+        assert!(!friday_night.contains(FRI_1200));
+        assert!(friday_night.contains(FRI_2330));
+        assert!(friday_night.contains(SAT_0130), "Saturday morning is part of Friday night");
+
+        let saturday_night = Schedule::new(vec![Weekday::Sat], time(22, 0), time(8, 0), Timezone::Utc);
+        assert!(!saturday_night.contains(SAT_0130), "Saturday morning is not part of Saturday night");
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let quiet_hours: QuietHours = toml::from_str(r#"
+            days = ["Mon", "Tuesday"]
+            start = "22:00"
+            end = "08:00"
+            timezone = "Europe/London"
+            action = "Silent"
+            [[affects]]
+            max_level = "Warn"
+        "#).expect("Should deserialize");
+
+        let expected = QuietHours::new(
+            Schedule::new(vec![Weekday::Mon, Weekday::Tue], time(22, 0), time(8, 0), Timezone::Named(chrono_tz::Europe::London)),
+            QuietHoursAction::Silent,
+            vec![MessageCondition::of_max(crate::message::Level::Warn)],
+        );
+        assert_eq!(quiet_hours, expected);
+    }
+}
@@ -39,5 +39,9 @@ pub mod message;
 pub mod config;
 pub mod destination;
 pub mod message_router;
+pub mod progress;
+#[cfg(feature = "reload")]
+#[cfg_attr(docsrs, doc(cfg(feature = "reload")))]
+pub mod reloading_router;
 pub mod send_error;
 pub mod util;
\ No newline at end of file
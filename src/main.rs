@@ -1,28 +1,98 @@
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
 use rnotifylib::{config, message};
+use rnotifylib::config::validation::Severity;
 use rnotifylib::message::{Level, Message, MessageDetail};
+use rnotifylib::message::action::Action;
+use rnotifylib::message::alert::{Alert, AlertState};
+use rnotifylib::message::attachment::Attachment;
 use rnotifylib::message::author::Author;
-use rnotifylib::message_router::MessageRouter;
+use rnotifylib::message_router::{MessageRouter, OpenAlert};
+use rnotifylib::progress::ProgressMessage;
+use rnotifylib::send_error::SendErrors;
+
+use crate::cli::state::{ALERTS_FILE_NAME, PROGRESS_FILE_NAME, State};
+
+mod cli;
 
 fn main() {
-    // TODO: Allow configuration of timezone.
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
         .expect("Current time is before the unix epoch!")
         .as_millis();
     let cli: Cli = Cli::parse();
 
-    let config = {
-        let file = config::fetch_config_file(cli.verbose, &cli.config_file)
-            .expect("Failed to fetch config file");
-        config::read_config_file(file)
+    // The schema doesn't depend on the config, so print it even if the config is broken.
+    if let Some(Command::Config { command: ConfigCommand::Schema }) = cli.command {
+        std::process::exit(cli::config::schema());
+    }
+
+    let mut config = match config::fetch_config_paths(cli.verbose, &cli.config_file)
+        .and_then(|paths| config::load_config_files(&paths)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
     };
 
+    if let Some(profile) = &cli.profile {
+        if let Err(err) = config.apply_profile(profile) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(command) = cli.command {
+        let exit_code = match command {
+            Command::Test { id, send } => cli::test::test_destinations(config, id, send),
+            Command::Flush => cli::state::flush(config, cli.verbose),
+            Command::Config { command: ConfigCommand::Check } => cli::config::check(&config),
+            Command::Config { command: ConfigCommand::List } => cli::config::list(&config),
+            Command::Config { command: ConfigCommand::Schema } => unreachable!("Schema is handled before loading the config"),
+            Command::Config { command: ConfigCommand::Migrate } => {
+                with_config_path(&cli.config_file, cli::config::migrate)
+            }
+            Command::Config { command: ConfigCommand::Add { kind } } => {
+                with_config_path(&cli.config_file, |path| cli::config::add(&config, path, kind))
+            }
+            Command::Config { command: ConfigCommand::Remove { id } } => {
+                with_config_path(&cli.config_file, |path| cli::config::remove(path, &id))
+            }
+        };
+        std::process::exit(exit_code);
+    }
+
+    if !cli.verbose {
+        let mut legacy_paths: Vec<_> = config.get_migrations().iter().map(|(path, _)| path).collect();
+        legacy_paths.dedup();
+        for path in legacy_paths {
+            match path {
+                Some(path) => eprintln!("Warning: The config file {} uses the old (rnotify 0.1) format, run `rnotify config migrate --config-file {}` to update it (use --verbose for details)",
+                                        path.display(), path.display()),
+                None => eprintln!("Warning: The config file uses the old (rnotify 0.1) format, run `rnotify config migrate` to update it (use --verbose for details)"),
+            }
+        }
+    }
+    for problem in config.validate() {
+        if cli.verbose || problem.get_severity() == Severity::Error {
+            eprintln!("{}", problem);
+        }
+    }
+
+    let attachments = cli.attachments.iter()
+        .map(|path| Attachment::from_path(path))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid --attach: {}", err);
+            std::process::exit(1);
+        });
+
     let message_detail = {
-        if cli.message.is_some() {
-            cli.message.unwrap()
+        if let Some(message) = cli.message {
+            message
         } else {
             if cli.verbose {
                 println!("Reading stdin.");
@@ -33,11 +103,17 @@ fn main() {
         }
     };
 
-
-    let message_detail = if cli.formatted {
-        MessageDetail::Formatted(message::formatted_detail::parse_raw_to_formatted(&message_detail))
+    let message_detail = if cli.ansi == AnsiMode::Convert && !cli.markdown && !cli.formatted {
+        MessageDetail::Formatted(message::ansi::parse_ansi(&message_detail))
     } else {
-        MessageDetail::Raw(message_detail)
+        let message_detail = message::ansi::strip_ansi(&message_detail);
+        if cli.markdown {
+            MessageDetail::Formatted(message::markdown::parse_markdown(&message_detail))
+        } else if cli.formatted {
+            MessageDetail::Formatted(message::formatted_detail::parse_raw_to_formatted(&message_detail))
+        } else {
+            MessageDetail::Raw(message_detail)
+        }
     };
 
     let author = Author::parse(cli.author.unwrap_or("".to_owned()));
@@ -50,21 +126,49 @@ fn main() {
         cli.component.as_deref().map(|s| s.into()),
         author,
         timestamp as i64,
-    );
+    ).with_fields(cli.fields.into_iter().collect())
+        .with_attachments(attachments)
+        .with_actions(cli.actions);
+    let message = match cli.alert_key {
+        Some(key) => message.with_alert(Alert::new(key, cli.alert_state)),
+        None => message,
+    };
 
     if cli.verbose {
         println!("Message: {:?}", message);
     }
 
+    let targets = match config.resolve_targets(&cli.to) {
+        Ok(targets) => targets,
+        Err(err) => {
+            eprintln!("Invalid --to: {}", err);
+            std::process::exit(1);
+        }
+    };
+
     let router = MessageRouter::from_config(config);
 
-    let result = router.route(&message);
+    let state = State::lock();
+    router.add_open_alerts(state.read::<OpenAlert>(ALERTS_FILE_NAME, "open alerts"));
+    cli::state::send_deferred(&state, &router, timestamp as i64, cli.verbose);
+
+    let result = if let Some(key) = cli.progress {
+        update_progress(&state, &router, &message, key, cli.progress_done)
+    } else if targets.is_empty() {
+        router.route(&message)
+    } else {
+        router.route_to(&message, &targets)
+    };
 
-    match result {
-        Ok(send_count) => {
-            println!("Message sent to {} destination{}", send_count, if send_count > 1 { "s" } else { "" } )
+    cli::state::save_deferred(&state, &router, cli.verbose);
+    if message.get_alert().is_some() {
+        if let Err(err) = state.write(ALERTS_FILE_NAME, &router.take_open_alerts()) {
+            eprintln!("Failed to save open alerts: {}", err);
         }
-        Err(_) => {}
+    }
+
+    if let Ok(send_count) = result {
+        println!("Message sent to {} destination{}", send_count, if send_count > 1 { "s" } else { "" } )
     }
     if result.is_ok() {
         return;
@@ -74,12 +178,82 @@ fn main() {
     eprintln!("{}", err);
 }
 
+/// A progress message that can be updated by later runs, using `--progress <KEY>`
+#[derive(Serialize, Deserialize)]
+struct StoredProgress {
+    key: String,
+    #[serde(flatten)]
+    progress: ProgressMessage,
+}
+
+/// Sends the message if there is no progress message with the key, otherwise updates the progress message.
+/// If `done`, the progress message is forgotten afterwards, so it can't be updated again.
+fn update_progress<'a>(state: &State, router: &MessageRouter, message: &'a Message, key: String, done: bool) -> Result<usize, SendErrors<'a>> {
+    let mut stored: Vec<StoredProgress> = state.read(PROGRESS_FILE_NAME, "progress messages");
+    let progress = stored.iter()
+        .position(|stored| stored.key == key)
+        .map(|i| stored.remove(i).progress)
+        .unwrap_or_default();
+
+    let mut handle = router.resume_progress(progress);
+    let result = handle.update(message);
+    let progress = handle.into_progress();
+    if !done && progress.is_sent() {
+        stored.push(StoredProgress { key, progress });
+    }
+    if let Err(err) = state.write(PROGRESS_FILE_NAME, &stored) {
+        eprintln!("Failed to save progress messages: {}", err);
+    }
+    result
+}
+
+/// Runs the given function with the path of the config file that should be edited,
+/// returning its exit code.
+fn with_config_path<F: FnOnce(&Path) -> i32>(config_file: &Option<PathBuf>, f: F) -> i32 {
+    match config::fetch_config_path(false, config_file) {
+        Ok(path) => f(&path),
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+fn parse_field(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() && !value.trim().is_empty() => Ok((key.trim().to_owned(), value.to_owned())),
+        Some((key, _)) if !key.trim().is_empty() => Err(format!("Field '{}' has an empty value", key.trim())),
+        _ => Err(format!("Expected KEY=VALUE, got '{}'", s)),
+    }
+}
+
+fn parse_action(s: &str) -> Result<Action, String> {
+    match s.split_once('=') {
+        Some((label, url)) if !label.trim().is_empty() && url.contains("://") => Ok(Action::new(label.trim(), url.trim())),
+        _ => Err(format!("Expected LABEL=URL, got '{}'", s)),
+    }
+}
+
+/// How escape sequences in the message are handled, see [`message::ansi`]
+#[derive(Clone, Copy, PartialEq, clap::ArgEnum)]
+enum AnsiMode {
+    Strip,
+    Convert,
+}
+
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
-    #[clap(long, value_parser)]
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Only use this config file, instead of merging the system, user and $RNOTIFY_CONFIG config files
+    #[clap(long, value_parser, global = true)]
     config_file: Option<PathBuf>,
-    #[clap(short, long)]
+    /// Use the config profile with this name
+    #[clap(long, env = "RNOTIFY_PROFILE", global = true)]
+    profile: Option<String>,
+    #[clap(short, long, global = true)]
     verbose: bool,
     #[clap(long)]
     dry_run: bool,
@@ -98,4 +272,88 @@ pub struct Cli {
 
     #[clap(short, long)]
     formatted: bool,
+    /// Parse the message as markdown: headings, **bold**, _italics_, `code` and code blocks
+    #[clap(long, conflicts_with = "formatted")]
+    markdown: bool,
+    /// What to do with terminal colours and other escape sequences in the message, e.g. from a script's output.
+    /// `convert` turns bold, italics and colours into formatting, unless --formatted or --markdown is used.
+    #[clap(long, value_enum, default_value_t = AnsiMode::Strip)]
+    ansi: AnsiMode,
+
+    /// Add a key / value field to the message, e.g. --field env=prod
+    #[clap(long = "field", value_name = "KEY=VALUE", value_parser = parse_field)]
+    fields: Vec<(String, String)>,
+
+    /// Attach a file to the message. Destinations that can't send files list its name and size instead.
+    #[clap(long = "attach", value_name = "FILE")]
+    attachments: Vec<PathBuf>,
+
+    /// Add a link to the message, e.g. to a dashboard or runbook. Shown as a button where possible.
+    #[clap(long = "action", value_name = "LABEL=URL", value_parser = parse_action)]
+    actions: Vec<Action>,
+
+    /// Marks the message as being about an ongoing problem, e.g. disk_full/server1. Messages resolving it
+    /// say how long it was firing for, and are grouped with the first message where possible.
+    #[clap(long, value_name = "KEY")]
+    alert_key: Option<String>,
+    #[clap(long, value_enum, default_value_t = AlertState::Firing, requires = "alert-key")]
+    alert_state: AlertState,
+
+    /// Send the message the first time this key is used, and edit it after that where possible,
+    /// e.g. to show the progress of a backup.
+    #[clap(long, value_name = "KEY", conflicts_with = "to")]
+    progress: Option<String>,
+    /// Forget the --progress message after this update, so the key can be used for a new message.
+    #[clap(long, requires = "progress")]
+    progress_done: bool,
+
+    /// Only send to the given destination ids / groups, instead of routing normally.
+    #[clap(long, value_name = "ID|GROUP")]
+    to: Vec<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that each destination is working, exiting with an error if any are not.
+    ///
+    /// Destinations that support it are checked without sending a message,
+    /// otherwise a test message is sent.
+    Test {
+        /// Only test the destination with this id
+        id: Option<String>,
+        /// Always send a test message, even if the destination can be checked without one
+        #[clap(long)]
+        send: bool,
+    },
+    /// Send any messages deferred during quiet hours whose quiet hours have ended, without sending a new message.
+    ///
+    /// Deferred messages are otherwise only sent the next time rnotify is run,
+    /// so this can be run regularly, e.g. from a cron job or systemd timer.
+    Flush,
+    /// Manage the config file
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Check the config file for problems, exiting with an error if there are any
+    Check,
+    /// List the configured destinations
+    List,
+    /// Print the JSON Schema of the config file, for editor autocompletion
+    Schema,
+    /// Update the user's config file (or --config-file) from the rnotify 0.1 format
+    Migrate,
+    /// Add a new destination to the user's config file (or --config-file), prompting for its fields
+    Add {
+        #[clap(value_enum)]
+        kind: cli::config::DestinationKind,
+    },
+    /// Remove the destination with the given id from the user's config file (or --config-file)
+    Remove {
+        id: String,
+    },
 }
@@ -0,0 +1,37 @@
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// A link shown with a [`Message`](crate::message::Message), e.g. to a dashboard or runbook.
+///
+/// Destinations that support it show actions as buttons, others list them as links.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    label: String,
+    url: String,
+}
+
+impl Action {
+    pub fn new<L: ToString, U: ToString>(label: L, url: U) -> Self {
+        Self {
+            label: label.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    /// The text of the button or link, e.g. `Dashboard`
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+}
+
+/// The label followed by the url, e.g. `Dashboard: https://grafana.example.com/d/abc`,
+/// for destinations that can't show links.
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.label, self.url)
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use crate::util::time_format;
+
+/// Marks a [`Message`](crate::message::Message) as being about an ongoing problem, identified by its key,
+/// e.g. a monitoring script that reports `disk_full/server1` each time it runs until the disk has space again.
+///
+/// The [`MessageRouter`](crate::message_router::MessageRouter) keeps track of which alerts are firing,
+/// so that the message resolving an alert can say how long it was firing for, and destinations that support it
+/// can group the messages about an alert together, e.g. by replying to the first message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    key: String,
+    state: AlertState,
+    /// When the alert started firing, filled in by the router when it resolves an alert it knows about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fired_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "binary", derive(clap::ArgEnum))]
+pub enum AlertState {
+    /// The problem is happening.
+    Firing,
+    /// The problem has gone away.
+    Resolved,
+}
+
+impl Alert {
+    pub fn new<S: ToString>(key: S, state: AlertState) -> Self {
+        Self {
+            key: key.to_string(),
+            state,
+            fired_at: None,
+        }
+    }
+
+    pub fn firing<S: ToString>(key: S) -> Self {
+        Self::new(key, AlertState::Firing)
+    }
+
+    pub fn resolved<S: ToString>(key: S) -> Self {
+        Self::new(key, AlertState::Resolved)
+    }
+
+    pub(crate) fn with_fired_at(mut self, unix_timestamp_millis: i64) -> Self {
+        self.fired_at = Some(unix_timestamp_millis);
+        self
+    }
+
+    pub fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn get_state(&self) -> AlertState {
+        self.state
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        self.state == AlertState::Resolved
+    }
+
+    /// The unix timestamp in milliseconds of when the alert started firing, if known.
+    pub fn get_fired_at(&self) -> Option<i64> {
+        self.fired_at
+    }
+
+    /// Describes the state of the alert, for a message sent at the given time,
+    /// e.g. `Firing` or `Resolved after 42 minutes`.
+    pub fn describe(&self, unix_timestamp_millis: i64) -> String {
+        match (self.state, self.fired_at) {
+            (AlertState::Firing, _) => "Firing".to_owned(),
+            (AlertState::Resolved, Some(fired_at)) => format!("Resolved after {}", time_format::duration(unix_timestamp_millis - fired_at)),
+            (AlertState::Resolved, None) => "Resolved".to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let fired_at = 1656676800000;
+        assert_eq!(Alert::firing("disk_full").describe(fired_at), "Firing");
+        assert_eq!(Alert::resolved("disk_full").describe(fired_at), "Resolved");
+        let resolved = Alert::resolved("disk_full").with_fired_at(fired_at);
+        assert_eq!(resolved.describe(fired_at + 42 * 60 * 1000), "Resolved after 42 minutes");
+    }
+}
@@ -0,0 +1,224 @@
+//! Converts terminal output containing ANSI escape sequences, e.g. coloured output from a script,
+//! into a [`FormattedMessageDetail`], or strips the escape sequences.
+//!
+//! [SGR](https://en.wikipedia.org/wiki/ANSI_escape_code#SGR) sequences are converted:
+//! - Bold (`1`), as [`Bold`](Style::Bold)
+//! - Italics (`3`), as [`Italics`](Style::Italics)
+//! - Foreground colours (`30`-`37`, `90`-`97`, `38;5;n` and `38;2;r;g;b`), as [`Color`](Style::Color)
+//!
+//! Other SGR attributes, such as background colours, and any other escape sequences, such as cursor movement, are removed.
+use crate::message::formatted_detail::{FormattedMessageComponent, FormattedMessageDetail, FormattedString, Style};
+
+const ESC: char = '\u{1b}';
+const BEL: char = '\u{07}';
+
+/// The xterm colours for `30`-`37` and `90`-`97`, and the first 16 colours of `38;5;n`.
+const COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+/// Parses the text, converting bold, italics and colours into styles.
+/// The text with the escape sequences removed is kept as the raw text.
+pub fn parse_ansi(s: &str) -> FormattedMessageDetail {
+    let mut parts: Vec<FormattedString> = vec![];
+    let mut state = SgrState::default();
+    for token in tokenize(s) {
+        match token {
+            Token::Text(text) => {
+                let styles = state.styles();
+                match parts.last_mut() {
+                    Some(last) if last.get_styles() == &styles => *last = FormattedString::new(format!("{}{}", last.get_string(), text), styles),
+                    _ => parts.push(FormattedString::new(text, styles)),
+                }
+            }
+            Token::Sgr(params) => state.apply(&params),
+            Token::Other => {}
+        }
+    }
+    let components = if parts.is_empty() { vec![] } else { vec![FormattedMessageComponent::Text(parts)] };
+    FormattedMessageDetail::new(strip_ansi(s), components)
+}
+
+/// Removes all escape sequences from the text.
+pub fn strip_ansi(s: &str) -> String {
+    tokenize(s).into_iter()
+        .filter_map(|token| match token {
+            Token::Text(text) => Some(text),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Text(&'a str),
+    /// The parameters of a `ESC [ ... m` sequence.
+    Sgr(Vec<u32>),
+    /// Any other escape sequence.
+    Other,
+}
+
+fn tokenize(s: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut rest = s;
+    while let Some(start) = rest.find(ESC) {
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        let (token, len) = escape_sequence(&rest[start + ESC.len_utf8()..]);
+        tokens.push(token);
+        rest = &rest[start + ESC.len_utf8() + len..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+/// Parses the escape sequence after the `ESC`, returning it and its length.
+fn escape_sequence(s: &str) -> (Token<'_>, usize) {
+    let mut chars = s.char_indices();
+    match chars.next() {
+        // CSI: ESC [ <parameter bytes> <intermediate bytes> <final byte>
+        Some((_, '[')) => {
+            for (i, c) in chars {
+                if ('@'..='~').contains(&c) {
+                    let params = &s[1..i];
+                    let token = if c == 'm' { parse_sgr(params).map_or(Token::Other, Token::Sgr) } else { Token::Other };
+                    return (token, i + 1);
+                }
+            }
+            (Token::Other, s.len())
+        }
+        // OSC: ESC ] ... terminated by BEL or ESC \
+        Some((_, ']')) => {
+            let end = s.find(BEL).map(|i| i + 1)
+                .or_else(|| s.find("\u{1b}\\").map(|i| i + 2))
+                .unwrap_or(s.len());
+            (Token::Other, end)
+        }
+        // Two character sequences, e.g. ESC ( B
+        Some((_, c)) if ('\u{20}'..='\u{2f}').contains(&c) => (Token::Other, c.len_utf8() + chars.next().map_or(0, |(_, c)| c.len_utf8())),
+        Some((_, c)) => (Token::Other, c.len_utf8()),
+        None => (Token::Other, 0),
+    }
+}
+
+/// Parses the parameters, e.g. `1;31`, returning `None` if they aren't SGR parameters.
+/// An empty parameter is `0`.
+fn parse_sgr(params: &str) -> Option<Vec<u32>> {
+    params.split(';')
+        .map(|param| if param.is_empty() { Some(0) } else { param.parse().ok() })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct SgrState {
+    bold: bool,
+    italics: bool,
+    color: Option<String>,
+}
+
+impl SgrState {
+    fn apply(&mut self, params: &[u32]) {
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                3 => self.italics = true,
+                22 => self.bold = false,
+                23 => self.italics = false,
+                30..=37 => self.color = Some(COLORS[param as usize - 30].to_owned()),
+                90..=97 => self.color = Some(COLORS[param as usize - 90 + 8].to_owned()),
+                39 => self.color = None,
+                38 => self.color = extended_color(&mut params),
+                // Background colours aren't converted, but the colour must be skipped.
+                48 => {
+                    extended_color(&mut params);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn styles(&self) -> Vec<Style> {
+        let mut styles = vec![];
+        if self.bold {
+            styles.push(Style::Bold);
+        }
+        if self.italics {
+            styles.push(Style::Italics);
+        }
+        if let Some(color) = &self.color {
+            styles.push(Style::Color { color: color.clone() });
+        }
+        styles
+    }
+}
+
+/// Parses the colour after a `38` or `48`, either `5;n` or `2;r;g;b`.
+fn extended_color(params: &mut impl Iterator<Item=u32>) -> Option<String> {
+    match params.next()? {
+        5 => {
+            let n = params.next()?;
+            Some(match n {
+                0..=15 => COLORS[n as usize].to_owned(),
+                16..=231 => {
+                    let level = |i: u32| if i == 0 { 0 } else { 55 + i * 40 };
+                    let n = n - 16;
+                    format!("#{:02x}{:02x}{:02x}", level(n / 36), level(n / 6 % 6), level(n % 6))
+                }
+                232..=255 => {
+                    let gray = 8 + (n - 232) * 10;
+                    format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+                }
+                _ => return None,
+            })
+        }
+        2 => {
+            let (r, g, b) = (params.next()?, params.next()?, params.next()?);
+            Some(format!("#{:02x}{:02x}{:02x}", r.min(255), g.min(255), b.min(255)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(strip_ansi("\u{1b}[1;31mFAILED\u{1b}[0m backup"), "FAILED backup");
+        assert_eq!(strip_ansi("50%\u{1b}[2K\u{1b}[1G100%"), "50%100%");
+        assert_eq!(strip_ansi("\u{1b}]8;;https://example.com\u{1b}\\link\u{1b}]8;;\u{1b}\\"), "link");
+        assert_eq!(strip_ansi("\u{1b}(Bplain\u{1b}"), "plain");
+    }
+
+    #[test]
+    fn test_parse() {
+        let detail = parse_ansi("\u{1b}[1mBackup \u{1b}[31mfailed\u{1b}[0m: \u{1b}[38;5;196mdisk\u{1b}[39m \u{1b}[38;2;0;128;255mfull\u{1b}[m\n");
+        assert_eq!(detail.raw(), "Backup failed: disk full\n");
+        let color = |color: &str| Style::Color { color: color.to_owned() };
+        assert_eq!(detail.components(), &vec![FormattedMessageComponent::Text(vec![
+            FormattedString::styled("Backup ", Style::Bold),
+            FormattedString::new("failed", vec![Style::Bold, color("#cd0000")]),
+            FormattedString::plain(": "),
+            FormattedString::styled("disk", color("#ff0000")),
+            FormattedString::plain(" "),
+            FormattedString::styled("full", color("#0080ff")),
+            FormattedString::plain("\n"),
+        ])]);
+    }
+
+    #[test]
+    fn test_background_ignored() {
+        let detail = parse_ansi("\u{1b}[48;5;1;3mwarning\u{1b}[23m ok");
+        assert_eq!(detail.components(), &vec![FormattedMessageComponent::Text(vec![
+            FormattedString::styled("warning", Style::Italics),
+            FormattedString::plain(" ok"),
+        ])]);
+    }
+}
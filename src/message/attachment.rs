@@ -0,0 +1,179 @@
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+/// A file sent along with a [`Message`](crate::message::Message), e.g. the full log of a failed job,
+/// or a graph.
+///
+/// Destinations that cannot send files instead list the names and sizes of the attachments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    name: String,
+    mime_type: String,
+    data: AttachmentData,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AttachmentData {
+    /// The file is read when the message is sent.
+    Path(PathBuf),
+    /// Stored as base64 when serialized, e.g. in deferred messages.
+    Bytes(#[serde(with = "base64_bytes")] Vec<u8>),
+}
+
+impl Attachment {
+    pub fn new(name: String, mime_type: String, data: AttachmentData) -> Self {
+        Self {
+            name,
+            mime_type,
+            data,
+        }
+    }
+
+    /// Attaches the file at the given path, guessing its MIME type from its extension.
+    ///
+    /// Returns an error if the file doesn't exist. It isn't read until the message is sent.
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        if !path.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a file", path.display())));
+        }
+        let name = path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".to_owned());
+        Ok(Self::new(name, guess_mime_type(path).to_owned(), AttachmentData::Path(path.to_owned())))
+    }
+
+    /// Attaches the given bytes as a file with the given name, guessing its MIME type from the name's extension.
+    pub fn from_bytes<S: ToString>(name: S, bytes: Vec<u8>) -> Self {
+        let name = name.to_string();
+        let mime_type = guess_mime_type(Path::new(&name)).to_owned();
+        Self::new(name, mime_type, AttachmentData::Bytes(bytes))
+    }
+
+    /// The file name of the attachment.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    pub fn get_data(&self) -> &AttachmentData {
+        &self.data
+    }
+
+    /// Whether the attachment is an image that can be shown inline, e.g. a PNG.
+    pub fn is_image(&self) -> bool {
+        matches!(self.mime_type.as_str(), "image/png" | "image/jpeg" | "image/gif" | "image/webp")
+    }
+
+    /// Reads the contents of the attachment.
+    pub fn read(&self) -> io::Result<Cow<'_, [u8]>> {
+        match &self.data {
+            AttachmentData::Path(path) => fs::read(path).map(Cow::Owned)
+                .map_err(|e| io::Error::new(e.kind(), format!("Failed to read attachment {}: {}", path.display(), e))),
+            AttachmentData::Bytes(bytes) => Ok(Cow::Borrowed(bytes)),
+        }
+    }
+
+    /// The size of the attachment in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        match &self.data {
+            AttachmentData::Path(path) => fs::metadata(path).map(|metadata| metadata.len()),
+            AttachmentData::Bytes(bytes) => Ok(bytes.len() as u64),
+        }
+    }
+}
+
+/// The name and size of the attachment, e.g. `build.log (1.5 KiB)`, for destinations that can't send files.
+impl Display for Attachment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.size() {
+            Ok(size) => write!(f, "{} ({})", self.name, format_size(size)),
+            Err(_) => write!(f, "{} (unreadable)", self.name),
+        }
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+mod base64_bytes {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        STANDARD.decode(s).map_err(serde::de::Error::custom)
+    }
+}
+
+fn guess_mime_type(path: &Path) -> &'static str {
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachment() {
+        let graph = Attachment::from_bytes("graph.PNG", vec![0; 1536]);
+        assert_eq!(graph.get_mime_type(), "image/png");
+        assert!(graph.is_image());
+        assert_eq!(graph.to_string(), "graph.PNG (1.5 KiB)");
+
+        let attachment = Attachment::from_path(Path::new("test/secret.txt")).expect("Should exist");
+        assert_eq!(attachment.get_name(), "secret.txt");
+        assert_eq!(attachment.get_mime_type(), "text/plain");
+        assert_eq!(attachment.read().unwrap().len() as u64, attachment.size().unwrap());
+
+        assert!(Attachment::from_path(Path::new("test/nonexistent.log")).is_err());
+        assert!(Attachment::from_path(Path::new("test")).is_err());
+    }
+
+    #[test]
+    fn test_serialize_bytes() {
+        let attachment = Attachment::from_bytes("hello.txt", b"Hello".to_vec());
+        let json = serde_json::to_string(&attachment).unwrap();
+        assert!(json.contains("\"SGVsbG8=\""), "Should be base64: {}", json);
+        assert_eq!(serde_json::from_str::<Attachment>(&json).unwrap(), attachment);
+    }
+}
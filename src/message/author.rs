@@ -1,4 +1,5 @@
 use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// The hostname used when the hostname
 /// cannot be retrieved.
@@ -88,7 +89,7 @@ impl Author {
     ///
     /// assert_eq!(author.to_string(), "?/user/cron/db_checker");
     pub fn extend(&mut self, parts: String) {
-        let vec: Vec<String> = parts.split("/").into_iter()
+        let vec: Vec<String> = parts.split('/')
             .filter(|s| !s.is_empty())
             .map(|s| s.to_owned())
             .collect();
@@ -101,4 +102,19 @@ impl Display for Author {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.parts.join("/"))
     }
-}
\ No newline at end of file
+}
+
+impl<'de> Deserialize<'de> for Author {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let s = String::deserialize(deserializer)?;
+        Ok(Author {
+            parts: s.split('/').map(|s| s.to_owned()).collect(),
+        })
+    }
+}
+
+impl Serialize for Author {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(&self.parts.join("/"))
+    }
+}
@@ -1,6 +1,10 @@
+use std::collections::BTreeMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 use crate::message::component::Component;
 use crate::message::{Level, Message, MessageDetail};
+use crate::message::alert::Alert;
+use crate::message::action::Action;
+use crate::message::attachment::Attachment;
 use crate::message::author::Author;
 use crate::message::detail_builder::MessageDetailBuilder;
 
@@ -11,6 +15,10 @@ pub struct MessageBuilder {
     component: Option<Component>,
     author: Author,
     unix_timestamp_millis: Option<i64>,
+    fields: BTreeMap<String, String>,
+    attachments: Vec<Attachment>,
+    actions: Vec<Action>,
+    alert: Option<Alert>,
 }
 
 impl MessageBuilder {
@@ -27,6 +35,10 @@ impl MessageBuilder {
             component: None,
             author,
             unix_timestamp_millis: None,
+            fields: BTreeMap::new(),
+            attachments: vec![],
+            actions: vec![],
+            alert: None,
         }
     }
 
@@ -68,6 +80,30 @@ impl MessageBuilder {
         self
     }
 
+    /// Adds a key / value field to the message, replacing any existing field with the same key.
+    pub fn field<K: ToString, V: ToString>(&mut self, key: K, value: V) -> &mut Self {
+        self.fields.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Attaches a file to the message.
+    pub fn attach(&mut self, attachment: Attachment) -> &mut Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    /// Marks the message as being part of an [Alert], see its documentation.
+    pub fn alert(&mut self, alert: Alert) -> &mut Self {
+        self.alert = Some(alert);
+        self
+    }
+
+    /// Adds a link to the message, e.g. to a dashboard or runbook, which is shown as a button where possible.
+    pub fn action<L: ToString, U: ToString>(&mut self, label: L, url: U) -> &mut Self {
+        self.actions.push(Action::new(label, url));
+        self
+    }
+
     /// Sets the timestamp of the message.
     /// This is set by default to the time which the [MessageBuilder] was created.
     ///
@@ -90,6 +126,10 @@ impl MessageBuilder {
             component: self.component,
             author: self.author,
             unix_timestamp_millis: self.unix_timestamp_millis.unwrap_or_else(Self::get_unix_time_millis),
+            fields: self.fields,
+            attachments: self.attachments,
+            actions: self.actions,
+            alert: self.alert,
         }
     }
 
@@ -103,7 +143,11 @@ impl MessageBuilder {
             message_detail: self.detail.clone(),
             component: self.component.clone(),
             author: self.author.clone(),
-            unix_timestamp_millis: self.unix_timestamp_millis.unwrap_or_else(Self::get_unix_time_millis)
+            unix_timestamp_millis: self.unix_timestamp_millis.unwrap_or_else(Self::get_unix_time_millis),
+            fields: self.fields.clone(),
+            attachments: self.attachments.clone(),
+            actions: self.actions.clone(),
+            alert: self.alert.clone(),
         }
     }
 
@@ -113,4 +157,10 @@ impl MessageBuilder {
             .expect("Expected now to be after unix epoch")
             .as_millis() as i64
     }
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
\ No newline at end of file
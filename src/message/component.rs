@@ -79,6 +79,19 @@ impl From<&str> for Component {
     }
 }
 
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Component {
+    fn schema_name() -> String {
+        "Component".to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = gen.subschema_for::<String>().into_object();
+        schema.metadata().description = Some("A component, with parts separated by '/', e.g. \"database/backup\"".to_owned());
+        schema.into()
+    }
+}
+
 impl<'de> Deserialize<'de> for Component {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
         let s = String::deserialize(deserializer)?;
@@ -13,6 +13,12 @@ pub trait FormattedStringAppendable {
         self.append(FormattedString::plain(s));
         self
     }
+
+    /// Appends the text as a hyperlink to the url.
+    fn append_link<S: ToString, U: ToString>(&mut self, s: S, url: U) -> &mut Self {
+        self.append(FormattedString::styled(s, Style::Link { url: url.to_string() }));
+        self
+    }
 }
 
 pub struct MessageDetailBuilder {
@@ -57,11 +63,50 @@ impl MessageDetailBuilder {
         self
     }
 
+    /// Adds a bulleted list.
+    pub fn list<F>(&mut self, apply: F) -> &mut Self
+        where F: FnOnce(&mut ListBuilder) {
+        let mut list = ListBuilder::default();
+        apply(&mut list);
+        self.contents.push(FormattedMessageComponent::List(list.items));
+        self
+    }
+
+    /// Adds a table of keys and values.
+    pub fn table<F>(&mut self, apply: F) -> &mut Self
+        where F: FnOnce(&mut TableBuilder) {
+        let mut table = TableBuilder::default();
+        apply(&mut table);
+        self.contents.push(FormattedMessageComponent::Table(table.rows));
+        self
+    }
+
+    /// Adds a block quote.
+    pub fn quote<F>(&mut self, apply: F) -> &mut Self
+        where F: FnOnce(&mut TextBlockBuilder) {
+        let mut text_block = TextBlockBuilder::default();
+        apply(&mut text_block);
+        self.contents.push(FormattedMessageComponent::Quote(text_block.build_vec()));
+        self
+    }
+
+    /// Adds a horizontal rule.
+    pub fn rule(&mut self) -> &mut Self {
+        self.contents.push(FormattedMessageComponent::Rule);
+        self
+    }
+
     pub fn build(self) -> MessageDetail {
         MessageDetail::Formatted(FormattedMessageDetail::new(self.raw, self.contents))
     }
 }
 
+impl Default for MessageDetailBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Default)]
 pub struct TextBlockBuilder {
     contents: Vec<FormattedString>,
@@ -102,8 +147,49 @@ impl FormattedStringAppendable for SectionBuilder {
     }
 }
 
+#[derive(Default)]
+pub struct ListBuilder {
+    items: Vec<Vec<FormattedString>>,
+}
+
+impl ListBuilder {
+    pub fn item<F>(&mut self, apply: F) -> &mut Self
+        where F: FnOnce(&mut TextBlockBuilder) {
+        let mut text_block = TextBlockBuilder::default();
+        apply(&mut text_block);
+        self.items.push(text_block.build_vec());
+        self
+    }
+
+    pub fn plain_item<S: ToString>(&mut self, s: S) -> &mut Self {
+        self.items.push(vec![FormattedString::plain(s)]);
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct TableBuilder {
+    rows: Vec<(String, Vec<FormattedString>)>,
+}
+
+impl TableBuilder {
+    pub fn row<S, F>(&mut self, key: S, apply: F) -> &mut Self
+        where F: FnOnce(&mut TextBlockBuilder),
+              S: ToString {
+        let mut text_block = TextBlockBuilder::default();
+        apply(&mut text_block);
+        self.rows.push((key.to_string(), text_block.build_vec()));
+        self
+    }
+
+    pub fn plain_row<S: ToString, V: ToString>(&mut self, key: S, value: V) -> &mut Self {
+        self.rows.push((key.to_string(), vec![FormattedString::plain(value)]));
+        self
+    }
+}
+
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use crate::message::formatted_detail::{FormattedMessageComponent, FormattedMessageDetail, FormattedString, Style};
     use crate::message::detail_builder::{FormattedStringAppendable, MessageDetailBuilder};
     use crate::message::MessageDetail;
@@ -130,4 +216,60 @@ mod test {
 
         assert_eq!(built, test);
     }
-}
\ No newline at end of file
+
+    /// A detail using every component, for testing how it is rendered.
+    pub(crate) fn example() -> FormattedMessageDetail {
+        let mut builder = MessageDetailBuilder::new();
+        builder.section("Disk_usage", |section| {
+                section.append_plain("1.5 GB *free*, ")
+                    .append_styled("df -h", Style::Monospace)
+                    .append_link("docs", "https://example.com/a_(b)");
+            })
+            .table(|table| {
+                table.plain_row("/", "45%")
+                    .row("/home", |value| {
+                        value.append_styled("95%", Style::Bold);
+                    });
+            })
+            .list(|list| {
+                list.plain_item("a < b & c")
+                    .item(|item| {
+                        item.append_link("https://example.com", "https://example.com");
+                    });
+            })
+            .rule()
+            .quote(|quote| {
+                quote.append_styled("No space\nleft", Style::Italics);
+            });
+        match builder.build() {
+            MessageDetail::Formatted(formatted) => formatted,
+            _ => panic!("Should be formatted"),
+        }
+    }
+
+    #[test]
+    fn test_blocks() {
+        let test = FormattedMessageDetail::new(
+            "Raw not available".to_string(),
+            vec![
+                FormattedMessageComponent::Section("Disk_usage".to_owned(), vec![
+                    FormattedString::plain("1.5 GB *free*, "),
+                    FormattedString::styled("df -h", Style::Monospace),
+                    FormattedString::styled("docs", Style::Link { url: "https://example.com/a_(b)".to_owned() }),
+                ]),
+                FormattedMessageComponent::Table(vec![
+                    ("/".to_owned(), vec![FormattedString::plain("45%")]),
+                    ("/home".to_owned(), vec![FormattedString::styled("95%", Style::Bold)]),
+                ]),
+                FormattedMessageComponent::List(vec![
+                    vec![FormattedString::plain("a < b & c")],
+                    vec![FormattedString::styled("https://example.com", Style::Link { url: "https://example.com".to_owned() })],
+                ]),
+                FormattedMessageComponent::Rule,
+                FormattedMessageComponent::Quote(vec![FormattedString::styled("No space\nleft", Style::Italics)]),
+            ]
+        );
+
+        assert_eq!(example(), test);
+    }
+}
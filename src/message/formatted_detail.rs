@@ -1,6 +1,7 @@
 use std::mem;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FormattedMessageDetail {
     raw: String,
     components: Vec<FormattedMessageComponent>,
@@ -23,13 +24,21 @@ impl FormattedMessageDetail {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FormattedMessageComponent {
     Section(String, Vec<FormattedString>),
     Text(Vec<FormattedString>),
+    /// A bulleted list, containing the text of each item.
+    List(Vec<Vec<FormattedString>>),
+    /// Rows of keys and their values, e.g. the disk usage of each mount point.
+    Table(Vec<(String, Vec<FormattedString>)>),
+    /// A block quote, e.g. the output of a command.
+    Quote(Vec<FormattedString>),
+    /// A horizontal line separating the components before and after it.
+    Rule,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FormattedString {
     styles: Vec<Style>,
     s: String,
@@ -60,23 +69,29 @@ impl FormattedString {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Style {
     Bold,
     Italics,
     Monospace,
-    Code{ lang: String }
+    Code{ lang: String },
+    /// A hyperlink to the url.
+    Link { url: String },
+    /// The colour of the text, as a hex colour, e.g. `#cd0000`. Destinations that can't colour text ignore it.
+    Color { color: String },
 }
 
+/// Parses text with sections formatted on a line as `#<Section Name>#`.
+/// Text between triple backticks becomes a code block, everything else is plain text.
 pub fn parse_raw_to_formatted(s: &str) -> FormattedMessageDetail {
     let mut components = vec![];
 
     let mut section_title = None;
-    let mut section_text = vec![];
+    let mut section_text = String::new();
 
-    fn push_section(section_title: &mut Option<String>, section_text: &mut Vec<FormattedString>, components: &mut Vec<FormattedMessageComponent>) {
+    fn push_section(section_title: &mut Option<String>, section_text: &mut String, components: &mut Vec<FormattedMessageComponent>) {
         if section_title.is_some() || !section_text.is_empty() {
-            let old = mem::replace(section_text, vec![]);
+            let old = parse_section_text(&mem::take(section_text));
             let component = if let Some(title) = section_title.take() {
                 FormattedMessageComponent::Section(title, old)
             } else {
@@ -92,7 +107,8 @@ pub fn parse_raw_to_formatted(s: &str) -> FormattedMessageDetail {
             push_section(&mut section_title, &mut section_text, &mut components);
             section_title = Some(line[2..line.len() - 2].to_owned());
         } else {
-            section_text.extend_from_slice(&parse_section_text(&format!("{}\n", line)));
+            section_text.push_str(line);
+            section_text.push('\n');
         }
     }
     push_section(&mut section_title, &mut section_text, &mut components);
@@ -100,5 +116,48 @@ pub fn parse_raw_to_formatted(s: &str) -> FormattedMessageDetail {
 }
 
 fn parse_section_text(s: &str) -> Vec<FormattedString> {
-    vec![FormattedString::new(s.to_owned(), vec![])]
-}
\ No newline at end of file
+    const CODE_FENCE: &str = "```";
+    let mut parts = vec![];
+    let mut rest = s;
+    while let Some(start) = rest.find(CODE_FENCE) {
+        let after_fence = &rest[start + CODE_FENCE.len()..];
+        let end = match after_fence.find(CODE_FENCE) {
+            Some(end) => end,
+            None => break,
+        };
+        if start > 0 {
+            parts.push(FormattedString::plain(&rest[..start]));
+        }
+        let code = &after_fence[..end];
+        // The language is a single word on the same line as the opening fence, e.g. ```rust
+        let (lang, code) = match code.split_once('\n') {
+            Some((lang, code)) if !lang.contains(char::is_whitespace) => (lang, code),
+            _ => ("", code),
+        };
+        parts.push(FormattedString::styled(code, Style::Code { lang: lang.to_owned() }));
+        rest = &after_fence[end + CODE_FENCE.len()..];
+    }
+    if !rest.is_empty() {
+        parts.push(FormattedString::plain(rest));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_raw_to_formatted() {
+        let formatted = parse_raw_to_formatted("Hello\n#<Disk Usage>#\n```/dev/sda1 45%\n/dev/sdb1 95%```\n```sh\ndf -h\n```\nunclosed ```");
+        assert_eq!(formatted.components(), &vec![
+            FormattedMessageComponent::Text(vec![FormattedString::plain("Hello\n")]),
+            FormattedMessageComponent::Section("Disk Usage".to_owned(), vec![
+                FormattedString::styled("/dev/sda1 45%\n/dev/sdb1 95%", Style::Code { lang: String::new() }),
+                FormattedString::plain("\n"),
+                FormattedString::styled("df -h\n", Style::Code { lang: "sh".to_owned() }),
+                FormattedString::plain("\nunclosed ```\n"),
+            ]),
+        ]);
+    }
+}
@@ -0,0 +1,349 @@
+//! Parses a subset of [CommonMark](https://commonmark.org/) into a [`FormattedMessageDetail`].
+//!
+//! Supported are:
+//! - Headings (`# Title`), which start a new [`Section`](FormattedMessageComponent::Section)
+//! - `**bold**` / `__bold__`
+//! - `*italics*` / `_italics_`
+//! - `` `inline code` ``, as [`Monospace`](Style::Monospace)
+//! - `[links](https://example.com)`
+//! - Fenced code blocks, with an optional language, as [`Code`](Style::Code)
+//! - Bulleted lists (`- item`), block quotes (`> quote`) and horizontal rules (`---`)
+//! - Backslash escapes, e.g. `\*not italics\*`
+//!
+//! Anything else is kept as plain text.
+use std::mem;
+use crate::message::formatted_detail::{FormattedMessageComponent, FormattedMessageDetail, FormattedString, Style};
+
+const CODE_FENCE: &str = "```";
+
+/// Parses the markdown into a formatted message, keeping the markdown as the raw text.
+pub fn parse_markdown(s: &str) -> FormattedMessageDetail {
+    let mut components = vec![];
+    let mut title = None;
+    let mut text = vec![];
+
+    let mut lines = s.lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some(lang) = code_fence(line) {
+            let mut code = String::new();
+            for line in lines.by_ref() {
+                if code_fence(line) == Some("") {
+                    break;
+                }
+                code.push_str(line);
+                code.push('\n');
+            }
+            push_part(&mut text, FormattedString::styled(code, Style::Code { lang: lang.to_owned() }));
+        } else if let Some(heading) = heading(line) {
+            push_component(&mut components, title.take(), mem::take(&mut text));
+            title = Some(heading.to_owned());
+        } else if is_rule(line) {
+            push_component(&mut components, title.take(), mem::take(&mut text));
+            components.push(FormattedMessageComponent::Rule);
+        } else if let Some(item) = list_item(line) {
+            push_component(&mut components, title.take(), mem::take(&mut text));
+            let mut items = vec![parse_line(item)];
+            while let Some(item) = lines.peek().and_then(|line| list_item(line)) {
+                items.push(parse_line(item));
+                lines.next();
+            }
+            components.push(FormattedMessageComponent::List(items));
+        } else if let Some(quoted) = quote(line) {
+            push_component(&mut components, title.take(), mem::take(&mut text));
+            let mut quote_text = parse_line(quoted);
+            while let Some(quoted) = lines.peek().and_then(|line| quote(line)) {
+                push_part(&mut quote_text, FormattedString::plain("\n"));
+                for part in parse_line(quoted) {
+                    push_part(&mut quote_text, part);
+                }
+                lines.next();
+            }
+            components.push(FormattedMessageComponent::Quote(quote_text));
+        } else {
+            for part in parse_line(line) {
+                push_part(&mut text, part);
+            }
+            push_part(&mut text, FormattedString::plain("\n"));
+        }
+    }
+    push_component(&mut components, title, text);
+    FormattedMessageDetail::new(s.to_owned(), components)
+}
+
+/// Parses the inline formatting of a line.
+fn parse_line(line: &str) -> Vec<FormattedString> {
+    let mut text = vec![];
+    for part in parse_inline(line, &[]) {
+        push_part(&mut text, part);
+    }
+    text
+}
+
+fn push_component(components: &mut Vec<FormattedMessageComponent>, title: Option<String>, text: Vec<FormattedString>) {
+    match title {
+        Some(title) => components.push(FormattedMessageComponent::Section(title, text)),
+        None if !text.is_empty() => components.push(FormattedMessageComponent::Text(text)),
+        None => {}
+    }
+}
+
+/// Adds the part to the text, joining it onto the previous part if they have the same style.
+fn push_part(text: &mut Vec<FormattedString>, part: FormattedString) {
+    if part.get_string().is_empty() {
+        return;
+    }
+    match text.last_mut() {
+        Some(last) if last.get_styles() == part.get_styles() && !is_code_block(last) => {
+            *last = FormattedString::new(format!("{}{}", last.get_string(), part.get_string()), part.get_styles().clone());
+        }
+        _ => text.push(part),
+    }
+}
+
+fn is_code_block(part: &FormattedString) -> bool {
+    part.get_styles().iter().any(|style| matches!(style, Style::Code { .. }))
+}
+
+/// The language of the code block if the line starts or ends one.
+fn code_fence(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix(CODE_FENCE).map(|lang| lang.trim())
+}
+
+/// Whether the line is a horizontal rule, e.g. `---` or `* * *`
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && ['-', '*', '_'].iter().any(|rule| chars.iter().all(|c| c == rule))
+}
+
+/// The text of the list item, if the line is one.
+fn list_item(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    ["- ", "* ", "+ "].iter().find_map(|marker| line.strip_prefix(marker))
+}
+
+/// The quoted text, if the line is part of a block quote.
+fn quote(line: &str) -> Option<&str> {
+    let quoted = line.trim_start().strip_prefix('>')?;
+    Some(quoted.strip_prefix(' ').unwrap_or(quoted))
+}
+
+/// The text of the heading, if the line is one.
+fn heading(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let level = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    // Closing #s are optional.
+    Some(rest.trim().trim_end_matches('#').trim_end())
+}
+
+fn parse_inline(s: &str, styles: &[Style]) -> Vec<FormattedString> {
+    let mut parts = vec![];
+    let mut plain = String::new();
+    let flush = |plain: &mut String, parts: &mut Vec<FormattedString>| {
+        if !plain.is_empty() {
+            parts.push(FormattedString::new(mem::take(plain), styles.to_vec()));
+        }
+    };
+
+    let mut i = 0;
+    'outer: while let Some(c) = s[i..].chars().next() {
+        let rest = &s[i..];
+        match c {
+            '\\' => {
+                if let Some(escaped) = rest[1..].chars().next().filter(|c| c.is_ascii_punctuation()) {
+                    plain.push(escaped);
+                    i += 1 + escaped.len_utf8();
+                    continue;
+                }
+            }
+            '`' => {
+                let ticks = rest.len() - rest.trim_start_matches('`').len();
+                let delimiter = &rest[..ticks];
+                if let Some(end) = rest[ticks..].find(delimiter) {
+                    let code = &rest[ticks..ticks + end];
+                    // A single space either side is removed, so code can start or end with a backtick.
+                    let code = code.strip_prefix(' ').and_then(|c| c.strip_suffix(' '))
+                        .filter(|c| !c.is_empty())
+                        .unwrap_or(code);
+                    flush(&mut plain, &mut parts);
+                    parts.push(FormattedString::new(code, with_style(styles, Style::Monospace)));
+                    i += ticks + end + ticks;
+                    continue;
+                }
+            }
+            '[' => {
+                if let Some((text, url, len)) = link(rest) {
+                    flush(&mut plain, &mut parts);
+                    parts.extend(parse_inline(text, &with_style(styles, Style::Link { url: url.to_owned() })));
+                    i += len;
+                    continue;
+                }
+            }
+            '*' | '_' => {
+                let double: String = [c, c].iter().collect();
+                let single = c.to_string();
+                for (delimiter, style) in [(double, Style::Bold), (single, Style::Italics)] {
+                    if !rest.starts_with(&delimiter) {
+                        continue;
+                    }
+                    if let Some(end) = find_closing(s, i, &delimiter) {
+                        flush(&mut plain, &mut parts);
+                        parts.extend(parse_inline(&s[i + delimiter.len()..end], &with_style(styles, style)));
+                        i = end + delimiter.len();
+                        continue 'outer;
+                    }
+                }
+            }
+            _ => {}
+        }
+        plain.push(c);
+        i += c.len_utf8();
+    }
+    flush(&mut plain, &mut parts);
+    parts
+}
+
+/// The text and url of the link at the start of `s`, and the length of the link's markdown.
+fn link(s: &str) -> Option<(&str, &str, usize)> {
+    let text_end = s.find("](")?;
+    let text = &s[1..text_end];
+    let url_start = text_end + 2;
+    let url_end = url_start + s[url_start..].find(')')?;
+    let url = s[url_start..url_end].trim();
+    if text.is_empty() || text.contains('[') || url.is_empty() || url.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((text, url, url_end + 1))
+}
+
+fn with_style(styles: &[Style], style: Style) -> Vec<Style> {
+    let mut styles = styles.to_vec();
+    styles.push(style);
+    styles
+}
+
+/// Finds the delimiter closing the emphasis opened at `start`, if there is one.
+fn find_closing(s: &str, start: usize, delimiter: &str) -> Option<usize> {
+    let underscore = delimiter.starts_with('_');
+    let before = s[..start].chars().next_back();
+    let content_start = start + delimiter.len();
+    // Emphasis can't start with a space, and underscores within words are left alone.
+    if s[content_start..].chars().next().is_none_or(char::is_whitespace)
+        || (underscore && before.is_some_and(char::is_alphanumeric)) {
+        return None;
+    }
+
+    let mut i = content_start;
+    while let Some(c) = s[i..].chars().next() {
+        let rest = &s[i..];
+        if c == '\\' {
+            i += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if let Some(after_delimiter) = rest.strip_prefix(delimiter) {
+            // A single delimiter shouldn't close on half of a double one.
+            let doubled = delimiter.len() == 1 && after_delimiter.starts_with(delimiter);
+            let preceded_by_space = s[..i].chars().next_back().is_some_and(char::is_whitespace);
+            let within_word = underscore && after_delimiter.chars().next().is_some_and(char::is_alphanumeric);
+            if !doubled && i > content_start && !preceded_by_space && !within_word {
+                return Some(i);
+            }
+            if doubled {
+                i += 2;
+                continue;
+            }
+        }
+        i += c.len_utf8();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::formatted_detail::FormattedMessageComponent::{Section, Text};
+    use super::*;
+
+    fn plain(s: &str) -> FormattedString {
+        FormattedString::plain(s)
+    }
+
+    fn styled(s: &str, styles: Vec<Style>) -> FormattedString {
+        FormattedString::new(s, styles)
+    }
+
+    #[test]
+    fn test_parse_markdown() {
+        let s = "Backup **failed** on _db1_\n\
+                 # Error\n\
+                 Exit code `1`\n\
+                 ```sh\n\
+                 pg_dump db1\n\
+                 ```\n\
+                 ## Log ##\n\
+                 see the log";
+        let formatted = parse_markdown(s);
+        assert_eq!(formatted.raw(), s);
+        assert_eq!(formatted.components(), &vec![
+            Text(vec![plain("Backup "), styled("failed", vec![Style::Bold]), plain(" on "),
+                      styled("db1", vec![Style::Italics]), plain("\n")]),
+            Section("Error".to_owned(), vec![
+                plain("Exit code "), styled("1", vec![Style::Monospace]), plain("\n"),
+                styled("pg_dump db1\n", vec![Style::Code { lang: "sh".to_owned() }]),
+            ]),
+            Section("Log".to_owned(), vec![plain("see the log\n")]),
+        ]);
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(parse_inline("**bold _and italic_**", &[]), vec![
+            styled("bold ", vec![Style::Bold]),
+            styled("and italic", vec![Style::Bold, Style::Italics]),
+        ]);
+        assert_eq!(parse_inline("*a **b** c*", &[]), vec![
+            styled("a ", vec![Style::Italics]),
+            styled("b", vec![Style::Italics, Style::Bold]),
+            styled(" c", vec![Style::Italics]),
+        ]);
+        assert_eq!(parse_inline("`` a`b ``", &[]), vec![styled("a`b", vec![Style::Monospace])]);
+        assert_eq!(parse_inline("see [the **docs**](https://example.com)", &[]), vec![
+            plain("see "),
+            styled("the ", vec![Style::Link { url: "https://example.com".to_owned() }]),
+            styled("docs", vec![Style::Link { url: "https://example.com".to_owned() }, Style::Bold]),
+        ]);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let s = "# Disks\n\
+                 - `/` is **fine**\n\
+                 * `/home` is full\n\
+                 ---\n\
+                 > No space\n\
+                 > left on device\n\
+                 Retrying";
+        assert_eq!(parse_markdown(s).components(), &vec![
+            Section("Disks".to_owned(), vec![]),
+            FormattedMessageComponent::List(vec![
+                vec![styled("/", vec![Style::Monospace]), plain(" is "), styled("fine", vec![Style::Bold])],
+                vec![styled("/home", vec![Style::Monospace]), plain(" is full")],
+            ]),
+            FormattedMessageComponent::Rule,
+            FormattedMessageComponent::Quote(vec![plain("No space\nleft on device")]),
+            Text(vec![plain("Retrying\n")]),
+        ]);
+    }
+
+    #[test]
+    fn test_not_formatting() {
+        let unchanged = ["snake_case_name", "2 * 3 * 4", "**unclosed", "* not italics*", "`unclosed", "#hashtag", "[not a link]", "[text] (url)"];
+        for s in unchanged {
+            assert_eq!(parse_inline(s, &[]), vec![plain(s)], "{} should be plain", s);
+        }
+        assert_eq!(parse_inline(r"\*escaped\* \`code\`", &[]), vec![plain("*escaped* `code`")]);
+        assert_eq!(parse_markdown("#hashtag").components(), &vec![Text(vec![plain("#hashtag\n")])]);
+    }
+}
@@ -1,12 +1,21 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use message::formatted_detail::FormattedMessageDetail;
 use serde::{Serialize, Deserialize};
 use crate::message;
+use crate::message::action::Action;
+use crate::message::alert::Alert;
+use crate::message::attachment::Attachment;
 use crate::message::author::Author;
 use crate::message::component::Component;
 
+pub mod action;
+pub mod alert;
+pub mod ansi;
+pub mod attachment;
 pub mod formatted_detail;
+pub mod markdown;
 pub mod author;
 pub mod component;
 pub mod builder;
@@ -27,11 +36,15 @@ pub mod detail_builder;
 /// - [Component] - Optional, indicating what the message is about, e.g a program or server.
 /// - [Author] - Who created the message
 /// - Timestamp - The unix timestamp in milliseconds, showing when the message was sent.
+/// - Fields - Optional key / value metadata, e.g. `job_id=123` or `env=prod`, which can be used for routing.
+/// - [Attachment]s - Optional files, e.g. a log file or graph.
+/// - [Action]s - Optional links, e.g. to a dashboard or runbook, shown as buttons where possible.
+/// - [Alert] - Optional, the ongoing problem this message is about, and whether it is firing or resolved.
 ///
 /// [`MessageDestination`]: crate::destination::MessageDestination
 /// [`MessageBuilder`]: builder::MessageBuilder
 /// [`MessageDetailBuilder`]: detail_builder::MessageDetailBuilder
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     level: Level,
     title: Option<String>,
@@ -39,6 +52,14 @@ pub struct Message {
     component: Option<Component>,
     author: Author,
     unix_timestamp_millis: i64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    actions: Vec<Action>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alert: Option<Alert>,
 }
 
 impl Message {
@@ -52,10 +73,38 @@ impl Message {
             message_detail,
             component,
             author,
-            unix_timestamp_millis
+            unix_timestamp_millis,
+            fields: BTreeMap::new(),
+            attachments: vec![],
+            actions: vec![],
+            alert: None,
         }
     }
 
+    /// Sets the key / value fields of the message.
+    pub fn with_fields(mut self, fields: BTreeMap<String, String>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Sets the files attached to the message.
+    pub fn with_attachments(mut self, attachments: Vec<Attachment>) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    /// Sets the links shown with the message.
+    pub fn with_actions(mut self, actions: Vec<Action>) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    /// Marks the message as being part of an [Alert].
+    pub fn with_alert(mut self, alert: Alert) -> Self {
+        self.alert = Some(alert);
+        self
+    }
+
     pub fn get_level(&self) -> &Level {
         &self.level
     }
@@ -79,9 +128,25 @@ impl Message {
     pub fn get_component(&self) -> &Option<Component> {
         &self.component
     }
+
+    pub fn get_fields(&self) -> &BTreeMap<String, String> {
+        &self.fields
+    }
+
+    pub fn get_attachments(&self) -> &Vec<Attachment> {
+        &self.attachments
+    }
+
+    pub fn get_actions(&self) -> &Vec<Action> {
+        &self.actions
+    }
+
+    pub fn get_alert(&self) -> &Option<Alert> {
+        &self.alert
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum MessageDetail {
     Raw(String),
     Formatted(FormattedMessageDetail),
@@ -109,14 +174,35 @@ impl Default for MessageDetail {
 /// The level / severity of the [Message]. This can be thought of as the log level.
 /// This is used in conjunction to [Component] to indicate how a message should be
 /// routed.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+///
+/// From least to most severe: Debug, Info, Success, Notice, Warn, Error, Critical, SelfError.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "binary", derive(clap::ArgEnum))]
 pub enum Level {
+    /// Detailed information that is only useful when investigating a problem.
+    ///
+    /// # Examples #
+    /// - The steps a job took, and how long each one took.
+    Debug,
     /// Indicates an informational message when everything is working properly.
     /// # Examples #
     /// - A job has completed successfully, e.g a backup
     /// - A daily status update to confirm that everything is running correctly.
+    #[default]
     Info,
+    /// Indicates that a job has completed successfully, shown in green where possible.
+    ///
+    /// # Examples #
+    /// - A backup finished
+    /// - A deployment succeeded
+    Success,
+    /// Something normal, but significant, that the user should be aware of.
+    ///
+    /// # Examples #
+    /// - A server was restarted for updates
+    /// - A certificate was renewed
+    Notice,
     /// Used when something unexpected occurs that could be the source of an error,
     /// but requires the user to check whether it is actually an issue.
     ///
@@ -133,11 +219,17 @@ pub enum Level {
     ///     - Or the program is malfunctioning
     /// - A program restarted in attempt to recover itself, but is expected to recover safely
     Error,
+    /// Indicates a serious failure that needs attention immediately.
+    ///
+    /// # Examples #
+    /// - A service is down, and won't recover by itself
+    /// - A disk is full, or failing
+    Critical,
     /// Indicates a failure in the notifications own configuration / workings.
     ///
     /// # Examples #
     /// - Sent by [`MessageRouter`] to [`Root`] level [`MessageDestination`]s when a message
-    /// cannot be sent to one or more destinations (e.g. network failure, invalid tokens)
+    ///   cannot be sent to one or more destinations (e.g. network failure, invalid tokens)
     ///
     /// [`Root`]: crate::destination::routed_destination::MessageRoutingBehaviour::Root
     /// [`MessageDestination`]: crate::destination::MessageDestination
@@ -145,25 +237,23 @@ pub enum Level {
     SelfError,
 }
 
-impl Default for Level {
-    fn default() -> Self {
-        Self::Info
-    }
-}
-
 impl Level {
     pub(crate) fn get_priority(&self) -> u32 {
         match &self {
+            Self::Debug => 0,
             Self::Info => 1,
-            Self::Warn => 3,
-            Self::Error => 4,
-            Self::SelfError => 5,
+            Self::Success => 2,
+            Self::Notice => 3,
+            Self::Warn => 4,
+            Self::Error => 5,
+            Self::Critical => 6,
+            Self::SelfError => 7,
         }
     }
 
     /// Gets the least severe [Level]
     pub fn min() -> Level {
-        Level::Info
+        Level::Debug
     }
 
     /// Gets the most severe [Level]
@@ -183,3 +273,34 @@ impl Ord for Level {
         self.get_priority().cmp(&other.get_priority())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::destination::message_condition::MessageCondition;
+    use crate::message::builder::MessageBuilder;
+    use super::*;
+
+    #[test]
+    fn test_level_order() {
+        let levels = [Level::Debug, Level::Info, Level::Success, Level::Notice,
+            Level::Warn, Level::Error, Level::Critical, Level::SelfError];
+        assert!(levels.windows(2).all(|pair| pair[0] < pair[1]), "Levels should be in order of severity");
+        assert_eq!(Level::min(), Level::Debug);
+        assert_eq!(Level::max(), Level::SelfError);
+    }
+
+    #[test]
+    fn test_existing_level_names() {
+        let condition: MessageCondition = toml::from_str("min_level = \"Warn\"\nmax_level = \"Error\"")
+            .expect("Existing level names should still parse");
+        let mut builder = MessageBuilder::new();
+        for (level, matches) in [(Level::Info, false), (Level::Notice, false), (Level::Warn, true),
+                                 (Level::Error, true), (Level::Critical, false), (Level::SelfError, false)] {
+            builder.level(level.clone());
+            assert_eq!(condition.matches(&builder.build_clone()), matches, "{:?}", level);
+        }
+
+        assert_eq!(serde_json::to_string(&Level::SelfError).unwrap(), "\"SelfError\"");
+        assert_eq!(serde_json::from_str::<Level>("\"Success\"").unwrap(), Level::Success);
+    }
+}
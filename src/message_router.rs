@@ -1,21 +1,37 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::mem;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use crate::config::Config;
 use crate::destination::routed_destination::{MessageRoutingBehaviour, RoutedDestination};
 use crate::destination::message_condition::MessageCondition;
-use crate::message::Message;
+use crate::destination::schedule::{QuietHours, QuietHoursAction};
+use crate::message::{Level, Message};
+use crate::message::alert::AlertState;
+use crate::message::builder::MessageBuilder;
+use crate::message::detail_builder::FormattedStringAppendable;
+use crate::progress::{ProgressHandle, ProgressMessage};
 use crate::send_error::{SendError, SendErrors};
 use serde::{Serialize, Deserialize};
 use crate::send_error::borrowed::SendErrorBorrowed;
+use crate::send_error::fallback::FallbackPath;
+use crate::send_error::partial;
 use crate::send_error::owned::SendErrorOwned;
 use crate::send_error::reported::{ErrorReportSummary, ReportedSendError};
 
 pub struct MessageRouter {
     destinations: Vec<Box<dyn RoutedDestination>>,
+    deferred: Mutex<Vec<DeferredMessage>>,
+    alerts: Mutex<Vec<OpenAlert>>,
 }
 
 impl MessageRouter {
     pub fn empty() -> Self {
         Self {
             destinations: vec![],
+            deferred: Mutex::new(vec![]),
+            alerts: Mutex::new(vec![]),
         }
     }
 
@@ -29,6 +45,8 @@ impl MessageRouter {
 
         Self {
             destinations,
+            deferred: Mutex::new(vec![]),
+            alerts: Mutex::new(vec![]),
         }
     }
 
@@ -37,86 +55,499 @@ impl MessageRouter {
     }
 
     pub fn route<'a>(&self, message: &'a Message) -> Result<usize, SendErrors<'a>> {
-        let mut errors: Vec<SendErrorBorrowed<'a>> = vec![];
+        let mut state = self.start_route(message);
+        self.route_all(&mut state);
+        self.finish_route(state)
+    }
 
-        let mut sent_to_non_root_dest = false;
+    /// Sends the message to each destination that should receive it.
+    fn route_all(&self, state: &mut RouteState) {
+        let message = state.message;
+        for dest in self.destinations.iter()
+            .filter(|dest| dest.get_routing_type().always_send_messages())
+            .filter(|dest| dest.should_receive(message)) {
 
-        let mut successful = 0;
+            self.send_to_dest(dest.as_ref(), state);
+        }
 
-        fn send_wrap<'a>(destination: &dyn RoutedDestination, message: &'a Message) -> Result<(), SendErrorBorrowed<'a>> {
-            destination.send(message).map_err(|err| {
-                SendErrorBorrowed::create(err, destination.get_id().to_owned(), message)
-            })
+        if !state.sent_to_non_root_dest {
+            // Find a drain.
+            for dest in self.destinations.iter()
+                .filter(|dest| dest.get_routing_type() == &MessageRoutingBehaviour::Drain)
+                .filter(|dest| dest.should_receive(message)) {
+
+                self.send_to_dest(dest.as_ref(), state);
+            }
         }
+    }
+
+    /// Creates a handle to send a message and then update it in place, see [`ProgressHandle`].
+    pub fn progress(&self) -> ProgressHandle<'_> {
+        ProgressHandle::new(self, ProgressMessage::default())
+    }
 
-        fn send_to_dest<'a>(dest: &dyn RoutedDestination,
-                            sent_to_non_root_dest: &mut bool,
-                            successful: &mut usize,
-                            errors: &mut Vec<SendErrorBorrowed<'a>>,
-                            message: &'a Message) {
-            match send_wrap(dest, message) {
-                Ok(()) => {
-                    if !dest.is_root() {
-                        *sent_to_non_root_dest = true;
+    /// Continues updating the messages sent by an earlier [`ProgressHandle`], e.g. from a previous run of a script.
+    pub fn resume_progress(&self, progress: ProgressMessage) -> ProgressHandle<'_> {
+        ProgressHandle::new(self, progress)
+    }
+
+    /// Routes the message if it hasn't been sent yet, otherwise edits the messages that were sent.
+    ///
+    /// Updates are only sent to destinations that [should receive](RoutedDestination::should_receive) them,
+    /// and are subject to their [`QuietHours`], except that edits never notify anyone so are only held back by `Drop`.
+    /// Destinations that failed to receive the first message, or can't edit messages, are sent each update as a new message.
+    pub(crate) fn update_progress<'a>(&self, message: &'a Message, progress: &mut ProgressMessage) -> Result<usize, SendErrors<'a>> {
+        if !progress.is_sent() {
+            let mut state = self.start_route(message);
+            state.progress = Some(BTreeMap::new());
+            self.route_all(&mut state);
+            let mut messages = state.progress.take().unwrap_or_default();
+            // Destinations that failed are sent each update as a new message, but ids that don't exist,
+            // e.g. an unknown fallback, would only fail again.
+            for error in &state.errors {
+                let id = error.get_failed_destination_id();
+                if self.get_destination(id).is_some() {
+                    messages.entry(id.to_owned()).or_insert(None);
+                }
+            }
+            *progress = ProgressMessage::new(messages);
+            return self.finish_route(state);
+        }
+
+        let mut state = RouteState::new(message);
+        for (id, message_id) in progress.get_messages() {
+            let dest = match self.get_destination(id) {
+                Some(dest) => dest,
+                None => {
+                    state.errors.push(SendErrorBorrowed::create(format!("No destination with id '{}'", id).into(), id.to_owned(), message));
+                    continue;
+                }
+            };
+            if !dest.should_receive(message) {
+                continue;
+            }
+            let message_id = match message_id {
+                Some(message_id) => message_id,
+                None => {
+                    if let Err(err) = self.dispatch(dest, &mut state) {
+                        state.errors.push(err);
                     }
-                    *successful += 1;
+                    continue;
                 }
-                Err(send_err) => errors.push(send_err),
+            };
+            if dest.get_routing_info().get_quiet_hours_action(message, message.get_unix_timestamp_millis()) == Some(&QuietHoursAction::Drop) {
+                continue;
+            }
+            match dest.edit(message_id, message) {
+                Ok(()) => state.successful += 1,
+                Err(err) => state.errors.push(SendErrorBorrowed::create(err, id.to_owned(), message)),
             }
         }
+        self.finish_route(state)
+    }
 
-        for dest in self.destinations.iter()
-            .filter(|dest| dest.get_routing_type().always_send_messages())
-            .filter(|dest| dest.should_receive(message)) {
+    /// Sends the message to only the destinations with the given ids, ignoring the [`RoutingInfo`]
+    /// of each destination.
+    ///
+    /// Failures are still reported to [`Root`](MessageRoutingBehaviour::Root) destinations.
+    pub fn route_to<'a>(&self, message: &'a Message, destination_ids: &[String]) -> Result<usize, SendErrors<'a>> {
+        let mut state = self.start_route(message);
 
-            send_to_dest(dest.as_ref(), &mut sent_to_non_root_dest, &mut successful, &mut errors, message);
+        for id in destination_ids {
+            let result = match self.get_destination(id) {
+                Some(dest) => deliver(dest, false, &mut state),
+                None => Err(format!("No destination with id '{}'", id).into()),
+            };
+            match result {
+                Ok(()) => state.successful += 1,
+                Err(err) => state.errors.push(SendErrorBorrowed::create(err, id.to_owned(), message)),
+            }
         }
 
-        if !sent_to_non_root_dest {
-            // Find a drain.
-            for dest in self.destinations.iter()
-                .filter(|dest| dest.get_routing_type() == &MessageRoutingBehaviour::Drain)
-                .filter(|dest| dest.should_receive(message)) {
+        self.finish_route(state)
+    }
 
-                send_to_dest(dest.as_ref(), &mut sent_to_non_root_dest, &mut successful, &mut errors, message);
+    /// Starts routing the message, finding the open alert it is part of if it has an [`Alert`](crate::message::alert::Alert).
+    fn start_route<'a>(&self, message: &'a Message) -> RouteState<'a> {
+        let mut state = RouteState::new(message);
+        let alert = match message.get_alert() {
+            Some(alert) => alert,
+            None => return state,
+        };
+        let mut alerts = self.alerts.lock().unwrap();
+        let open_alert = alerts.iter()
+            .position(|open| open.key == alert.get_key())
+            .map(|i| alerts.remove(i));
+        match (alert.get_state(), open_alert) {
+            (AlertState::Firing, open_alert) => {
+                state.open_alert = Some(open_alert.unwrap_or_else(|| OpenAlert::new(alert.get_key().to_owned(), message.get_unix_timestamp_millis())));
             }
+            (AlertState::Resolved, Some(open_alert)) => {
+                let alert = alert.clone().with_fired_at(open_alert.fired_at);
+                state.resolving = Some(message.clone().with_alert(alert));
+                state.open_alert = Some(open_alert);
+            }
+            (AlertState::Resolved, None) => {}
         }
+        state
+    }
 
-        if errors.is_empty() {
-            return Ok(successful);
+    fn finish_route<'a>(&self, mut state: RouteState<'a>) -> Result<usize, SendErrors<'a>> {
+        if let Some(open_alert) = state.open_alert.take() {
+            // If the message resolving the alert didn't reach anywhere, the alert is kept open,
+            // so that resolving it again is still grouped with the messages that fired it.
+            let undelivered = state.successful == 0 && state.deferred == 0 && !state.errors.is_empty();
+            if state.resolving.is_none() || undelivered {
+                self.alerts.lock().unwrap().push(open_alert);
+            }
         }
 
-        let root_destinations: Vec<_> = self.destinations.iter()
-            .filter(|dest| dest.is_root())
-            .map(|dest| dest.to_owned())
-            .collect();
+        if state.errors.is_empty() {
+            return Ok(state.successful);
+        }
 
-        let reported_errors = errors.into_iter().map(|error| {
-            let report_message = error.create_report_message();
-            let mut any_report_success = false;
-            let mut report_fails = vec![];
+        let reported_errors = state.errors.into_iter().map(|error| {
+            let summary = self.report_error(&error);
+            ReportedSendError::new(error, summary)
+        }).collect();
 
-            for root_dest in &root_destinations {
-                match root_dest.send(&report_message) {
-                    Ok(_) => {
-                        any_report_success = true;
-                    }
-                    Err(send_err) => {
-                        let send_err = SendErrorOwned::create(send_err, root_dest.get_id().to_owned(), report_message.clone());
-                        report_fails.push(send_err);
-                    }
+
+        Err(SendErrors::new(state.message, reported_errors, state.successful, state.fallback_paths))
+    }
+
+    /// Sends the message to the destination, following its fallback chain if that fails.
+    fn send_to_dest<'a>(&self, dest: &dyn RoutedDestination, state: &mut RouteState<'a>) {
+        if state.was_delivered_to(dest.get_id()) {
+            return;
+        }
+        if let Err(send_err) = self.dispatch(dest, state) {
+            if dest.get_routing_info().get_fallback().is_empty() {
+                state.errors.push(send_err);
+            } else {
+                self.send_to_fallback(dest, send_err, state);
+            }
+        }
+    }
+
+    /// Tries each destination in the fallback chain of the failed destination in order,
+    /// until one of them accepts the message.
+    ///
+    /// If one does, the errors are only recorded in the [`FallbackPath`], otherwise they are reported as usual.
+    /// Fallback destinations are sent the message even if their [`RoutingInfo`] would not send it to them.
+    fn send_to_fallback<'a>(&self, failed: &dyn RoutedDestination, error: SendErrorBorrowed<'a>, state: &mut RouteState<'a>) {
+        let mut path = FallbackPath::new(failed.get_id().to_owned());
+        let mut errors = vec![error];
+        for id in failed.get_routing_info().get_fallback() {
+            path.attempt(id.to_owned());
+            if state.was_delivered_to(id) {
+                path.delivered();
+                break;
+            }
+            let result = match self.get_destination(id) {
+                Some(fallback) => self.dispatch(fallback, state),
+                None => Err(SendErrorBorrowed::create(format!("No destination with id '{}'", id).into(), id.to_owned(), state.message)),
+            };
+            match result {
+                Ok(true) => {
+                    path.delivered();
+                    break;
                 }
+                Ok(false) => {}
+                Err(send_err) => errors.push(send_err),
             }
+        }
+        for error in &errors {
+            path.failed(error.get_failed_destination_id().to_owned(), error.get_error().to_string());
+        }
+        if path.get_delivered_to().is_none() {
+            state.errors.extend(errors);
+        }
+        state.fallback_paths.push(path);
+    }
 
-            ReportedSendError::new(error, ErrorReportSummary::new(any_report_success, report_fails))
-        }).collect();
+    /// Sends the message to a single destination, taking into account its [`QuietHours`].
+    /// Returns whether the destination accepted the message, either by sending it or deferring it.
+    fn dispatch<'a>(&self, dest: &dyn RoutedDestination, state: &mut RouteState<'a>) -> Result<bool, SendErrorBorrowed<'a>> {
+        let message = state.message;
+        let result = match dest.get_routing_info().get_quiet_hours_action(message, message.get_unix_timestamp_millis()) {
+            None => deliver(dest, false, state),
+            Some(QuietHoursAction::Drop) => return Ok(false),
+            Some(QuietHoursAction::Silent) => deliver(dest, true, state),
+            Some(QuietHoursAction::Defer) => {
+                let deferred = state.resolving.as_ref().unwrap_or(message).clone();
+                self.deferred.lock().unwrap().push(DeferredMessage::new(dest.get_id().to_owned(), deferred));
+                state.deferred += 1;
+                // The deferred message can't be edited, so updates are sent as new messages.
+                if let Some(progress) = &mut state.progress {
+                    progress.insert(dest.get_id().to_owned(), None);
+                }
+                if !dest.is_root() {
+                    state.sent_to_non_root_dest = true;
+                }
+                return Ok(true);
+            }
+        };
+        let result = match result {
+            // Reported, but otherwise treated as delivered, so the message isn't sent twice.
+            Err(err) if partial::is_partial(err.as_ref()) => {
+                state.errors.push(SendErrorBorrowed::create(err, dest.get_id().to_owned(), message));
+                Ok(())
+            }
+            result => result,
+        };
+        match result {
+            Ok(()) => {
+                if !dest.is_root() {
+                    state.sent_to_non_root_dest = true;
+                }
+                state.successful += 1;
+                state.delivered_to.push(dest.get_id().to_owned());
+                Ok(true)
+            }
+            Err(err) => Err(SendErrorBorrowed::create(err, dest.get_id().to_owned(), message)),
+        }
+    }
+
+    /// Tests each destination individually, or only the destination with the given id.
+    ///
+    /// Destinations are checked with their [`health_check`] if they have one, so that the check
+    /// does not send anything to users. Otherwise, or if `always_send` is true, a clearly marked test message
+    /// is sent to them.
+    ///
+    /// [`health_check`]: crate::destination::MessageDestination::health_check
+    pub fn test_destinations(&self, id: Option<&str>, always_send: bool) -> Vec<DestinationTestResult> {
+        self.destinations.iter()
+            .filter(|dest| id.is_none() || id == Some(dest.get_id()))
+            .map(|dest| {
+                let start = Instant::now();
+                let health_check = if always_send { None } else { dest.health_check() };
+                let (method, result) = match health_check {
+                    Some(result) => (TestMethod::HealthCheck, result),
+                    None => (TestMethod::TestMessage, dest.send(&create_test_message(dest.get_id()))),
+                };
+                DestinationTestResult {
+                    destination_id: dest.get_id().to_owned(),
+                    method,
+                    duration: start.elapsed(),
+                    result,
+                }
+            })
+            .collect()
+    }
+
+    fn get_destination(&self, id: &str) -> Option<&dyn RoutedDestination> {
+        self.destinations.iter()
+            .find(|dest| dest.get_id() == id)
+            .map(|dest| dest.as_ref())
+    }
+
+    /// Takes the messages that have been deferred due to [`QuietHours`] with
+    /// the [`Defer`](QuietHoursAction::Defer) action during calls to [`route`](Self::route).
+    ///
+    /// These should be stored and given to [`send_deferred`](Self::send_deferred) at a later time.
+    /// The router never sends them by itself, so something must call [`send_deferred`](Self::send_deferred)
+    /// after the quiet hours have ended, e.g. a timer.
+    pub fn take_deferred(&self) -> Vec<DeferredMessage> {
+        mem::take(&mut *self.deferred.lock().unwrap())
+    }
+
+    /// Takes the alerts that are currently firing, see [`Alert`](crate::message::alert::Alert).
+    ///
+    /// These should be stored and given to [`add_open_alerts`](Self::add_open_alerts) the next time
+    /// messages are sent, so that the messages resolving them can be grouped with the messages that fired them.
+    pub fn take_open_alerts(&self) -> Vec<OpenAlert> {
+        mem::take(&mut *self.alerts.lock().unwrap())
+    }
 
+    /// Adds alerts that were firing the last time messages were sent, see [`take_open_alerts`](Self::take_open_alerts).
+    pub fn add_open_alerts(&self, alerts: Vec<OpenAlert>) {
+        self.alerts.lock().unwrap().extend(alerts);
+    }
 
-        Err(SendErrors::new(message, reported_errors, successful))
+    #[cfg(feature = "reload")]
+    pub(crate) fn add_deferred(&self, deferred: Vec<DeferredMessage>) {
+        self.deferred.lock().unwrap().extend(deferred);
+    }
+
+    /// Attempts to send messages that were previously deferred, as of the given time.
+    ///
+    /// Messages whose destination is still in its quiet hours are deferred again, and can be retrieved
+    /// with [`take_deferred`](Self::take_deferred). Messages for destinations that no longer exist are discarded.
+    ///
+    /// Any failures to send are reported to [`Root`](MessageRoutingBehaviour::Root) destinations, and returned.
+    pub fn send_deferred(&self, deferred: Vec<DeferredMessage>, unix_timestamp_millis: i64) -> Vec<SendErrorOwned> {
+        let mut errors = vec![];
+        for deferred_message in deferred {
+            let dest = match self.get_destination(&deferred_message.destination_id) {
+                Some(dest) => dest,
+                None => continue,
+            };
+            let message = &deferred_message.message;
+            let result = match dest.get_routing_info().get_quiet_hours_action(message, unix_timestamp_millis) {
+                Some(QuietHoursAction::Defer) => {
+                    self.deferred.lock().unwrap().push(deferred_message);
+                    continue;
+                }
+                Some(QuietHoursAction::Silent) => dest.send_silent(message),
+                _ => dest.send(message),
+            };
+            if let Err(err) = result {
+                let error = SendErrorOwned::create(err, deferred_message.destination_id, deferred_message.message);
+                self.report_error(&error);
+                errors.push(error);
+            }
+        }
+        errors
+    }
+
+    /// Reports the error to all [`Root`](MessageRoutingBehaviour::Root) destinations.
+    fn report_error<E: SendError>(&self, error: &E) -> ErrorReportSummary {
+        self.send_to_root(&error.create_report_message())
+    }
+
+    /// Sends the message to all the [`Root`](MessageRoutingBehaviour::Root) destinations, ignoring their routing.
+    /// This is used to report problems with rnotify itself.
+    pub fn send_to_root(&self, message: &Message) -> ErrorReportSummary {
+        let mut any_report_success = false;
+        let mut report_fails = vec![];
+
+        for root_dest in self.destinations.iter().filter(|dest| dest.is_root()) {
+            match root_dest.send(message) {
+                Ok(_) => {
+                    any_report_success = true;
+                }
+                Err(send_err) => {
+                    let send_err = SendErrorOwned::create(send_err, root_dest.get_id().to_owned(), message.clone());
+                    report_fails.push(send_err);
+                }
+            }
+        }
+        ErrorReportSummary::new(any_report_success, report_fails)
     }
 
 }
 
+/// Sends the message being routed to the destination, as part of its open alert if it has one.
+fn deliver(dest: &dyn RoutedDestination, silent: bool, state: &mut RouteState) -> Result<(), Box<dyn Error>> {
+    let message = state.resolving.as_ref().unwrap_or(state.message);
+    if let Some(progress) = &mut state.progress {
+        let result = dest.send_editable(message, silent);
+        let sent_id = match &result {
+            Ok(sent_id) => sent_id.clone(),
+            Err(err) if partial::is_partial(err.as_ref()) => partial::sent_id(err.as_ref()),
+            Err(_) => return result.map(|_| ()),
+        };
+        progress.insert(dest.get_id().to_owned(), sent_id);
+        return result.map(|_| ());
+    }
+    if message.get_alert().is_none() {
+        return if silent { dest.send_silent(message) } else { dest.send(message) };
+    }
+    let original = state.open_alert.as_ref()
+        .and_then(|open_alert| open_alert.messages.get(dest.get_id()))
+        .map(|id| id.as_str());
+    let result = dest.send_alert(message, silent, original);
+    let sent_id = match &result {
+        Ok(sent_id) => sent_id.clone(),
+        Err(err) => partial::sent_id(err.as_ref()),
+    };
+    if let (Some(open_alert), Some(sent_id)) = (&mut state.open_alert, sent_id) {
+        open_alert.messages.entry(dest.get_id().to_owned()).or_insert(sent_id);
+    }
+    result.map(|_| ())
+}
+
+fn create_test_message(destination_id: &str) -> Message {
+    let text = format!("This is a test message, checking that destination '{}' is working. It can be safely ignored.", destination_id);
+    let mut builder = MessageBuilder::new();
+    builder.level(Level::Info)
+        .title("Rnotify Test Message")
+        .author("rnotify/test")
+        .body(|body| {
+            body.raw(text.clone())
+                .text_block(|block| {
+                    block.append_plain(text);
+                });
+        });
+    builder.build()
+}
+
+/// How a destination was tested, see [`MessageRouter::test_destinations`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestMethod {
+    /// Using the destination's [`health_check`](crate::destination::MessageDestination::health_check)
+    HealthCheck,
+    /// By sending a test message to the destination.
+    TestMessage,
+}
+
+/// The outcome of testing a single destination, see [`MessageRouter::test_destinations`]
+#[derive(Debug)]
+pub struct DestinationTestResult {
+    destination_id: String,
+    method: TestMethod,
+    duration: Duration,
+    result: Result<(), Box<dyn Error>>,
+}
+
+impl DestinationTestResult {
+    pub fn get_destination_id(&self) -> &str {
+        &self.destination_id
+    }
+
+    pub fn get_method(&self) -> TestMethod {
+        self.method
+    }
+
+    /// How long the test took.
+    pub fn get_duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn get_result(&self) -> &Result<(), Box<dyn Error>> {
+        &self.result
+    }
+}
+
+/// Keeps track of the progress of routing a single message.
+struct RouteState<'a> {
+    message: &'a Message,
+    sent_to_non_root_dest: bool,
+    successful: usize,
+    /// The number of destinations the message was deferred to, see [`QuietHoursAction::Defer`]
+    deferred: usize,
+    delivered_to: Vec<String>,
+    errors: Vec<SendErrorBorrowed<'a>>,
+    fallback_paths: Vec<FallbackPath>,
+    /// The alert the message is part of, if it is firing or resolves an alert that was firing.
+    open_alert: Option<OpenAlert>,
+    /// The message, with when the alert started firing, if it resolves an open alert.
+    resolving: Option<Message>,
+    /// The id of the message sent to each destination, if it is the first message of a [`ProgressHandle`].
+    progress: Option<BTreeMap<String, Option<String>>>,
+}
+
+impl<'a> RouteState<'a> {
+    fn new(message: &'a Message) -> Self {
+        Self {
+            message,
+            sent_to_non_root_dest: false,
+            successful: 0,
+            deferred: 0,
+            delivered_to: vec![],
+            errors: vec![],
+            fallback_paths: vec![],
+            open_alert: None,
+            resolving: None,
+            progress: None,
+        }
+    }
+
+    fn was_delivered_to(&self, id: &str) -> bool {
+        self.delivered_to.iter().any(|delivered| delivered == id)
+    }
+}
+
 impl Default for MessageRouter {
     fn default() -> Self {
         Self::empty()
@@ -136,31 +567,68 @@ impl Default for MessageRouter {
 /// # [`MessageCondition`]s #
 /// If none specified, all messages are allowed.
 /// Otherwise it acts like a whitelist.
+///
+/// Additionally, [`QuietHours`] may hold back messages at certain times.
+///
+/// # Fallback #
+/// If sending to this destination fails, the message is sent to the destinations
+/// with the given ids in order, until one of them succeeds, regardless of their own [`RoutingInfo`].
+/// If one succeeds, the failure is not reported to [`Root`](MessageRoutingBehaviour::Root) destinations.
+/// ```toml
+/// fallback = ["telegram_main", "mail_ops"]
+/// ```
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct RoutingInfo {
     // Whether errors with sending notifications will be reported to this destination.
     #[serde(default)]
     routing_type: MessageRoutingBehaviour,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     whitelist: Vec<MessageCondition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    quiet_hours: Vec<QuietHours>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fallback: Vec<String>,
 }
 
 impl RoutingInfo {
     pub fn of(routing_type: MessageRoutingBehaviour) -> Self {
         Self {
             routing_type,
-            whitelist: vec![]
+            whitelist: vec![],
+            quiet_hours: vec![],
+            fallback: vec![],
         }
     }
 
+    pub fn with_quiet_hours(mut self, quiet_hours: QuietHours) -> Self {
+        self.quiet_hours.push(quiet_hours);
+        self
+    }
+
     pub fn root() -> Self {
         Self::of(MessageRoutingBehaviour::Root)
     }
 
+    pub fn with_fallback(mut self, fallback: Vec<String>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
     pub fn get_routing_behaviour(&self) -> &MessageRoutingBehaviour {
         &self.routing_type
     }
 
+    /// Checks the [`MessageCondition`]s used are valid, returning a description of each problem.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self.whitelist.iter()
+            .filter_map(|condition| condition.validate().err())
+            .map(|e| format!("In whitelist: {}", e))
+            .collect();
+        problems.extend(self.quiet_hours.iter().flat_map(|quiet_hours| quiet_hours.validate()));
+        problems
+    }
+
     pub fn applies_to(&self, message: &Message) -> bool {
         if self.whitelist.is_empty() {
             return true;
@@ -168,4 +636,440 @@ impl RoutingInfo {
         self.whitelist.iter()
             .any(|condition| condition.matches(message))
     }
-}
\ No newline at end of file
+
+    /// The ids of the destinations that messages should be sent to in order if sending
+    /// to this destination fails.
+    pub fn get_fallback(&self) -> &Vec<String> {
+        &self.fallback
+    }
+
+    /// Gets the action of the first [`QuietHours`] that apply to the message at the given time, if any.
+    pub fn get_quiet_hours_action(&self, message: &Message, unix_timestamp_millis: i64) -> Option<&QuietHoursAction> {
+        self.quiet_hours.iter()
+            .find(|quiet_hours| quiet_hours.applies_to(message, unix_timestamp_millis))
+            .map(|quiet_hours| quiet_hours.get_action())
+    }
+}
+
+/// A message that was held back from a destination due to its [`QuietHours`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeferredMessage {
+    destination_id: String,
+    message: Message,
+}
+
+impl DeferredMessage {
+    pub fn new(destination_id: String, message: Message) -> Self {
+        Self {
+            destination_id,
+            message,
+        }
+    }
+
+    pub fn get_destination_id(&self) -> &str {
+        &self.destination_id
+    }
+
+    pub fn get_message(&self) -> &Message {
+        &self.message
+    }
+}
+
+/// An [`Alert`](crate::message::alert::Alert) that is firing, see [`MessageRouter::take_open_alerts`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OpenAlert {
+    key: String,
+    fired_at: i64,
+    /// The id of the first message about the alert sent to each destination, by destination id.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    messages: BTreeMap<String, String>,
+}
+
+impl OpenAlert {
+    pub fn new(key: String, fired_at: i64) -> Self {
+        Self {
+            key,
+            fired_at,
+            messages: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    /// The unix timestamp in milliseconds of when the alert started firing.
+    pub fn get_fired_at(&self) -> i64 {
+        self.fired_at
+    }
+
+    /// The id of the first message about the alert sent to the given destination, if it returned one.
+    pub fn get_message_id(&self, destination_id: &str) -> Option<&str> {
+        self.messages.get(destination_id).map(|id| id.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use chrono::NaiveTime;
+    use crate::destination::kinds::rust_receiver::RustReceiverDestination;
+    use crate::destination::routed_destination::RoutedDestinationBase;
+    use crate::destination::schedule::Schedule;
+    use crate::message::alert::Alert;
+    use crate::message::builder::MessageBuilder;
+    use crate::send_error::partial::PartialSendError;
+    use crate::util::timezone::Timezone;
+    use super::*;
+
+    // 2022-07-01T23:30:00Z
+    const NIGHT: i64 = 1656718200000;
+    // 2022-07-02T12:00:00Z
+    const NEXT_DAY: i64 = 1656763200000;
+
+    fn night_quiet_hours(action: QuietHoursAction) -> QuietHours {
+        let schedule = Schedule::daily(NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+                                       NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+                                       Timezone::Utc);
+        QuietHours::new(schedule, action, vec![])
+    }
+
+    #[test]
+    fn test_quiet_hours_drop() {
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        let routing_info = RoutingInfo::of(MessageRoutingBehaviour::Additive)
+            .with_quiet_hours(night_quiet_hours(QuietHoursAction::Drop));
+        router.add_destination(Box::new(RoutedDestinationBase::create("test".to_owned(), RustReceiverDestination::create(send), routing_info)));
+
+        let mut builder = MessageBuilder::new();
+        builder.timestamp(NIGHT);
+        router.route(&builder.build_clone()).expect("Should not fail");
+        assert!(recv.try_recv().is_err(), "Should have been dropped during quiet hours");
+
+        builder.timestamp(NEXT_DAY);
+        let message = builder.build();
+        router.route(&message).expect("Should not fail");
+        assert_eq!(recv.try_recv(), Ok(message));
+    }
+
+    #[test]
+    fn test_quiet_hours_defer() {
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        let routing_info = RoutingInfo::of(MessageRoutingBehaviour::Additive)
+            .with_quiet_hours(night_quiet_hours(QuietHoursAction::Defer));
+        router.add_destination(Box::new(RoutedDestinationBase::create("test".to_owned(), RustReceiverDestination::create(send), routing_info)));
+
+        let mut builder = MessageBuilder::new();
+        builder.timestamp(NIGHT);
+        let message = builder.build();
+        router.route(&message).expect("Should not fail");
+        assert!(recv.try_recv().is_err(), "Should have been deferred");
+
+        let deferred = router.take_deferred();
+        assert_eq!(deferred, vec![DeferredMessage::new("test".to_owned(), message.clone())]);
+
+        assert!(router.send_deferred(deferred, NIGHT).is_empty());
+        assert!(recv.try_recv().is_err(), "Should still be deferred");
+
+        let deferred = router.take_deferred();
+        assert_eq!(deferred.len(), 1, "Should have been deferred again");
+        assert!(router.send_deferred(deferred, NEXT_DAY).is_empty());
+        assert_eq!(recv.try_recv(), Ok(message));
+        assert!(router.take_deferred().is_empty());
+    }
+
+    #[derive(Debug)]
+    struct FailingDestination;
+
+    impl crate::destination::MessageDestination for FailingDestination {
+        fn send(&self, _: &Message) -> Result<(), Box<dyn std::error::Error>> {
+            Err("Always fails".into())
+        }
+    }
+
+    #[test]
+    fn test_fallback() {
+        let (root_send, root_recv) = mpsc::channel();
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(RoutedDestinationBase::create("root".to_owned(), RustReceiverDestination::create(root_send), RoutingInfo::root())));
+        let routing_info = RoutingInfo::of(MessageRoutingBehaviour::Additive)
+            .with_fallback(vec!["missing".to_owned(), "broken_backup".to_owned(), "backup".to_owned()]);
+        router.add_destination(Box::new(RoutedDestinationBase::create("broken".to_owned(), FailingDestination, routing_info)));
+        router.add_destination(Box::new(RoutedDestinationBase::create("broken_backup".to_owned(), FailingDestination, RoutingInfo::of(MessageRoutingBehaviour::Drain))));
+        router.add_destination(Box::new(RoutedDestinationBase::create("backup".to_owned(), RustReceiverDestination::create(send), RoutingInfo::of(MessageRoutingBehaviour::Drain))));
+
+        let message = MessageBuilder::new().build();
+        let sent = router.route(&message).expect("Should have recovered by sending to backup");
+        assert_eq!(sent, 2);
+
+        assert_eq!(recv.try_recv(), Ok(message.clone()), "Should have fallen back to backup");
+        assert!(recv.try_recv().is_err(), "Backup should not receive the message twice as a drain");
+
+        // Root only gets the message, since the failures were recovered from.
+        assert_eq!(root_recv.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_fallback_not_delivered() {
+        let (root_send, root_recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(RoutedDestinationBase::create("root".to_owned(), RustReceiverDestination::create(root_send), RoutingInfo::root())));
+        let routing_info = RoutingInfo::of(MessageRoutingBehaviour::Additive)
+            .with_fallback(vec!["missing".to_owned(), "broken_backup".to_owned()]);
+        router.add_destination(Box::new(RoutedDestinationBase::create("broken".to_owned(), FailingDestination, routing_info)));
+        router.add_destination(Box::new(RoutedDestinationBase::create("broken_backup".to_owned(), FailingDestination, RoutingInfo::of(MessageRoutingBehaviour::Drain))));
+
+        let message = MessageBuilder::new().build();
+        let errors = router.route(&message).expect_err("Should have failed to send anywhere");
+
+        let failed: Vec<_> = errors.get_errors().iter().map(|error| error.get_failed_destination_id()).collect();
+        // broken_backup is also tried as a drain, since nothing else received the message.
+        assert_eq!(failed, vec!["broken", "missing", "broken_backup", "broken_backup"]);
+        assert_eq!(errors.get_fallback_paths().len(), 1);
+        let path = &errors.get_fallback_paths()[0];
+        assert_eq!(path.get_path(), &vec!["broken".to_owned(), "missing".to_owned(), "broken_backup".to_owned()]);
+        assert_eq!(path.get_delivered_to(), None);
+        assert_eq!(path.get_failures().len(), 3);
+
+        // Root gets the message and a report for each error.
+        assert_eq!(root_recv.try_iter().count(), 5);
+    }
+
+    #[derive(Debug)]
+    struct PartialDestination;
+
+    impl crate::destination::MessageDestination for PartialDestination {
+        fn send(&self, _: &Message) -> Result<(), Box<dyn std::error::Error>> {
+            Err(Box::new(PartialSendError::new(None, "Failed to upload attachment".into())))
+        }
+    }
+
+    #[test]
+    fn test_partial_send() {
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        let routing_info = RoutingInfo::of(MessageRoutingBehaviour::Additive).with_fallback(vec!["backup".to_owned()]);
+        router.add_destination(Box::new(RoutedDestinationBase::create("partial".to_owned(), PartialDestination, routing_info)));
+        router.add_destination(Box::new(RoutedDestinationBase::create("backup".to_owned(), RustReceiverDestination::create(send), RoutingInfo::of(MessageRoutingBehaviour::Drain))));
+
+        let message = MessageBuilder::new().build();
+        let errors = router.route(&message).expect_err("Should report the partial failure");
+        assert_eq!(errors.get_errors().len(), 1);
+        assert_eq!(errors.get_successfully_sent(), 1, "Should count as delivered");
+        assert!(errors.get_fallback_paths().is_empty());
+        assert!(recv.try_recv().is_err(), "Should not fall back or drain, since the message was delivered");
+    }
+
+    #[test]
+    fn test_route_to_failures() {
+        let (root_send, root_recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(RoutedDestinationBase::create("root".to_owned(), RustReceiverDestination::create(root_send), RoutingInfo::root())));
+        router.add_destination(Box::new(RoutedDestinationBase::create("broken".to_owned(), FailingDestination, RoutingInfo::of(MessageRoutingBehaviour::Drain))));
+
+        let message = MessageBuilder::new().build();
+        let errors = router.route_to(&message, &["broken".to_owned(), "missing".to_owned()]).expect_err("Should fail");
+        let failed: Vec<&str> = errors.get_errors().iter().map(|e| e.get_failed_destination_id()).collect();
+        assert_eq!(failed, vec!["broken", "missing"]);
+        assert!(errors.get_errors().iter().all(|e| e.get_report_summary().was_reported()));
+
+        // Root isn't targeted, so only gets the reports of the failures.
+        let reports: Vec<Message> = root_recv.try_iter().collect();
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|report| report.get_level() == &Level::SelfError));
+    }
+
+    /// Records the original message id it is given for each alert message, and returns the message's title as its id.
+    #[derive(Debug)]
+    struct AlertDestination(mpsc::Sender<(Message, Option<String>)>);
+
+    impl crate::destination::MessageDestination for AlertDestination {
+        fn send(&self, _: &Message) -> Result<(), Box<dyn std::error::Error>> {
+            Ok(())
+        }
+
+        fn send_alert(&self, message: &Message, _: bool, original: Option<&str>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+            self.0.send((message.clone(), original.map(|id| id.to_owned()))).unwrap();
+            Ok(message.get_title().clone())
+        }
+    }
+
+    #[test]
+    fn test_alerts() {
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(RoutedDestinationBase::create("test".to_owned(), AlertDestination(send), RoutingInfo::root())));
+
+        let mut builder = MessageBuilder::new();
+        builder.title("first").alert(Alert::firing("disk_full")).timestamp(NIGHT);
+        router.route(&builder.build_clone()).expect("Should not fail");
+        let (_, original) = recv.try_recv().unwrap();
+        assert_eq!(original, None);
+
+        builder.title("second").timestamp(NIGHT + 60 * 1000);
+        router.route(&builder.build_clone()).expect("Should not fail");
+        let (_, original) = recv.try_recv().unwrap();
+        assert_eq!(original.as_deref(), Some("first"), "Should be grouped with the first message");
+
+        // Open alerts are kept between runs.
+        let alerts = router.take_open_alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].get_fired_at(), NIGHT);
+        router.add_open_alerts(alerts);
+
+        builder.title("resolved").alert(Alert::resolved("disk_full")).timestamp(NIGHT + 42 * 60 * 1000);
+        router.route(&builder.build_clone()).expect("Should not fail");
+        let (message, original) = recv.try_recv().unwrap();
+        assert_eq!(original.as_deref(), Some("first"));
+        let alert = message.get_alert().as_ref().unwrap();
+        assert_eq!(alert.describe(message.get_unix_timestamp_millis()), "Resolved after 42 minutes");
+        assert!(router.take_open_alerts().is_empty(), "Alert should no longer be open");
+
+        // Resolving an alert that isn't open still sends the message.
+        router.route(&builder.build()).expect("Should not fail");
+        let (message, original) = recv.try_recv().unwrap();
+        assert_eq!(original, None);
+        assert_eq!(message.get_alert().as_ref().unwrap().get_fired_at(), None);
+    }
+
+    #[test]
+    fn test_alert_not_resolved() {
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(RoutedDestinationBase::create("test".to_owned(), AlertDestination(send), RoutingInfo::root())));
+        let mut builder = MessageBuilder::new();
+        builder.title("first").alert(Alert::firing("disk_full")).timestamp(NIGHT);
+        router.route(&builder.build_clone()).expect("Should not fail");
+        assert!(recv.try_recv().is_ok());
+
+        let mut broken_router = MessageRouter::empty();
+        broken_router.add_destination(Box::new(RoutedDestinationBase::create("broken".to_owned(), FailingDestination, RoutingInfo::root())));
+        broken_router.add_open_alerts(router.take_open_alerts());
+        builder.title("resolved").alert(Alert::resolved("disk_full")).timestamp(NIGHT + 60 * 1000);
+        broken_router.route(&builder.build()).expect_err("Should fail");
+
+        let alerts = broken_router.take_open_alerts();
+        assert_eq!(alerts.len(), 1, "Should still be open, since nothing received the message resolving it");
+        assert_eq!(alerts[0].get_fired_at(), NIGHT);
+        assert_eq!(alerts[0].get_message_id("test"), Some("first"));
+    }
+
+    /// Records each message it sends or edits, returning the message's title as its id.
+    #[derive(Debug)]
+    struct EditableDestination(mpsc::Sender<(Message, Option<String>)>);
+
+    impl crate::destination::MessageDestination for EditableDestination {
+        fn send(&self, _: &Message) -> Result<(), Box<dyn std::error::Error>> {
+            Ok(())
+        }
+
+        fn send_editable(&self, message: &Message, _: bool) -> Result<Option<String>, Box<dyn std::error::Error>> {
+            self.0.send((message.clone(), None)).unwrap();
+            Ok(message.get_title().clone())
+        }
+
+        fn edit(&self, id: &str, message: &Message) -> Result<(), Box<dyn std::error::Error>> {
+            self.0.send((message.clone(), Some(id.to_owned()))).unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let (send, recv) = mpsc::channel();
+        let (other_send, other_recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(RoutedDestinationBase::create("editable".to_owned(), EditableDestination(send), RoutingInfo::root())));
+        router.add_destination(Box::new(RoutedDestinationBase::create("receiver".to_owned(), RustReceiverDestination::create(other_send), RoutingInfo::root())));
+
+        let mut builder = MessageBuilder::new();
+        builder.title("Backup 30%");
+        let mut progress = router.progress();
+        progress.update(&builder.build_clone()).expect("Should not fail");
+        assert_eq!(recv.try_recv().unwrap().1, None, "Should send the first message");
+        assert_eq!(progress.get_progress().get_message_id("editable"), Some("Backup 30%"));
+        assert_eq!(progress.get_progress().get_message_id("receiver"), None);
+
+        // Resuming from a stored progress message, e.g. in a later run.
+        let mut progress = router.resume_progress(progress.into_progress());
+        builder.title("Backup done");
+        let message = builder.build();
+        assert_eq!(progress.update(&message).expect("Should not fail"), 2);
+        assert_eq!(recv.try_recv(), Ok((message.clone(), Some("Backup 30%".to_owned()))), "Should edit the first message");
+        assert_eq!(other_recv.try_iter().count(), 2, "Should send each update as a new message when can't edit");
+    }
+
+    #[test]
+    fn test_progress_quiet_hours_and_failures() {
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        let routing_info = RoutingInfo::of(MessageRoutingBehaviour::Additive)
+            .with_quiet_hours(night_quiet_hours(QuietHoursAction::Defer));
+        router.add_destination(Box::new(RoutedDestinationBase::create("quiet".to_owned(), EditableDestination(send), routing_info)));
+        router.add_destination(Box::new(RoutedDestinationBase::create("broken".to_owned(), FailingDestination, RoutingInfo::of(MessageRoutingBehaviour::Additive))));
+
+        let mut builder = MessageBuilder::new();
+        builder.title("Backup 30%").timestamp(NIGHT);
+        let mut progress = router.progress();
+        assert!(progress.update(&builder.build_clone()).is_err(), "Should report that 'broken' failed");
+        assert!(recv.try_recv().is_err(), "Should have been deferred");
+        assert!(progress.get_progress().is_sent(), "Should remember the deferred and failed destinations");
+
+        builder.title("Backup done");
+        let message = builder.build();
+        let errors = progress.update(&message).expect_err("Should report that 'broken' failed again");
+        assert_eq!(errors.get_errors()[0].get_failed_destination_id(), "broken");
+        assert!(recv.try_recv().is_err(), "Should be deferred rather than sent during quiet hours");
+        assert_eq!(router.take_deferred().len(), 2);
+    }
+
+    #[test]
+    fn test_progress_unknown_fallback() {
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(RoutedDestinationBase::create("editable".to_owned(), EditableDestination(send), RoutingInfo::root())));
+        let routing_info = RoutingInfo::of(MessageRoutingBehaviour::Additive).with_fallback(vec!["missing".to_owned()]);
+        router.add_destination(Box::new(RoutedDestinationBase::create("broken".to_owned(), FailingDestination, routing_info)));
+
+        let mut builder = MessageBuilder::new();
+        builder.title("Backup 30%");
+        let mut progress = router.progress();
+        let message = builder.build_clone();
+        let errors = progress.update(&message).expect_err("Should report that 'broken' and 'missing' failed");
+        let failed: Vec<&str> = errors.get_errors().iter().map(|e| e.get_failed_destination_id()).collect();
+        assert_eq!(failed, vec!["broken", "missing"]);
+        assert!(!progress.get_progress().get_messages().contains_key("missing"), "Should not remember a destination that doesn't exist");
+        assert!(recv.try_recv().is_ok());
+
+        builder.title("Backup done");
+        let message = builder.build();
+        let errors = progress.update(&message).expect_err("Should report that 'broken' failed again");
+        let failed: Vec<&str> = errors.get_errors().iter().map(|e| e.get_failed_destination_id()).collect();
+        assert_eq!(failed, vec!["broken"]);
+        assert_eq!(recv.try_recv().unwrap().1.as_deref(), Some("Backup 30%"), "Should edit the first message");
+    }
+
+    #[test]
+    fn test_test_destinations() {
+        let (send, recv) = mpsc::channel();
+        let mut router = MessageRouter::empty();
+        router.add_destination(Box::new(RoutedDestinationBase::create("working".to_owned(), RustReceiverDestination::create(send), RoutingInfo::root())));
+        router.add_destination(Box::new(RoutedDestinationBase::create("broken".to_owned(), FailingDestination, RoutingInfo::root())));
+
+        let results = router.test_destinations(None, false);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].get_method(), TestMethod::TestMessage, "Rust receiver has no health check");
+        assert!(results[0].get_result().is_ok());
+        assert!(results[1].get_result().is_err());
+
+        let test_message = recv.try_recv().expect("Should have received a test message");
+        assert_eq!(test_message.get_title().as_deref(), Some("Rnotify Test Message"));
+
+        let results = router.test_destinations(Some("broken"), false);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].get_destination_id(), "broken");
+    }
+}
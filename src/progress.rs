@@ -0,0 +1,90 @@
+//! Messages that are sent once and then updated in place, e.g. to show the progress of a long running job.
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::message::Message;
+use crate::message_router::MessageRouter;
+use crate::send_error::SendErrors;
+
+/// Sends a message, and then updates it, e.g. `Backup 30%`, `Backup 60%`, `Backup done`.
+///
+/// The first [`update`](Self::update) is routed like any other message. Later updates
+/// edit the messages sent to the same destinations, if they are able to, or are sent as new messages if not.
+/// ```rust
+/// use std::sync::mpsc;
+/// use rnotifylib::destination::kinds::rust_receiver::RustReceiverDestination;
+/// use rnotifylib::destination::routed_destination::RoutedDestinationBase;
+/// use rnotifylib::message::builder::MessageBuilder;
+/// use rnotifylib::message_router::{MessageRouter, RoutingInfo};
+///
+/// let (send, recv) = mpsc::channel();
+/// let mut router = MessageRouter::empty();
+/// router.add_destination(Box::new(RoutedDestinationBase::create("receiver".to_owned(), RustReceiverDestination::create(send), RoutingInfo::root())));
+///
+/// let mut progress = router.progress();
+/// let mut builder = MessageBuilder::new();
+/// for percent in [30, 60, 100] {
+///     builder.title(format!("Backup {}%", percent));
+///     progress.update(&builder.build_clone()).expect("Should send");
+/// }
+/// // The rust receiver can't edit messages, so receives each update.
+/// assert_eq!(recv.try_iter().count(), 3);
+/// ```
+pub struct ProgressHandle<'r> {
+    router: &'r MessageRouter,
+    progress: ProgressMessage,
+}
+
+impl<'r> ProgressHandle<'r> {
+    pub(crate) fn new(router: &'r MessageRouter, progress: ProgressMessage) -> Self {
+        Self {
+            router,
+            progress,
+        }
+    }
+
+    /// Sends the message the first time, and replaces it after that.
+    pub fn update<'a>(&mut self, message: &'a Message) -> Result<usize, SendErrors<'a>> {
+        self.router.update_progress(message, &mut self.progress)
+    }
+
+    pub fn get_progress(&self) -> &ProgressMessage {
+        &self.progress
+    }
+
+    /// Gets the messages that were sent, so that they can be stored and updated later
+    /// with [`MessageRouter::resume_progress`].
+    pub fn into_progress(self) -> ProgressMessage {
+        self.progress
+    }
+}
+
+/// The messages sent by a [`ProgressHandle`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ProgressMessage {
+    /// The id of the message sent to each destination by destination id,
+    /// or `None` if the destination can't edit messages.
+    #[serde(default)]
+    messages: BTreeMap<String, Option<String>>,
+}
+
+impl ProgressMessage {
+    pub(crate) fn new(messages: BTreeMap<String, Option<String>>) -> Self {
+        Self {
+            messages,
+        }
+    }
+
+    pub(crate) fn get_messages(&self) -> &BTreeMap<String, Option<String>> {
+        &self.messages
+    }
+
+    /// Whether the message has been sent to any destinations yet.
+    pub fn is_sent(&self) -> bool {
+        !self.messages.is_empty()
+    }
+
+    /// The id of the message sent to the destination, if it was sent there and the destination can edit messages.
+    pub fn get_message_id(&self, destination_id: &str) -> Option<&str> {
+        self.messages.get(destination_id)?.as_deref()
+    }
+}
@@ -0,0 +1,376 @@
+//! A [`MessageRouter`] that reloads its config when the config files change.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::config::{self, Config};
+use crate::config::error::ConfigError;
+use crate::config::validation::Severity;
+use crate::message::{Level, Message};
+use crate::message::builder::MessageBuilder;
+use crate::message::component::Component;
+use crate::message_router::{DeferredMessage, MessageRouter, OpenAlert};
+use crate::send_error::owned::SendErrorOwned;
+use crate::send_error::SendErrors;
+
+/// How long the config files must go without changing before they are reloaded,
+/// since saving a file often causes several events.
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// A [`MessageRouter`] created from config files, which is recreated whenever they change.
+///
+/// Each path is either a config file or a drop-in directory such as `/etc/rnotify.d`, whose `*.toml` files
+/// are loaded in alphabetical order (see [`drop_in_paths`](config::drop_in_paths)), so files added to it later are picked up.
+///
+/// The config is reloaded in the background shortly after the files change. If a changed config cannot be loaded,
+/// or has errors when [validated](Config::validate), then the previous config is kept and the problem is
+/// sent to its [`Root`](crate::destination::routed_destination::MessageRoutingBehaviour::Root)
+/// destinations as a [`SelfError`](Level::SelfError) message.
+///
+/// ```rust,no_run
+/// use std::path::PathBuf;
+/// use rnotifylib::message::builder::MessageBuilder;
+/// use rnotifylib::reloading_router::ReloadingMessageRouter;
+///
+/// let paths = vec![PathBuf::from("/etc/rnotify.toml"), PathBuf::from("/etc/rnotify.d")];
+/// let router = ReloadingMessageRouter::new(paths, None)
+///     .expect("Config should be valid");
+///
+/// loop {
+///     let mut builder = MessageBuilder::new();
+///     builder.title("Still running");
+///     router.route(&builder.build()).expect("Should send");
+///     std::thread::sleep(std::time::Duration::from_secs(60 * 60));
+/// }
+/// ```
+pub struct ReloadingMessageRouter {
+    shared: Arc<Shared>,
+    // Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+    /// Sends `None` to stop the thread reloading the config.
+    stop: Sender<Option<notify::Result<Event>>>,
+    reload_thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    paths: Vec<PathBuf>,
+    profile: Option<String>,
+    /// The files and drop-in directories in `paths`, as they appear in events.
+    watched: Vec<WatchedPath>,
+    /// Routing holds the read lock until it has finished, so that the messages deferred and alerts
+    /// opened by the old router are always moved to the new one.
+    router: RwLock<MessageRouter>,
+}
+
+enum WatchedPath {
+    File(PathBuf),
+    DropInDir(PathBuf),
+}
+
+impl ReloadingMessageRouter {
+    /// Loads the config from the given files and drop-in directories (see [`load_config_files`](config::load_config_files)),
+    /// applying the given profile if there is one, and starts watching them for changes.
+    ///
+    /// Returns an error if the config cannot be loaded or is invalid, or the files cannot be watched.
+    pub fn new(paths: Vec<PathBuf>, profile: Option<String>) -> Result<Self, ConfigError> {
+        let config = load(&paths, profile.as_deref())?;
+
+        let (sender, events) = mpsc::channel();
+        let stop = sender.clone();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(Some(event));
+        })
+            .map_err(|e| ConfigError::io(None, to_io_error(e)))?;
+        let mut watched = vec![];
+        let mut dirs = vec![];
+        for path in &paths {
+            // Watch the directories rather than the files themselves, since editors often replace the
+            // file when saving it, which would stop the file from being watched.
+            // Events give canonical paths, even if the config paths are relative.
+            let dir = if path.is_dir() { path.as_path() } else { watch_dir(path) };
+            let dir = fs::canonicalize(dir).map_err(|e| ConfigError::io(Some(dir.to_owned()), e))?;
+            if path.is_dir() {
+                watched.push(WatchedPath::DropInDir(dir.clone()));
+            } else if let Some(file_name) = path.file_name() {
+                watched.push(WatchedPath::File(dir.join(file_name)));
+            }
+            dirs.push(dir);
+        }
+        dirs.sort();
+        dirs.dedup();
+        for dir in &dirs {
+            watcher.watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| ConfigError::io(Some(dir.to_owned()), to_io_error(e)))?;
+        }
+
+        let shared = Arc::new(Shared {
+            paths,
+            profile,
+            watched,
+            router: RwLock::new(MessageRouter::from_config(config)),
+        });
+        let thread_shared = shared.clone();
+        let reload_thread = thread::Builder::new()
+            .name("rnotify-reload".to_owned())
+            .spawn(move || thread_shared.reload_on_change(events))
+            .map_err(|e| ConfigError::io(None, e))?;
+
+        Ok(Self {
+            shared,
+            _watcher: watcher,
+            stop,
+            reload_thread: Some(reload_thread),
+        })
+    }
+
+    /// Reloads the config from the config files now, rather than waiting for them to change.
+    ///
+    /// If the new config cannot be loaded or is invalid, the current config is kept, and the
+    /// error is sent to its root destinations as well as being returned.
+    pub fn reload(&self) -> Result<(), ConfigError> {
+        self.shared.reload()
+    }
+
+    /// Routes the message using the current config, see [`MessageRouter::route`]
+    pub fn route<'a>(&self, message: &'a Message) -> Result<usize, SendErrors<'a>> {
+        self.shared.router().route(message)
+    }
+
+    /// Sends the message to only the given destinations, see [`MessageRouter::route_to`]
+    pub fn route_to<'a>(&self, message: &'a Message, destination_ids: &[String]) -> Result<usize, SendErrors<'a>> {
+        self.shared.router().route_to(message, destination_ids)
+    }
+
+    /// See [`MessageRouter::take_deferred`]
+    pub fn take_deferred(&self) -> Vec<DeferredMessage> {
+        self.shared.router().take_deferred()
+    }
+
+    /// See [`MessageRouter::send_deferred`]
+    pub fn send_deferred(&self, deferred: Vec<DeferredMessage>, unix_timestamp_millis: i64) -> Vec<SendErrorOwned> {
+        self.shared.router().send_deferred(deferred, unix_timestamp_millis)
+    }
+
+    /// See [`MessageRouter::take_open_alerts`]
+    pub fn take_open_alerts(&self) -> Vec<OpenAlert> {
+        self.shared.router().take_open_alerts()
+    }
+
+    /// See [`MessageRouter::add_open_alerts`]
+    pub fn add_open_alerts(&self, alerts: Vec<OpenAlert>) {
+        self.shared.router().add_open_alerts(alerts)
+    }
+
+    pub fn get_paths(&self) -> &Vec<PathBuf> {
+        &self.shared.paths
+    }
+}
+
+impl Drop for ReloadingMessageRouter {
+    fn drop(&mut self) {
+        // Wait for the thread, so that nothing is reloaded (or reported) once the router has been dropped.
+        let _ = self.stop.send(None);
+        if let Some(thread) = self.reload_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    fn router(&self) -> RwLockReadGuard<'_, MessageRouter> {
+        // Routing that panicked doesn't leave the router half changed, so it can still be used.
+        self.router.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn reload(&self) -> Result<(), ConfigError> {
+        match load(&self.paths, self.profile.as_deref()) {
+            Ok(config) => {
+                let new_router = MessageRouter::from_config(config);
+                let mut router = self.router.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+                new_router.add_deferred(router.take_deferred());
+                new_router.add_open_alerts(router.take_open_alerts());
+                *router = new_router;
+                Ok(())
+            }
+            Err(err) => {
+                self.router().send_to_root(&create_reload_error_message(&err));
+                Err(err)
+            }
+        }
+    }
+
+    /// Reloads the config once the config files have stopped changing, until it receives `None`.
+    fn reload_on_change(&self, events: Receiver<Option<notify::Result<Event>>>) {
+        while let Ok(Some(event)) = events.recv() {
+            if !self.is_change(&event) {
+                continue;
+            }
+            // Other files in the same directories may keep changing, so only wait for the config files to settle.
+            let mut last_change = Instant::now();
+            while let Some(wait) = SETTLE_TIME.checked_sub(last_change.elapsed()) {
+                match events.recv_timeout(wait) {
+                    Ok(Some(event)) if self.is_change(&event) => last_change = Instant::now(),
+                    Ok(Some(_)) => {}
+                    Ok(None) | Err(RecvTimeoutError::Disconnected) => return,
+                    Err(RecvTimeoutError::Timeout) => break,
+                }
+            }
+            // Problems reloading are reported to the root destinations, so there's nothing more to do with them.
+            let _ = self.reload();
+        }
+    }
+
+    fn is_change(&self, event: &notify::Result<Event>) -> bool {
+        // Errors watching mean we might have missed changes.
+        event.as_ref().map_or(true, |event| self.is_config_event(event))
+    }
+
+    fn is_config_event(&self, event: &Event) -> bool {
+        !event.kind.is_access() && event.paths.iter().any(|changed| {
+            self.watched.iter().any(|watched| match watched {
+                WatchedPath::File(path) => changed == path,
+                WatchedPath::DropInDir(dir) => changed.parent() == Some(dir) && config::is_drop_in(changed),
+            })
+        })
+    }
+}
+
+fn load(paths: &[PathBuf], profile: Option<&str>) -> Result<Config, ConfigError> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            files.extend(config::drop_in_paths(path)?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    let mut config = config::load_config_files(&files)?;
+    if let Some(profile) = profile {
+        config.apply_profile(profile).map_err(ConfigError::Profile)?;
+    }
+    let errors: Vec<_> = config.validate().into_iter()
+        .filter(|problem| problem.get_severity() == Severity::Error)
+        .collect();
+    if !errors.is_empty() {
+        return Err(ConfigError::Invalid(errors));
+    }
+    Ok(config)
+}
+
+fn watch_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn to_io_error(error: notify::Error) -> std::io::Error {
+    match error.kind {
+        notify::ErrorKind::Io(e) => e,
+        _ => std::io::Error::other(error.to_string()),
+    }
+}
+
+fn create_reload_error_message(error: &ConfigError) -> Message {
+    let text = format!("Rnotify failed to reload its config, so is still using the previous config. {}", error);
+    let mut builder = MessageBuilder::new();
+    builder.level(Level::SelfError)
+        .title("Failed to reload config")
+        .component(Component::from("rnotify/config"))
+        .author("rnotify")
+        .body(|body| {
+            body.raw(text);
+        });
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    fn config(dir: &Path, destination_ids: &[&str]) -> String {
+        destination_ids.iter()
+            .map(|id| format!("[[destinations]]\nrouting_type = \"Root\"\ntype = \"File\"\nid = \"{}\"\npath = \"{}\"\n\n",
+                              id, dir.join("log").display()))
+            .collect()
+    }
+
+    /// Routes a message until `done` is true of the number of destinations it was sent to, or 10 seconds have passed.
+    fn route_until(router: &ReloadingMessageRouter, done: impl Fn(usize) -> bool) -> usize {
+        let mut builder = MessageBuilder::new();
+        builder.title("Hello");
+        let start = Instant::now();
+        loop {
+            let sent = router.route(&builder.build_clone()).expect("Should send");
+            if done(sent) || start.elapsed() > Duration::from_secs(10) {
+                return sent;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn wait_for_log(path: &Path, text: &str) -> String {
+        let start = Instant::now();
+        loop {
+            let log = fs::read_to_string(path).unwrap_or_default();
+            if log.contains(text) || start.elapsed() > Duration::from_secs(10) {
+                return log;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ReloadingMessageRouter>();
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("rnotify_test_reload_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rnotify.toml");
+        fs::write(&path, config(&dir, &["first"])).unwrap();
+
+        let router = ReloadingMessageRouter::new(vec![path.clone()], None).expect("Config should be valid");
+        assert_eq!(route_until(&router, |_| true), 1);
+
+        fs::write(&path, config(&dir, &["first", "second"])).unwrap();
+        assert_eq!(route_until(&router, |sent| sent == 2), 2, "Should notice the config changed");
+
+        // Duplicate ids are invalid, so the old config should be kept.
+        fs::write(&path, config(&dir, &["first", "first"])).unwrap();
+        let log = wait_for_log(&dir.join("log"), "Failed to reload config");
+        assert!(log.contains("Failed to reload config"), "Should have reported the invalid config:\n{}", log);
+        assert_eq!(route_until(&router, |_| true), 2);
+
+        // Stop watching first, otherwise removing the config would report that it couldn't be reloaded.
+        drop(router);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_drop_in_dir() {
+        let dir = std::env::temp_dir().join(format!("rnotify_test_reload_drop_in_{}", std::process::id()));
+        let drop_in_dir = dir.join("rnotify.d");
+        fs::create_dir_all(&drop_in_dir).unwrap();
+        fs::write(drop_in_dir.join("10-first.toml"), config(&dir, &["first"])).unwrap();
+        fs::write(drop_in_dir.join("notes.txt"), "Not a config file").unwrap();
+
+        let router = ReloadingMessageRouter::new(vec![drop_in_dir.clone()], None).expect("Config should be valid");
+        assert_eq!(route_until(&router, |_| true), 1);
+
+        fs::write(drop_in_dir.join("20-second.toml"), config(&dir, &["second"])).unwrap();
+        assert_eq!(route_until(&router, |sent| sent == 2), 2, "Should load the new drop-in file");
+
+        drop(router);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt::{Display, Formatter};
+
+/// The path a message took through the fallback chain of a destination that failed.
+/// See [`RoutingInfo::get_fallback`](crate::message_router::RoutingInfo::get_fallback)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FallbackPath {
+    /// The destination that originally failed, followed by each fallback destination tried, in order.
+    path: Vec<String>,
+    /// The id of each destination in the path that failed, with the error.
+    failures: Vec<(String, String)>,
+    delivered: bool,
+}
+
+impl FallbackPath {
+    pub fn new(failed_destination_id: String) -> Self {
+        Self {
+            path: vec![failed_destination_id],
+            failures: vec![],
+            delivered: false,
+        }
+    }
+
+    pub(crate) fn attempt(&mut self, destination_id: String) {
+        self.path.push(destination_id);
+    }
+
+    pub(crate) fn failed(&mut self, destination_id: String, error: String) {
+        self.failures.push((destination_id, error));
+    }
+
+    pub(crate) fn delivered(&mut self) {
+        self.delivered = true;
+    }
+
+    /// The id of the destination that failed, causing its fallback chain to be used.
+    pub fn get_failed_destination_id(&self) -> &str {
+        &self.path[0]
+    }
+
+    /// The ids of the destinations the message was attempted to be sent to, starting
+    /// with the destination that originally failed.
+    pub fn get_path(&self) -> &Vec<String> {
+        &self.path
+    }
+
+    /// The id of each destination in the path that failed to send the message, with the error.
+    /// If the message was delivered, these errors are only recorded here rather than being reported.
+    pub fn get_failures(&self) -> &Vec<(String, String)> {
+        &self.failures
+    }
+
+    /// The id of the fallback destination that accepted the message, if any.
+    pub fn get_delivered_to(&self) -> Option<&str> {
+        if self.delivered {
+            self.path.last().map(|s| s.as_str())
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for FallbackPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.join(" -> "))?;
+        match self.get_delivered_to() {
+            Some(id) => write!(f, " (delivered to '{}')", id),
+            None => write!(f, " (not delivered)"),
+        }
+    }
+}
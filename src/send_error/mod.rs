@@ -2,13 +2,17 @@ use std::error::Error;
 use std::fmt::{Display, Formatter};
 use crate::message::author::Author;
 use crate::message::{Level, Message, MessageDetail};
+use crate::send_error::fallback::FallbackPath;
 use crate::send_error::reported::ReportedSendError;
 
 pub mod borrowed;
+pub mod fallback;
 pub mod owned;
+pub mod partial;
 pub mod reported;
 
 pub trait SendError {
+    #[allow(clippy::borrowed_box)]
     fn get_error(&self) -> &Box<dyn Error>;
 
     fn get_failed_destination_id(&self) -> &str;
@@ -19,10 +23,10 @@ pub trait SendError {
         Message::new(Level::SelfError,
                      Some(format!("Failed to send notification to destination {}", self.get_failed_destination_id())),
                      MessageDetail::Raw(format!("Rnotify failed to send a message {:?} to destination id '{}'. Error: '{}' A notification has been sent here because this is configured as a root logger.",
-                                                         self.get_failed_message(), self.get_failed_destination_id(), self.get_error())),
+                                                self.get_failed_message(), self.get_failed_destination_id(), self.get_error())),
                      None,
                      Author::parse("rnotify".to_owned()),
-                     self.get_failed_message().get_unix_timestamp_millis().clone(),
+                     self.get_failed_message().get_unix_timestamp_millis(),
         )
     }
 }
@@ -34,22 +38,39 @@ pub struct SendErrors<'a> {
     successfully_sent: usize,
     original_message: &'a Message,
     errors: Vec<ReportedSendError<'a>>,
+    fallback_paths: Vec<FallbackPath>,
 }
 
 impl<'a> SendErrors<'a> {
 
-    pub fn new(message: &'a Message, errors: Vec<ReportedSendError<'a>>, successfully_sent: usize) -> Self {
+    pub fn new(message: &'a Message, errors: Vec<ReportedSendError<'a>>, successfully_sent: usize, fallback_paths: Vec<FallbackPath>) -> Self {
         Self {
             successfully_sent,
             original_message: message,
             errors,
+            fallback_paths,
         }
     }
 
+    /// Get the number of destinations the message was sent to.
+    pub fn get_successfully_sent(&self) -> usize {
+        self.successfully_sent
+    }
+
     /// Get the message that caused errors when sent.
     pub fn get_message(&self) -> &Message {
         self.original_message
     }
+
+    /// Get the errors that occurred when sending the message.
+    pub fn get_errors(&self) -> &Vec<ReportedSendError<'a>> {
+        &self.errors
+    }
+
+    /// Get the paths taken through the fallback chains of destinations that failed.
+    pub fn get_fallback_paths(&self) -> &Vec<FallbackPath> {
+        &self.fallback_paths
+    }
 }
 
 impl<'a> Display for SendErrors<'a> {
@@ -83,6 +104,13 @@ impl<'a> Display for SendErrors<'a> {
                 }
             }
         }
+        if !self.fallback_paths.is_empty() {
+            writeln!(f, "--")?;
+            writeln!(f, "Fallbacks:")?;
+            for path in &self.fallback_paths {
+                writeln!(f, "   - {}", path)?;
+            }
+        }
         writeln!(f, "-----")
     }
 }
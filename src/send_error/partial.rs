@@ -0,0 +1,59 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// An error from a destination that had already delivered the message when it failed,
+/// e.g. if its text was sent but an attachment couldn't be uploaded.
+///
+/// The error is still reported, but the message counts as delivered, so it is not sent again
+/// to a fallback destination or later on, which would duplicate what was already delivered.
+#[derive(Debug)]
+pub struct PartialSendError {
+    sent_id: Option<String>,
+    error: Box<dyn Error>,
+}
+
+impl PartialSendError {
+    /// `sent_id` is the id of the message that was delivered, if the destination returns one,
+    /// see [`send_alert`](crate::destination::MessageDestination::send_alert)
+    pub fn new(sent_id: Option<String>, error: Box<dyn Error>) -> Self {
+        Self {
+            sent_id,
+            error,
+        }
+    }
+
+    pub fn get_sent_id(&self) -> Option<&str> {
+        self.sent_id.as_deref()
+    }
+
+    pub fn get_error(&self) -> &dyn Error {
+        self.error.as_ref()
+    }
+
+    pub(crate) fn map_error<F: FnOnce(Box<dyn Error>) -> Box<dyn Error>>(self, f: F) -> Self {
+        Self {
+            sent_id: self.sent_id,
+            error: f(self.error),
+        }
+    }
+}
+
+impl Error for PartialSendError {}
+
+impl Display for PartialSendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The message was delivered, but not completely: {}", self.error)
+    }
+}
+
+/// Whether the error means the message was delivered anyway, see [`PartialSendError`]
+pub fn is_partial(error: &(dyn Error + 'static)) -> bool {
+    error.is::<PartialSendError>()
+}
+
+/// The id of the message that was delivered despite the error, if any, see [`PartialSendError`]
+pub fn sent_id(error: &(dyn Error + 'static)) -> Option<String> {
+    error.downcast_ref::<PartialSendError>()
+        .and_then(|partial| partial.get_sent_id())
+        .map(|id| id.to_owned())
+}
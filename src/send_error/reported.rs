@@ -25,11 +25,11 @@ impl<'a> ReportedSendError<'a> {
 
 impl<'a> SendError for ReportedSendError<'a> {
     fn get_error(&self) -> &Box<dyn Error> {
-        &self.send_err.get_error()
+        self.send_err.get_error()
     }
 
     fn get_failed_destination_id(&self) -> &str {
-        &self.send_err.get_failed_destination_id()
+        self.send_err.get_failed_destination_id()
     }
 
     fn get_failed_message(&self) -> &Message {
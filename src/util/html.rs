@@ -1,68 +1,141 @@
 use crate::message::formatted_detail::{FormattedMessageComponent, FormattedMessageDetail, FormattedString, Style};
 
+/// The HTML tags the destination supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtmlDialect {
+    /// Full HTML, e.g. for emails.
+    Standard,
+    /// The subset supported by Telegram's `HTML` parse mode, which has no block elements
+    /// apart from `<pre>` and `<blockquote>`, so lists, tables and rules are written out as text.
+    Telegram,
+}
+
+/// Renders a formatted message as HTML, escaping any text that would otherwise be parsed as tags.
 pub trait HtmlMessageDetail {
-    fn create_html(&self) -> String;
+    fn create_html(&self, dialect: HtmlDialect) -> String;
 }
 
 impl HtmlMessageDetail for FormattedMessageDetail {
-    fn create_html(&self) -> String {
-        formatted_to_html(&self)
+    fn create_html(&self, dialect: HtmlDialect) -> String {
+        formatted_to_html(self, dialect)
     }
 }
 
-fn formatted_to_html(formatted: &FormattedMessageDetail) -> String {
+fn formatted_to_html(formatted: &FormattedMessageDetail, dialect: HtmlDialect) -> String {
     let mut html = String::with_capacity(100);
     for component in formatted.components() {
-        match component {
-            FormattedMessageComponent::Section(section, formatted_string) => {
-                html.push_str(&format!("<div><h2>{}</h2><p>{}</p></div>",
-                                       escape_html(section),
-                                       parse_formatted(formatted_string)
-                ));
-            }
-            FormattedMessageComponent::Text(formatted_string) => {
-                html.push_str(&format!("<p>{}</p>", parse_formatted(formatted_string)))
-            }
+        match dialect {
+            HtmlDialect::Standard => html.push_str(&component_to_html(component)),
+            HtmlDialect::Telegram => html.push_str(&component_to_telegram_html(component)),
         }
     }
     html
 }
 
-fn escape_html(s: &str) -> String {
+fn component_to_html(component: &FormattedMessageComponent) -> String {
+    let dialect = HtmlDialect::Standard;
+    match component {
+        FormattedMessageComponent::Section(section, formatted_string) => {
+            format!("<div><h2>{}</h2><p>{}</p></div>", escape_html(section), parse_formatted(formatted_string, dialect))
+        }
+        FormattedMessageComponent::Text(formatted_string) => format!("<p>{}</p>", parse_formatted(formatted_string, dialect)),
+        FormattedMessageComponent::List(items) => {
+            let items: String = items.iter()
+                .map(|item| format!("<li>{}</li>", parse_formatted(item, dialect)))
+                .collect();
+            format!("<ul>{}</ul>", items)
+        }
+        FormattedMessageComponent::Table(rows) => {
+            let rows: String = rows.iter()
+                .map(|(key, value)| format!("<tr><th>{}</th><td>{}</td></tr>", escape_html(key), parse_formatted(value, dialect)))
+                .collect();
+            format!("<table>{}</table>", rows)
+        }
+        FormattedMessageComponent::Quote(formatted_string) => {
+            format!("<blockquote>{}</blockquote>", parse_formatted(formatted_string, dialect))
+        }
+        FormattedMessageComponent::Rule => "<hr>".to_owned(),
+    }
+}
+
+fn component_to_telegram_html(component: &FormattedMessageComponent) -> String {
+    let dialect = HtmlDialect::Telegram;
+    match component {
+        FormattedMessageComponent::Section(title, formatted_string) => {
+            format!("<b><u>{}</u></b>\n{}\n", escape_html(title), parse_formatted(formatted_string, dialect))
+        }
+        FormattedMessageComponent::Text(formatted_string) => parse_formatted(formatted_string, dialect),
+        FormattedMessageComponent::List(items) => items.iter()
+            .map(|item| format!("\u{2022} {}\n", parse_formatted(item, dialect)))
+            .collect(),
+        FormattedMessageComponent::Table(rows) => rows.iter()
+            .map(|(key, value)| format!("<b>{}</b>: {}\n", escape_html(key), parse_formatted(value, dialect)))
+            .collect(),
+        FormattedMessageComponent::Quote(formatted_string) => {
+            format!("<blockquote>{}</blockquote>\n", parse_formatted(formatted_string, dialect))
+        }
+        FormattedMessageComponent::Rule => "\u{2500}".repeat(20) + "\n",
+    }
+}
+
+pub(crate) fn escape_html(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('>', "&gt;")
         .replace('<', "&lt;")
 }
 
-fn parse_formatted(formatted: &Vec<FormattedString>) -> String {
+/// Escapes the string for use inside a double quoted attribute.
+pub(crate) fn escape_attribute(s: &str) -> String {
+    escape_html(s).replace('"', "&quot;")
+}
+
+fn parse_formatted(formatted: &[FormattedString], dialect: HtmlDialect) -> String {
     let mut html = String::new();
     for part in formatted {
         for style in part.get_styles() {
-            let start_tag = match style {
-                Style::Bold => "<b>",
-                Style::Italics => "<i>",
-                Style::Monospace => "<code>",
-                Style::Code { lang: _ } => "<code>",
-            };
-            html.push_str(start_tag);
+            html.push_str(&start_tag(style, dialect));
         }
         html.push_str(&escape_html(part.get_string()));
-        for style in part.get_styles() {
-            let end_tag = match style {
-                Style::Bold => "</b>",
-                Style::Italics => "</i>",
-                Style::Monospace => "</code>",
-                Style::Code { lang: _ } => "</code>",
-            };
-            html.push_str(end_tag);
+        for style in part.get_styles().iter().rev() {
+            html.push_str(end_tag(style, dialect));
         }
     }
     html
 }
 
+fn start_tag(style: &Style, dialect: HtmlDialect) -> String {
+    match (style, dialect) {
+        (Style::Bold, _) => "<b>".to_owned(),
+        (Style::Italics, _) => "<i>".to_owned(),
+        (Style::Monospace, _) => "<code>".to_owned(),
+        (Style::Code { lang: _ }, HtmlDialect::Standard) => "<code>".to_owned(),
+        (Style::Code { lang }, HtmlDialect::Telegram) if lang.is_empty() => "<pre>".to_owned(),
+        (Style::Code { lang }, HtmlDialect::Telegram) => format!("<pre><code class=\"language-{}\">", escape_attribute(lang)),
+        (Style::Link { url }, _) => format!("<a href=\"{}\">", escape_attribute(url)),
+        (Style::Color { color }, HtmlDialect::Standard) => format!("<span style=\"color: {}\">", escape_attribute(color)),
+        // Telegram doesn't support coloured text.
+        (Style::Color { color: _ }, HtmlDialect::Telegram) => String::new(),
+    }
+}
+
+fn end_tag(style: &Style, dialect: HtmlDialect) -> &'static str {
+    match (style, dialect) {
+        (Style::Bold, _) => "</b>",
+        (Style::Italics, _) => "</i>",
+        (Style::Monospace, _) => "</code>",
+        (Style::Code { lang: _ }, HtmlDialect::Standard) => "</code>",
+        (Style::Code { lang }, HtmlDialect::Telegram) if lang.is_empty() => "</pre>",
+        (Style::Code { lang: _ }, HtmlDialect::Telegram) => "</code></pre>",
+        (Style::Link { url: _ }, _) => "</a>",
+        (Style::Color { color: _ }, HtmlDialect::Standard) => "</span>",
+        (Style::Color { color: _ }, HtmlDialect::Telegram) => "",
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::message::detail_builder::{FormattedStringAppendable, MessageDetailBuilder};
+    use crate::message::detail_builder::test::example;
     use crate::message::MessageDetail::Formatted;
     use super::*;
 
@@ -78,11 +151,31 @@ mod test {
         });
         let message = builder.build();
         if let Formatted(formatted_detail) = message {
-            let html = formatted_to_html(&formatted_detail);
+            let html = formatted_to_html(&formatted_detail, HtmlDialect::Standard);
             assert_eq!(html, "<div><h2>hello world</h2><p>Dear fellow inhabitants. it has come to my attention that <b>you have not been doing your job.</b></p></div><p>That is all.</p>")
         }
         else {
             panic!("oops");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_dialects() {
+        let detail = example();
+        assert_eq!(detail.create_html(HtmlDialect::Standard),
+                   "<div><h2>Disk_usage</h2><p>1.5 GB *free*, <code>df -h</code><a href=\"https://example.com/a_(b)\">docs</a></p></div>\
+                    <table><tr><th>/</th><td>45%</td></tr><tr><th>/home</th><td><b>95%</b></td></tr></table>\
+                    <ul><li>a &lt; b &amp; c</li><li><a href=\"https://example.com\">https://example.com</a></li></ul>\
+                    <hr><blockquote><i>No space\nleft</i></blockquote>");
+        assert_eq!(detail.create_html(HtmlDialect::Telegram),
+                   "<b><u>Disk_usage</u></b>\n1.5 GB *free*, <code>df -h</code><a href=\"https://example.com/a_(b)\">docs</a>\n\
+                    <b>/</b>: 45%\n<b>/home</b>: <b>95%</b>\n\u{2022} a &lt; b &amp; c\n\u{2022} <a href=\"https://example.com\">https://example.com</a>\n\
+                    \u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\n\
+                    <blockquote><i>No space\nleft</i></blockquote>\n");
+    }
+
+    #[test]
+    fn test_escape_attribute() {
+        assert_eq!(escape_attribute("https://example.com/?a=\"<b>\"&c"), "https://example.com/?a=&quot;&lt;b&gt;&quot;&amp;c");
+    }
+}
@@ -1,18 +1,197 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use error::MessageSendError;
 
 pub mod error;
 
+/// The most times a rate limited request is retried, see [`send_with_retry`]
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+/// The longest to wait before retrying a rate limited request, beyond which it fails instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Sends a GET request to the url, returning an error if the response code is not 200.
+pub fn get(url: &str) -> Result<(), Box<dyn Error>> {
+    let response = minreq::get(url)
+        .with_header("Accept", "application/json")
+        .send()?;
+    let code = response.status_code;
+    if code != 200 {
+        let response = response.as_str()?;
+        return Err(Box::new(MessageSendError::new(format!("Got response code {}: Response body: {}", code, response))));
+    }
+    Ok(())
+}
+
 pub fn post_as_json_to<T: Serialize>(url: &str, payload: &T) -> Result<(), Box<dyn Error>> {
-    let response = minreq::post(url)
+    send_json(minreq::post(url), payload).map(|_| ())
+}
+
+/// Like [`post_as_json_to`], but returns the JSON response, e.g. to get the id of the message that was sent.
+pub fn post_as_json_for<T: Serialize, R: DeserializeOwned>(url: &str, payload: &T) -> Result<R, Box<dyn Error>> {
+    Ok(send_json(minreq::post(url), payload)?.json()?)
+}
+
+pub fn patch_as_json_to<T: Serialize>(url: &str, payload: &T) -> Result<(), Box<dyn Error>> {
+    send_json(minreq::patch(url), payload).map(|_| ())
+}
+
+fn send_json<T: Serialize>(request: minreq::Request, payload: &T) -> Result<minreq::Response, Box<dyn Error>> {
+    let request = request
         .with_json(payload)?
+        .with_header("Accept", "application/json");
+    send_with_retry(request)
+}
+
+/// Sends the request, waiting and trying again if it is rate limited (429) and the platform says when to retry,
+/// up to [`MAX_RATE_LIMIT_RETRIES`] times.
+fn send_with_retry(request: minreq::Request) -> Result<minreq::Response, Box<dyn Error>> {
+    let mut retries = 0;
+    loop {
+        let response = request.clone().send()?;
+        if response.status_code == 429 && retries < MAX_RATE_LIMIT_RETRIES {
+            if let Some(wait) = retry_after(&response.headers, response.as_bytes()).filter(|wait| *wait <= MAX_RETRY_AFTER) {
+                thread::sleep(wait);
+                retries += 1;
+                continue;
+            }
+        }
+        return check_post_response(response);
+    }
+}
+
+/// How long to wait before retrying a rate limited request, from the `Retry-After` header,
+/// or the `retry_after` in the body given by Discord (`retry_after`) and Telegram (`parameters.retry_after`).
+fn retry_after(headers: &HashMap<String, String>, body: &[u8]) -> Option<Duration> {
+    let header = headers.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+        .and_then(|(_, value)| value.trim().parse::<f64>().ok());
+    let from_body = || {
+        let json: serde_json::Value = serde_json::from_slice(body).ok()?;
+        json.get("retry_after").or_else(|| json.pointer("/parameters/retry_after"))?.as_f64()
+    };
+    header.or_else(from_body)
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// A part of a `multipart/form-data` request, see [`post_multipart_to`]
+pub struct FormPart<'a> {
+    name: String,
+    file: Option<(&'a str, &'a str)>,
+    data: &'a [u8],
+}
+
+impl<'a> FormPart<'a> {
+    pub fn text(name: &str, value: &'a str) -> Self {
+        Self {
+            name: name.to_owned(),
+            file: None,
+            data: value.as_bytes(),
+        }
+    }
+
+    pub fn file(name: &str, filename: &'a str, content_type: &'a str, data: &'a [u8]) -> Self {
+        Self {
+            name: name.to_owned(),
+            file: Some((filename, content_type)),
+            data,
+        }
+    }
+}
+
+/// Sends the parts as a `multipart/form-data` POST request, e.g. to upload files.
+pub fn post_multipart_to(url: &str, parts: &[FormPart]) -> Result<(), Box<dyn Error>> {
+    send_multipart(url, parts).map(|_| ())
+}
+
+/// Like [`post_multipart_to`], but returns the JSON response.
+pub fn post_multipart_for<R: DeserializeOwned>(url: &str, parts: &[FormPart]) -> Result<R, Box<dyn Error>> {
+    Ok(send_multipart(url, parts)?.json()?)
+}
+
+fn send_multipart(url: &str, parts: &[FormPart]) -> Result<minreq::Response, Box<dyn Error>> {
+    let boundary = boundary(parts);
+    let mut body = vec![];
+    for part in parts {
+        body.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", boundary, quote(&part.name)).as_bytes());
+        if let Some((filename, content_type)) = part.file {
+            body.extend_from_slice(format!("; filename=\"{}\"\r\nContent-Type: {}", quote(filename), content_type).as_bytes());
+        }
+        body.extend_from_slice(b"\r\n\r\n");
+        body.extend_from_slice(part.data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    let request = minreq::post(url)
+        .with_header("Content-Type", format!("multipart/form-data; boundary={}", boundary))
         .with_header("Accept", "application/json")
-        .send()?;
+        .with_body(body);
+    send_with_retry(request)
+}
+
+/// Creates a boundary that doesn't appear in any of the parts, which would otherwise end the part early.
+fn boundary(parts: &[FormPart]) -> String {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() ^ std::process::id() as u128;
+    boundary_from(seed, parts)
+}
+
+fn boundary_from(seed: u128, parts: &[FormPart]) -> String {
+    let mut hasher = DefaultHasher::new();
+    for attempt in 0u32.. {
+        (seed, attempt).hash(&mut hasher);
+        let boundary = format!("rnotify-{:016x}", hasher.finish());
+        if !parts.iter().any(|part| contains(part.data, boundary.as_bytes())) {
+            return boundary;
+        }
+    }
+    unreachable!("Every boundary appears in the parts")
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+fn quote(s: &str) -> String {
+    s.replace(['"', '\r', '\n'], "_")
+}
+
+fn check_post_response(response: minreq::Response) -> Result<minreq::Response, Box<dyn Error>> {
     let code = response.status_code;
     if code != 200 && code != 204 {
         let response = response.as_str()?;
         return Err(Box::new(MessageSendError::new(format!("Got response code {}: Response body: {}", code, response))));
     }
-    Ok(())
-}
\ No newline at end of file
+    Ok(response)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after() {
+        let headers = HashMap::from([("retry-after".to_owned(), "2".to_owned())]);
+        assert_eq!(retry_after(&headers, b""), Some(Duration::from_secs(2)));
+
+        let none = HashMap::new();
+        assert_eq!(retry_after(&none, br#"{"message": "You are being rate limited.", "retry_after": 0.5}"#), Some(Duration::from_millis(500)));
+        assert_eq!(retry_after(&none, br#"{"ok": false, "error_code": 429, "parameters": {"retry_after": 3}}"#), Some(Duration::from_secs(3)));
+        assert_eq!(retry_after(&none, b"Too many requests"), None);
+    }
+
+    #[test]
+    fn test_boundary() {
+        let first = boundary_from(1, &[]);
+        let data = format!("--{}--", first).into_bytes();
+        let parts = [FormPart::file("file", "log.txt", "text/plain", &data)];
+        let boundary = boundary_from(1, &parts);
+        assert_ne!(boundary, first, "Should not use a boundary that is in the data");
+        assert!(!contains(&data, boundary.as_bytes()));
+    }
+}
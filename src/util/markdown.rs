@@ -0,0 +1,185 @@
+use crate::message::formatted_detail::{FormattedMessageComponent, FormattedMessageDetail, FormattedString, Style};
+
+/// The flavour of markdown to render, since each platform supports different formatting and escaping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkdownDialect {
+    /// Discord's markdown, which has no horizontal rules, and shows multi-line monospace text as a code block.
+    Discord,
+    /// Telegram's [MarkdownV2](https://core.telegram.org/bots/api#markdownv2-style), where every special character must be escaped.
+    TelegramV2,
+    /// [CommonMark](https://commonmark.org/)
+    CommonMark,
+    /// Slack's [mrkdwn](https://api.slack.com/reference/surfaces/formatting), which has no escapes except for `&`, `<` and `>`.
+    SlackMrkdwn,
+}
+
+/// Renders a formatted message as markdown, escaping any text that would otherwise be formatted.
+/// Colours are ignored, since none of the dialects support them.
+pub trait MarkdownMessageDetail {
+    fn create_markdown(&self, dialect: MarkdownDialect) -> String;
+}
+
+impl MarkdownMessageDetail for FormattedMessageDetail {
+    fn create_markdown(&self, dialect: MarkdownDialect) -> String {
+        self.components().iter()
+            .map(|component| component_to_markdown(component, dialect))
+            .collect()
+    }
+}
+
+/// Renders a single component, ending with a newline unless it is text.
+pub(crate) fn component_to_markdown(component: &FormattedMessageComponent, dialect: MarkdownDialect) -> String {
+    match component {
+        FormattedMessageComponent::Section(title, contents) => {
+            let heading = match dialect {
+                MarkdownDialect::Discord => format!("**__{}__**", escape_markdown(title, dialect)),
+                MarkdownDialect::TelegramV2 => format!("*__{}__*", escape_markdown(title, dialect)),
+                MarkdownDialect::CommonMark => format!("## {}", escape_markdown(title, dialect)),
+                MarkdownDialect::SlackMrkdwn => format!("*{}*", escape_markdown(title, dialect)),
+            };
+            format!("{}\n{}\n", heading, to_markdown_string(contents, dialect))
+        }
+        FormattedMessageComponent::Text(text) => to_markdown_string(text, dialect),
+        FormattedMessageComponent::List(items) => {
+            // Telegram and Slack don't have lists, and Telegram would need the marker to be escaped.
+            let marker = match dialect {
+                MarkdownDialect::Discord | MarkdownDialect::CommonMark => "-",
+                MarkdownDialect::TelegramV2 | MarkdownDialect::SlackMrkdwn => "\u{2022}",
+            };
+            items.iter()
+                .map(|item| format!("{} {}\n", marker, to_markdown_string(item, dialect)))
+                .collect()
+        }
+        FormattedMessageComponent::Table(rows) => rows.iter()
+            .map(|(key, value)| format!("{}: {}\n", bold(&escape_markdown(key, dialect), dialect), to_markdown_string(value, dialect)))
+            .collect(),
+        FormattedMessageComponent::Quote(text) => to_markdown_string(text, dialect).lines()
+            .map(|line| format!("> {}\n", line))
+            .collect(),
+        FormattedMessageComponent::Rule => match dialect {
+            MarkdownDialect::CommonMark => "***\n".to_owned(),
+            _ => "\u{2500}".repeat(20) + "\n",
+        },
+    }
+}
+
+pub(crate) fn to_markdown_string(parts: &[FormattedString], dialect: MarkdownDialect) -> String {
+    parts.iter().map(|part| to_markdown_format(part, dialect)).collect()
+}
+
+fn to_markdown_format(formatted_string: &FormattedString, dialect: MarkdownDialect) -> String {
+    let is_code = formatted_string.get_styles().iter()
+        .any(|style| matches!(style, Style::Monospace | Style::Code { .. }));
+    let mut result = if is_code {
+        escape_code(formatted_string.get_string(), dialect)
+    } else {
+        escape_markdown(formatted_string.get_string(), dialect)
+    };
+    for style in formatted_string.get_styles() {
+        result = apply_style(&result, style, dialect);
+    }
+    result
+}
+
+fn apply_style(s: &str, style: &Style, dialect: MarkdownDialect) -> String {
+    match style {
+        Style::Bold => bold(s, dialect),
+        Style::Italics => format!("_{}_", s),
+        Style::Monospace => {
+            if dialect != MarkdownDialect::SlackMrkdwn && (s.is_empty() || s.contains('\n')) {
+                return code_block(s, "", dialect);
+            }
+            format!("`{}`", s)
+        }
+        Style::Code { lang } => code_block(s, lang, dialect),
+        Style::Link { url } => match dialect {
+            MarkdownDialect::SlackMrkdwn => format!("<{}|{}>", url, s),
+            // Only `)` and `\` need escaping in Telegram link urls.
+            MarkdownDialect::TelegramV2 => format!("[{}]({})", s, url.replace('\\', "\\\\").replace(')', "\\)")),
+            _ => format!("[{}]({})", s, url),
+        },
+        Style::Color { .. } => s.to_owned(),
+    }
+}
+
+fn bold(s: &str, dialect: MarkdownDialect) -> String {
+    match dialect {
+        MarkdownDialect::Discord | MarkdownDialect::CommonMark => format!("**{}**", s),
+        MarkdownDialect::TelegramV2 | MarkdownDialect::SlackMrkdwn => format!("*{}*", s),
+    }
+}
+
+fn code_block(s: &str, lang: &str, dialect: MarkdownDialect) -> String {
+    let newline = if s.ends_with('\n') { "" } else { "\n" };
+    match dialect {
+        // Slack doesn't support a language.
+        MarkdownDialect::SlackMrkdwn => format!("```{}```", s),
+        _ => format!("```{}\n{}{}```", lang, s, newline),
+    }
+}
+
+/// Escapes the characters that would be treated as formatting.
+pub fn escape_markdown(s: &str, dialect: MarkdownDialect) -> String {
+    let special: &[char] = match dialect {
+        MarkdownDialect::Discord => &['\\', '*', '_', '~', '`', '|', '>', '#', '[', ']', '-'],
+        MarkdownDialect::TelegramV2 => &['\\', '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!'],
+        MarkdownDialect::CommonMark => &['\\', '*', '_', '~', '`', '|', '<', '>', '#', '[', ']', '!'],
+        MarkdownDialect::SlackMrkdwn => return s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+    };
+    escape_chars(s, special)
+}
+
+/// Escapes the text of inline code or a code block.
+fn escape_code(s: &str, dialect: MarkdownDialect) -> String {
+    match dialect {
+        MarkdownDialect::TelegramV2 => escape_chars(s, &['\\', '`']),
+        MarkdownDialect::SlackMrkdwn => escape_markdown(s, dialect),
+        MarkdownDialect::Discord | MarkdownDialect::CommonMark => s.to_owned(),
+    }
+}
+
+fn escape_chars(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::detail_builder::test::example;
+    use super::*;
+
+    #[test]
+    fn test_dialects() {
+        let detail = example();
+        assert_eq!(detail.create_markdown(MarkdownDialect::Discord),
+                   "**__Disk\\_usage__**\n1.5 GB \\*free\\*, `df -h`[docs](https://example.com/a_(b))\n**/**: 45%\n**/home**: **95%**\n\
+                    - a < b & c\n- [https://example.com](https://example.com)\n\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\n\
+                    > _No space\n> left_\n");
+        assert_eq!(detail.create_markdown(MarkdownDialect::TelegramV2),
+                   "*__Disk\\_usage__*\n1\\.5 GB \\*free\\*, `df -h`[docs](https://example.com/a_(b\\))\n*/*: 45%\n*/home*: *95%*\n\
+                    \u{2022} a < b & c\n\u{2022} [https://example\\.com](https://example.com)\n\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\n\
+                    > _No space\n> left_\n");
+        assert_eq!(detail.create_markdown(MarkdownDialect::CommonMark),
+                   "## Disk\\_usage\n1.5 GB \\*free\\*, `df -h`[docs](https://example.com/a_(b))\n**/**: 45%\n**/home**: **95%**\n\
+                    - a \\< b & c\n- [https://example.com](https://example.com)\n***\n> _No space\n> left_\n");
+        assert_eq!(detail.create_markdown(MarkdownDialect::SlackMrkdwn),
+                   "*Disk_usage*\n1.5 GB *free*, `df -h`<https://example.com/a_(b)|docs>\n*/*: 45%\n*/home*: *95%*\n\
+                    \u{2022} a &lt; b &amp; c\n\u{2022} <https://example.com|https://example.com>\n\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\n\
+                    > _No space\n> left_\n");
+    }
+
+    #[test]
+    fn test_code_block() {
+        let code = vec![FormattedString::styled("let a = 1;\nlet b = a;", Style::Code { lang: "rust".to_owned() })];
+        assert_eq!(to_markdown_string(&code, MarkdownDialect::Discord), "```rust\nlet a = 1;\nlet b = a;\n```");
+        assert_eq!(to_markdown_string(&code, MarkdownDialect::SlackMrkdwn), "```let a = 1;\nlet b = a;```");
+        let multi_line = FormattedString::styled("a\nb", Style::Monospace);
+        assert_eq!(to_markdown_string(&[multi_line], MarkdownDialect::Discord), "```\na\nb\n```");
+    }
+}
@@ -1,3 +1,10 @@
 pub mod html;
+pub mod markdown;
+pub mod overflow;
+pub mod plain_text;
+pub mod secret;
+pub mod template;
+pub mod time_format;
+pub mod timezone;
 #[cfg(feature = "http")]
-pub mod http_util;
\ No newline at end of file
+pub mod http_util;
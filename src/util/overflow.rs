@@ -0,0 +1,497 @@
+//! Fitting messages into the length limits of a platform, e.g. Discord's 4096 character embed descriptions.
+//!
+//! A message that is too long is either truncated, with a `... (N more bytes in message.txt)` marker and the full text
+//! attached as `message.txt`, or split into several messages sent one after the other.
+//! Messages are split between lines, or between characters if a single line is too long,
+//! and each part is formatted separately, so formatting is never left unclosed.
+use std::borrow::Cow;
+use std::mem;
+use serde::{Deserialize, Serialize};
+use crate::message::{Message, MessageDetail};
+use crate::message::attachment::Attachment;
+use crate::message::formatted_detail::{FormattedMessageComponent, FormattedMessageDetail, FormattedString, Style};
+use crate::util::plain_text::PlainTextMessageDetail;
+
+/// The name of the attachment containing the full text of a truncated message.
+const FULL_TEXT_NAME: &str = "message.txt";
+
+/// The most messages a message is split into, so that a huge message doesn't flood the chat.
+pub const MAX_PARTS: usize = 10;
+
+/// What a destination does with a message that is too long for it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Overflow {
+    /// Cut the message short, and attach its full text as a file.
+    #[default]
+    Truncate,
+    /// Send the message as several messages, e.g. with titles `Backup (1/3)`, `Backup (2/3)` ...
+    Split,
+}
+
+/// Fits the message into the limits of a destination, where `fits` is whether a message is short enough to send.
+///
+/// Returns the messages to send, in order. This is just the message if it already fits,
+/// or if it can't be made to fit, e.g. because its title alone is too long.
+/// A message is split into at most [`MAX_PARTS`] messages, the last of which is truncated if there is more.
+pub fn fit<F: Fn(&Message) -> bool>(message: &Message, overflow: Overflow, fits: F) -> Vec<Cow<'_, Message>> {
+    if fits(message) {
+        return vec![Cow::Borrowed(message)];
+    }
+    let result = match overflow {
+        Overflow::Truncate => truncated(message, &fits, true, 1),
+        Overflow::Split => split(message, &fits).or_else(|| truncated(message, &fits, true, MAX_PARTS)),
+    };
+    result.unwrap_or_else(|| vec![Cow::Borrowed(message)])
+}
+
+/// Cuts the message short to fit into the limits of a destination, without attaching its full text,
+/// e.g. when editing a message, which can't add attachments.
+pub fn truncate<F: Fn(&Message) -> bool>(message: &Message, fits: F) -> Cow<'_, Message> {
+    if fits(message) {
+        return Cow::Borrowed(message);
+    }
+    truncated(message, &fits, false, 1)
+        .and_then(|mut parts| parts.pop())
+        .unwrap_or(Cow::Borrowed(message))
+}
+
+/// Splits the message into parts that each fit, or returns `None` if that would take more than [`MAX_PARTS`] messages.
+fn split<'a, F: Fn(&Message) -> bool>(message: &'a Message, fits: &F) -> Option<Vec<Cow<'a, Message>>> {
+    let detail = message.get_message_detail();
+    // The number of parts isn't known yet, so leave room for it.
+    let fits_chunk = |chunk: &[Piece]| {
+        fits(&with_detail(message, join(detail, chunk), Some(part_title(message, 99, 99)), true, true))
+    };
+    let chunks = chunks(pieces(detail), fits_chunk, MAX_PARTS + 1)?;
+    let count = chunks.len();
+    if count > MAX_PARTS {
+        return None;
+    }
+    Some(chunks.iter().enumerate()
+        .map(|(i, chunk)| Cow::Owned(with_detail(message, join(detail, chunk), Some(part_title(message, i + 1, count)), i == 0, i + 1 == count)))
+        .collect())
+}
+
+/// Cuts the message short, into `parts` messages that each fit, ending with a marker saying how much is missing,
+/// and attaching the full text to the last message if `attach_full_text`.
+fn truncated<'a, F: Fn(&Message) -> bool>(message: &'a Message, fits: &F, attach_full_text: bool, parts: usize) -> Option<Vec<Cow<'a, Message>>> {
+    let detail = message.get_message_detail();
+    let total_len = text_len(detail);
+    let marker = |len| marker_piece(detail, total_len - len, attach_full_text);
+    let title = |i: usize| if parts == 1 { message.get_title().clone() } else { Some(part_title(message, i + 1, parts)) };
+    // Any part may turn out to be the last, so leave room for the marker in each.
+    let fits_chunk = |chunk: &[Piece]| {
+        let mut chunk = chunk.to_vec();
+        chunk.push(marker(0));
+        fits(&with_detail(message, join(detail, &chunk), title(parts - 1), true, true))
+    };
+    let mut chunks = chunks(pieces(detail), fits_chunk, parts)?;
+    let count = chunks.len();
+    let shown: usize = chunks.iter().map(|chunk| text_len(&join(detail, chunk))).sum();
+    if let Some(last) = chunks.last_mut() {
+        last.push(marker(shown));
+    }
+    Some(chunks.iter().enumerate()
+        .map(|(i, chunk)| {
+            let title = if count == 1 { message.get_title().clone() } else { Some(part_title(message, i + 1, count)) };
+            let mut part = with_detail(message, join(detail, chunk), title, i == 0, i + 1 == count);
+            if attach_full_text && i + 1 == count {
+                let mut attachments = part.get_attachments().clone();
+                attachments.push(Attachment::from_bytes(FULL_TEXT_NAME, detail.raw().as_bytes().to_vec()));
+                part = part.with_attachments(attachments);
+            }
+            Cow::Owned(part)
+        })
+        .collect())
+}
+
+/// The title of one part of a split message, e.g. `Backup (1/3)`
+fn part_title(message: &Message, part: usize, count: usize) -> String {
+    match message.get_title() {
+        Some(title) => format!("{} ({}/{})", title, part, count),
+        None => format!("({}/{})", part, count),
+    }
+}
+
+/// Creates a message like the given message, but with a different detail and title.
+/// Only the first part has the message's fields and alert, and only the last part has its attachments and actions.
+fn with_detail(message: &Message, detail: MessageDetail, title: Option<String>, first: bool, last: bool) -> Message {
+    let mut part = Message::new(message.get_level().clone(), title, detail, message.get_component().clone(),
+                                message.get_author().clone(), message.get_unix_timestamp_millis());
+    if first {
+        part = part.with_fields(message.get_fields().clone());
+        if let Some(alert) = message.get_alert() {
+            part = part.with_alert(alert.clone());
+        }
+    }
+    if last {
+        part = part.with_attachments(message.get_attachments().clone())
+            .with_actions(message.get_actions().clone());
+    }
+    part
+}
+
+/// The length of the text of the detail, in bytes, ignoring formatting.
+fn text_len(detail: &MessageDetail) -> usize {
+    match detail {
+        MessageDetail::Raw(raw) => raw.len(),
+        MessageDetail::Formatted(formatted) => formatted.create_plain_text().len(),
+    }
+}
+
+/// Says how much of the message wasn't shown, e.g. `... (1024 more bytes in message.txt)`
+fn marker_piece(detail: &MessageDetail, remaining: usize, attached: bool) -> Piece {
+    let marker = if attached {
+        format!("\n... ({} more bytes in {})", remaining, FULL_TEXT_NAME)
+    } else {
+        format!("\n... ({} more bytes)", remaining)
+    };
+    match detail {
+        MessageDetail::Raw(_) => Piece::Raw(marker),
+        MessageDetail::Formatted(_) => Piece::Component(usize::MAX, FormattedMessageComponent::Text(vec![FormattedString::plain(marker)])),
+    }
+}
+
+/// A small part of a message detail, which can be joined back together with the pieces next to it.
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Raw(String),
+    /// Part of the component with the index in the original message.
+    Component(usize, FormattedMessageComponent),
+}
+
+/// Splits the detail into lines, list items and table rows.
+fn pieces(detail: &MessageDetail) -> Vec<Piece> {
+    match detail {
+        MessageDetail::Raw(raw) => raw.split_inclusive('\n').map(|line| Piece::Raw(line.to_owned())).collect(),
+        MessageDetail::Formatted(formatted) => {
+            let mut pieces = vec![];
+            for (i, component) in formatted.components().iter().enumerate() {
+                let parts: Vec<FormattedMessageComponent> = match component {
+                    FormattedMessageComponent::Section(title, text) => lines(text).into_iter()
+                        .map(|line| FormattedMessageComponent::Section(title.clone(), line))
+                        .collect(),
+                    FormattedMessageComponent::Text(text) => lines(text).into_iter().map(FormattedMessageComponent::Text).collect(),
+                    FormattedMessageComponent::Quote(text) => lines(text).into_iter().map(FormattedMessageComponent::Quote).collect(),
+                    FormattedMessageComponent::List(items) => items.iter()
+                        .map(|item| FormattedMessageComponent::List(vec![item.clone()]))
+                        .collect(),
+                    FormattedMessageComponent::Table(rows) => rows.iter()
+                        .map(|row| FormattedMessageComponent::Table(vec![row.clone()]))
+                        .collect(),
+                    FormattedMessageComponent::Rule => vec![FormattedMessageComponent::Rule],
+                };
+                // Keep sections with no text, so that their title is still shown.
+                if parts.is_empty() {
+                    pieces.push(Piece::Component(i, component.clone()));
+                }
+                pieces.extend(parts.into_iter().map(|part| Piece::Component(i, part)));
+            }
+            pieces
+        }
+    }
+}
+
+/// Splits the text into lines, each ending with its newline.
+/// Newlines are taken out of styled text, so that e.g. bold text is closed on the same line,
+/// except for code, where the newlines are part of the code block.
+fn lines(text: &[FormattedString]) -> Vec<Vec<FormattedString>> {
+    let mut lines = vec![];
+    let mut line = vec![];
+    for part in text {
+        let styles = part.get_styles();
+        let keep_newline = styles.is_empty() || styles.iter().any(|style| matches!(style, Style::Monospace | Style::Code { .. }));
+        for s in part.get_string().split_inclusive('\n') {
+            match s.strip_suffix('\n') {
+                Some(s) if !keep_newline => {
+                    if !s.is_empty() {
+                        line.push(FormattedString::new(s, styles.clone()));
+                    }
+                    line.push(FormattedString::plain("\n"));
+                }
+                _ => line.push(FormattedString::new(s, styles.clone())),
+            }
+            if s.ends_with('\n') {
+                lines.push(mem::take(&mut line));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Joins the pieces back into a detail like the original.
+fn join(original: &MessageDetail, pieces: &[Piece]) -> MessageDetail {
+    if let MessageDetail::Raw(_) = original {
+        return MessageDetail::Raw(pieces.iter()
+            .filter_map(|piece| match piece {
+                Piece::Raw(s) => Some(s.as_str()),
+                Piece::Component(..) => None,
+            })
+            .collect());
+    }
+    let mut components: Vec<(usize, FormattedMessageComponent)> = vec![];
+    for piece in pieces {
+        let (i, component) = match piece {
+            Piece::Component(i, component) => (*i, component),
+            Piece::Raw(_) => continue,
+        };
+        match components.last_mut() {
+            Some((last_i, last)) if *last_i == i => merge(last, component),
+            _ => components.push((i, component.clone())),
+        }
+    }
+    let components: Vec<FormattedMessageComponent> = components.into_iter().map(|(_, component)| component).collect();
+    let raw = FormattedMessageDetail::new(String::new(), components.clone()).create_plain_text();
+    MessageDetail::Formatted(FormattedMessageDetail::new(raw, components))
+}
+
+/// Adds the contents of the component to another part of the same component.
+fn merge(component: &mut FormattedMessageComponent, other: &FormattedMessageComponent) {
+    match (component, other) {
+        (FormattedMessageComponent::Section(_, text), FormattedMessageComponent::Section(_, other))
+        | (FormattedMessageComponent::Text(text), FormattedMessageComponent::Text(other))
+        | (FormattedMessageComponent::Quote(text), FormattedMessageComponent::Quote(other)) => {
+            for part in other {
+                match text.last_mut() {
+                    Some(last) if last.get_styles() == part.get_styles() => {
+                        *last = FormattedString::new(format!("{}{}", last.get_string(), part.get_string()), part.get_styles().clone());
+                    }
+                    _ => text.push(part.clone()),
+                }
+            }
+        }
+        (FormattedMessageComponent::List(items), FormattedMessageComponent::List(other)) => items.extend(other.iter().cloned()),
+        (FormattedMessageComponent::Table(rows), FormattedMessageComponent::Table(other)) => rows.extend(other.iter().cloned()),
+        _ => {}
+    }
+}
+
+/// Groups the pieces into at most `max_chunks` chunks that each fit, splitting pieces that are too long on their own.
+/// Returns `None` if a piece can't be made to fit, even on its own.
+fn chunks<F: Fn(&[Piece]) -> bool>(pieces: Vec<Piece>, fits: F, max_chunks: usize) -> Option<Vec<Vec<Piece>>> {
+    let mut chunks = vec![];
+    let mut chunk: Vec<Piece> = vec![];
+    for mut piece in pieces {
+        loop {
+            chunk.push(piece);
+            if fits(&chunk) {
+                break;
+            }
+            piece = chunk.pop().unwrap();
+            if chunk.is_empty() {
+                let (head, tail) = split_piece(&piece, |head| fits(std::slice::from_ref(head)))?;
+                chunk.push(head);
+                piece = tail;
+            }
+            chunks.push(mem::take(&mut chunk));
+            if chunks.len() == max_chunks {
+                return Some(chunks);
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    Some(chunks)
+}
+
+/// Splits the piece at the last character where the start of it still fits.
+fn split_piece<F: Fn(&Piece) -> bool>(piece: &Piece, fits: F) -> Option<(Piece, Piece)> {
+    let len = piece_len(piece);
+    // The most characters that are known to fit, and the fewest that are known not to.
+    let (mut low, mut high) = (0, len);
+    while high - low > 1 {
+        let mid = (low + high) / 2;
+        if fits(&split_piece_at(piece, mid).0) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    if low == 0 {
+        return None;
+    }
+    Some(split_piece_at(piece, low))
+}
+
+/// The number of characters in the text of the piece.
+fn piece_len(piece: &Piece) -> usize {
+    let text_len = |text: &[FormattedString]| text.iter().map(|part| part.get_string().chars().count()).sum();
+    match piece {
+        Piece::Raw(s) => s.chars().count(),
+        Piece::Component(_, component) => match component {
+            FormattedMessageComponent::Section(_, text)
+            | FormattedMessageComponent::Text(text)
+            | FormattedMessageComponent::Quote(text) => text_len(text),
+            FormattedMessageComponent::List(items) => items.iter().map(|item| text_len(item)).sum(),
+            FormattedMessageComponent::Table(rows) => rows.iter().map(|(_, value)| text_len(value)).sum(),
+            FormattedMessageComponent::Rule => 0,
+        },
+    }
+}
+
+fn split_piece_at(piece: &Piece, at: usize) -> (Piece, Piece) {
+    match piece {
+        Piece::Raw(s) => {
+            let (head, tail) = split_str_at(s, at);
+            (Piece::Raw(head.to_owned()), Piece::Raw(tail.to_owned()))
+        }
+        Piece::Component(i, component) => {
+            let (head, tail) = match component {
+                FormattedMessageComponent::Section(title, text) => {
+                    let (head, tail) = split_text_at(text, at);
+                    (FormattedMessageComponent::Section(title.clone(), head), FormattedMessageComponent::Section(title.clone(), tail))
+                }
+                FormattedMessageComponent::Text(text) => {
+                    let (head, tail) = split_text_at(text, at);
+                    (FormattedMessageComponent::Text(head), FormattedMessageComponent::Text(tail))
+                }
+                FormattedMessageComponent::Quote(text) => {
+                    let (head, tail) = split_text_at(text, at);
+                    (FormattedMessageComponent::Quote(head), FormattedMessageComponent::Quote(tail))
+                }
+                // Pieces of lists and tables only have one item / row.
+                FormattedMessageComponent::List(items) => {
+                    let (head, tail) = split_text_at(items.first().map_or(&[][..], |item| item), at);
+                    (FormattedMessageComponent::List(vec![head]), FormattedMessageComponent::List(vec![tail]))
+                }
+                FormattedMessageComponent::Table(rows) => {
+                    let (key, value) = rows.first().cloned().unwrap_or_default();
+                    let (head, tail) = split_text_at(&value, at);
+                    (FormattedMessageComponent::Table(vec![(key.clone(), head)]), FormattedMessageComponent::Table(vec![(key, tail)]))
+                }
+                FormattedMessageComponent::Rule => (FormattedMessageComponent::Rule, FormattedMessageComponent::Rule),
+            };
+            (Piece::Component(*i, head), Piece::Component(*i, tail))
+        }
+    }
+}
+
+/// Splits the text after the given number of characters, keeping the style of the part it splits.
+fn split_text_at(text: &[FormattedString], at: usize) -> (Vec<FormattedString>, Vec<FormattedString>) {
+    let mut head = vec![];
+    let mut tail = vec![];
+    let mut remaining = at;
+    for part in text {
+        let len = part.get_string().chars().count();
+        if remaining >= len {
+            head.push(part.clone());
+            remaining -= len;
+        } else if remaining > 0 {
+            let (start, end) = split_str_at(part.get_string(), remaining);
+            head.push(FormattedString::new(start, part.get_styles().clone()));
+            tail.push(FormattedString::new(end, part.get_styles().clone()));
+            remaining = 0;
+        } else {
+            tail.push(part.clone());
+        }
+    }
+    (head, tail)
+}
+
+fn split_str_at(s: &str, chars: usize) -> (&str, &str) {
+    let index = s.char_indices().nth(chars).map_or(s.len(), |(i, _)| i);
+    s.split_at(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Level;
+    use crate::message::action::Action;
+    use crate::message::author::Author;
+    use crate::message::builder::MessageBuilder;
+    use crate::message::detail_builder::FormattedStringAppendable;
+    use crate::util::markdown::{MarkdownDialect, MarkdownMessageDetail};
+    use super::*;
+
+    fn raw_message(text: &str) -> Message {
+        Message::new(Level::Info, Some("Backup".to_owned()), MessageDetail::Raw(text.to_owned()), None, Author::parse("backup.sh".to_owned()), 0)
+            .with_actions(vec![Action::new("Log", "https://example.com/log")])
+    }
+
+    fn detail_fits(max: usize) -> impl Fn(&Message) -> bool {
+        move |message| message.get_message_detail().raw().chars().count() <= max
+    }
+
+    #[test]
+    fn test_fits() {
+        let message = raw_message("Short");
+        assert!(matches!(fit(&message, Overflow::Truncate, detail_fits(100))[..], [Cow::Borrowed(_)]));
+    }
+
+    #[test]
+    fn test_truncate() {
+        let text = "line one\nline two\nline three\nline four\nline five\nline six\n";
+        let message = raw_message(text);
+        let fitted = fit(&message, Overflow::Truncate, detail_fits(50));
+        assert_eq!(fitted.len(), 1);
+        assert_eq!(fitted[0].get_message_detail().raw(), "line one\n\n... (49 more bytes in message.txt)");
+        let attachment = fitted[0].get_attachments().last().expect("Should attach the full text");
+        assert_eq!(attachment.get_name(), "message.txt");
+        assert_eq!(attachment.read().unwrap().as_ref(), text.as_bytes());
+
+        let truncated = truncate(&message, detail_fits(30));
+        assert_eq!(truncated.get_message_detail().raw(), "line one\n\n... (49 more bytes)");
+        assert!(truncated.get_attachments().is_empty(), "Should not attach the full text");
+    }
+
+    #[test]
+    fn test_split_max_parts() {
+        let text: String = (0..MAX_PARTS * 10).map(|i| format!("line {:02}\n", i)).collect();
+        let message = raw_message(&text);
+        let fitted = fit(&message, Overflow::Split, detail_fits(50));
+        assert_eq!(fitted.len(), MAX_PARTS);
+        assert_eq!(fitted[0].get_title().as_deref(), Some("Backup (1/10)"));
+        let last = fitted.last().unwrap();
+        assert!(last.get_message_detail().raw().ends_with(" more bytes in message.txt)"), "{}", last.get_message_detail().raw());
+        assert_eq!(last.get_attachments().last().unwrap().read().unwrap().as_ref(), text.as_bytes());
+        assert!(fitted.iter().all(|part| detail_fits(50)(part)));
+    }
+
+    #[test]
+    fn test_split() {
+        let message = raw_message("line one\nline two\nthis line is too long on its own\n");
+        let fitted = fit(&message, Overflow::Split, detail_fits(20));
+        let details: Vec<&str> = fitted.iter().map(|m| m.get_message_detail().raw()).collect();
+        assert_eq!(details, vec!["line one\nline two\n", "this line is too lon", "g on its own\n"]);
+        assert_eq!(fitted[0].get_title().as_deref(), Some("Backup (1/3)"));
+        assert_eq!(fitted[2].get_title().as_deref(), Some("Backup (3/3)"));
+        assert!(fitted[0].get_actions().is_empty());
+        assert_eq!(fitted[2].get_actions().len(), 1, "Actions should be on the last part");
+    }
+
+    #[test]
+    fn test_split_formatted() {
+        let mut builder = MessageBuilder::new();
+        builder.body(|body| {
+            body.section("Output", |section| {
+                section.append_plain("first\n")
+                    .append_styled("bold text\nmore bold\n", Style::Bold);
+            });
+        });
+        let message = builder.build();
+        let markdown_fits = |message: &Message| match message.get_message_detail() {
+            MessageDetail::Formatted(formatted) => formatted.create_markdown(MarkdownDialect::Discord).chars().count() <= 40,
+            MessageDetail::Raw(_) => true,
+        };
+        let fitted = fit(&message, Overflow::Split, markdown_fits);
+        let markdown: Vec<String> = fitted.iter()
+            .map(|m| match m.get_message_detail() {
+                MessageDetail::Formatted(formatted) => formatted.create_markdown(MarkdownDialect::Discord),
+                MessageDetail::Raw(_) => panic!("Should be formatted"),
+            })
+            .collect();
+        assert_eq!(markdown, vec!["**__Output__**\nfirst\n**bold text**\n\n", "**__Output__**\n**more bold**\n\n"]);
+    }
+
+    #[test]
+    fn test_cannot_fit() {
+        let message = raw_message("Too long");
+        let fitted = fit(&message, Overflow::Split, |_| false);
+        assert_eq!(fitted, vec![Cow::Borrowed(&message)]);
+    }
+}
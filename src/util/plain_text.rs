@@ -0,0 +1,65 @@
+use crate::message::formatted_detail::{FormattedMessageComponent, FormattedMessageDetail, FormattedString, Style};
+
+/// Renders a formatted message as plain text, for destinations that can't show formatting,
+/// e.g. sections become `Title:` followed by their text, and links become `text (url)`.
+pub trait PlainTextMessageDetail {
+    fn create_plain_text(&self) -> String;
+}
+
+impl PlainTextMessageDetail for FormattedMessageDetail {
+    fn create_plain_text(&self) -> String {
+        let mut s = String::new();
+        for component in self.components() {
+            if !s.is_empty() && !s.ends_with('\n') {
+                s.push('\n');
+            }
+            s.push_str(&component_to_plain_text(component));
+        }
+        s.trim_end_matches('\n').to_owned()
+    }
+}
+
+fn component_to_plain_text(component: &FormattedMessageComponent) -> String {
+    match component {
+        FormattedMessageComponent::Section(title, contents) => format!("{}:\n{}", title, to_plain_text(contents)),
+        FormattedMessageComponent::Text(text) => to_plain_text(text),
+        FormattedMessageComponent::List(items) => items.iter()
+            .map(|item| format!("- {}\n", to_plain_text(item)))
+            .collect(),
+        FormattedMessageComponent::Table(rows) => rows.iter()
+            .map(|(key, value)| format!("{}: {}\n", key, to_plain_text(value)))
+            .collect(),
+        FormattedMessageComponent::Quote(text) => to_plain_text(text).lines()
+            .map(|line| format!("> {}\n", line))
+            .collect(),
+        FormattedMessageComponent::Rule => "----------\n".to_owned(),
+    }
+}
+
+/// The text of the parts, with the url after any link whose text isn't the url.
+pub(crate) fn to_plain_text(parts: &[FormattedString]) -> String {
+    parts.iter()
+        .map(|part| {
+            let link = part.get_styles().iter().find_map(|style| match style {
+                Style::Link { url } if url != part.get_string() => Some(url),
+                _ => None,
+            });
+            match link {
+                Some(url) => format!("{} ({})", part.get_string(), url),
+                None => part.get_string().to_owned(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::detail_builder::test::example;
+    use super::*;
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(example().create_plain_text(), "Disk_usage:\n1.5 GB *free*, df -hdocs (https://example.com/a_(b))\n/: 45%\n/home: 95%\n\
+                                                   - a < b & c\n- https://example.com\n----------\n> No space\n> left");
+    }
+}
@@ -0,0 +1,264 @@
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use crate::config::interpolate::{self, Reference, ENV_KEY, FILE_KEY, VALUE_KEY};
+use crate::send_error::partial::PartialSendError;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Secrets shorter than this are not [redacted](redact), since they would
+/// replace common words in the text rather than the secret itself.
+const MIN_REDACTED_LEN: usize = 6;
+
+/// A sensitive string, such as a token or password.
+///
+/// The value is hidden from [`Debug`] output, and is removed from the errors of the destination it belongs to,
+/// see [`MessageDestination::secrets`](crate::destination::MessageDestination::secrets).
+///
+/// The value can be read from an environment variable or file, see [`interpolate`](crate::config::interpolate).
+/// If it was, it is serialized as that reference rather than as the value, so that it is not written into config files.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret {
+    secret: String,
+    reference: Option<Reference>,
+}
+
+impl Secret {
+    pub fn new(secret: String) -> Self {
+        Self {
+            secret,
+            reference: None,
+        }
+    }
+
+    /// Gets the actual value of the secret.
+    pub fn expose(&self) -> &str {
+        &self.secret
+    }
+
+    /// Where the value was read from, if it was not written in the config itself.
+    pub fn get_reference(&self) -> Option<&Reference> {
+        self.reference.as_ref()
+    }
+}
+
+impl From<String> for Secret {
+    fn from(s: String) -> Self {
+        Self::new(s)
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        let (key, value) = match &self.reference {
+            None => return serializer.serialize_str(&self.secret),
+            Some(Reference::Template(template)) => return serializer.serialize_str(template),
+            Some(Reference::File(path)) => (FILE_KEY, path),
+            Some(Reference::Env(var)) => (ENV_KEY, var),
+        };
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(key, value)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        deserializer.deserialize_any(SecretVisitor)
+    }
+}
+
+struct SecretVisitor;
+
+impl<'de> Visitor<'de> for SecretVisitor {
+    type Value = Secret;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "a string, or a table with a '{}' or '{}' key", FILE_KEY, ENV_KEY)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Secret, E> {
+        let secret = interpolate::expand_env(v).map_err(E::custom)?;
+        let reference = if secret != v { Some(Reference::Template(v.to_owned())) } else { None };
+        Ok(Secret { secret, reference })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Secret, A::Error> {
+        let mut reference = None;
+        let mut value = None;
+        while let Some((key, v)) = map.next_entry::<String, String>()? {
+            let new_reference = match key.as_str() {
+                FILE_KEY => Reference::File(v),
+                ENV_KEY => Reference::Env(v),
+                VALUE_KEY => {
+                    value = Some(v);
+                    continue;
+                }
+                _ => return Err(de::Error::unknown_field(&key, &[FILE_KEY, ENV_KEY])),
+            };
+            if reference.replace(new_reference).is_some() {
+                return Err(de::Error::custom(format_args!("only one of '{}' and '{}' can be given", FILE_KEY, ENV_KEY)));
+            }
+        }
+        let reference = reference.ok_or_else(|| de::Error::custom(format_args!("expected a '{}' or '{}' key", FILE_KEY, ENV_KEY)))?;
+        // The value is only missing if the config wasn't interpolated, in which case files are relative to the working directory.
+        let secret = match value {
+            Some(value) => value,
+            None => interpolate::resolve(&reference, None).map_err(de::Error::custom)?,
+        };
+        Ok(Secret { secret, reference: Some(reference) })
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Secret {
+    fn schema_name() -> String {
+        "Secret".to_owned()
+    }
+
+    /// A string, or a reference to a file or environment variable containing it,
+    /// see [`interpolate`](crate::config::interpolate)
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{InstanceType, Metadata, ObjectValidation, Schema, SchemaObject, SubschemaValidation};
+
+        let string = gen.subschema_for::<String>();
+        let reference = |key: &str, description: &str| -> Schema {
+            SchemaObject {
+                instance_type: Some(InstanceType::Object.into()),
+                metadata: Some(Box::new(Metadata { description: Some(description.to_owned()), ..Default::default() })),
+                object: Some(Box::new(ObjectValidation {
+                    properties: [(key.to_owned(), string.clone())].into_iter().collect(),
+                    required: [key.to_owned()].into_iter().collect(),
+                    additional_properties: Some(Box::new(Schema::Bool(false))),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }.into()
+        };
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![
+                    string.clone(),
+                    reference(FILE_KEY, "Read the value from this file"),
+                    reference(ENV_KEY, "Read the value from this environment variable"),
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
+/// Replaces the given [`Secret`]s in the text with a placeholder.
+pub fn redact(s: &str, secrets: &[&Secret]) -> String {
+    let mut redacted = s.to_owned();
+    for secret in secrets.iter().filter(|secret| secret.expose().len() >= MIN_REDACTED_LEN) {
+        redacted = redacted.replace(secret.expose(), REDACTED);
+    }
+    redacted
+}
+
+/// Removes the given [`Secret`]s from the error, e.g. a bot token in a url.
+/// The error is only replaced if it contains any of them.
+pub fn redact_error(err: Box<dyn Error>, secrets: &[&Secret]) -> Box<dyn Error> {
+    // Keep the error recognisable as a partial failure.
+    let err = match err.downcast::<PartialSendError>() {
+        Ok(partial) => return Box::new(partial.map_error(|err| redact_error(err, secrets))),
+        Err(err) => err,
+    };
+    let (display, debug) = (err.to_string(), format!("{:?}", err));
+    let (redacted_display, redacted_debug) = (redact(&display, secrets), redact(&debug, secrets));
+    if redacted_display == display && redacted_debug == debug {
+        return err;
+    }
+    Box::new(RedactedError {
+        display: redacted_display,
+        debug: redacted_debug,
+    })
+}
+
+/// An error that contained secrets, with them removed, see [`redact_error`]
+struct RedactedError {
+    display: String,
+    debug: String,
+}
+
+impl Display for RedactedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display)
+    }
+}
+
+impl Debug for RedactedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.debug)
+    }
+}
+
+impl Error for RedactedError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let secret = Secret::new("hunter2-but-longer".to_owned());
+        assert_eq!(format!("{:?}", secret), "Secret([REDACTED])");
+        assert_eq!(redact("Failed to log in with hunter2-but-longer", &[&secret]), "Failed to log in with [REDACTED]");
+        assert_eq!(secret.expose(), "hunter2-but-longer");
+
+        let short = Secret::new("a".to_owned());
+        assert_eq!(redact("Failed to log in", &[&short]), "Failed to log in");
+
+        let err = redact_error("Error sending to hunter2-but-longer".into(), &[&secret]);
+        assert_eq!(err.to_string(), "Error sending to [REDACTED]");
+        assert!(!format!("{:?}", err).contains("hunter2"));
+
+        let partial = PartialSendError::new(Some("1".to_owned()), "Error sending to hunter2-but-longer".into());
+        let err = redact_error(Box::new(partial), &[&secret]);
+        assert_eq!(crate::send_error::partial::sent_id(err.as_ref()), Some("1".to_owned()));
+        assert!(!err.to_string().contains("hunter2"));
+    }
+
+    #[test]
+    fn test_serialize_reference() {
+        #[derive(Serialize, Deserialize)]
+        struct Destination {
+            token: Secret,
+            password: Secret,
+            url: Secret,
+        }
+
+        std::env::set_var("RNOTIFY_TEST_SECRET", "abc123");
+        // The password is the same as the file's contents, but was written in the config, so should stay that way.
+        let dest: Destination = toml::from_str(r#"
+            token = { "$file" = "test/secret.txt" }
+            password = "123456:abcdef"
+            url = "https://example.com/${RNOTIFY_TEST_SECRET}"
+        "#).unwrap();
+        assert_eq!(dest.token.expose(), "123456:abcdef");
+        assert_eq!(dest.url.expose(), "https://example.com/abc123");
+
+        let value = toml::Value::try_from(&dest).unwrap();
+        let expected: toml::Value = toml::from_str(r#"
+            password = "123456:abcdef"
+            url = "https://example.com/${RNOTIFY_TEST_SECRET}"
+            token = { "$file" = "test/secret.txt" }
+        "#).unwrap();
+        assert_eq!(value, expected);
+
+        assert!(toml::from_str::<Destination>(r#"token = { env = "X" }
+            password = "a"
+            url = "b""#).is_err(), "Only $file and $env are references");
+    }
+}
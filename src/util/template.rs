@@ -0,0 +1,389 @@
+//! A small templating language for customising how destinations lay out messages.
+//!
+//! - `{field}` is replaced with the value of the field, or nothing if the message doesn't have it.
+//! - `{field:option}` changes how the field is displayed, see below.
+//! - `{?field}...{/field}` is only included if the message has the field, e.g. `{?title}{title}: {/title}`
+//! - `{{` and `}}` are a literal `{` and `}`
+//!
+//! The fields are:
+//! - `level` - e.g. `Warn`
+//! - `title`
+//! - `component` - e.g. `database/backup`
+//! - `author` - e.g. `root@server/backup.sh`
+//! - `timestamp` - when the message was sent, in the destination's [`TimeFormat`].
+//!   The option is a [`strftime`](chrono::format::strftime) format, optionally followed by `|` and a time zone,
+//!   e.g. `{timestamp:%Y-%m-%d %H:%M:%S|UTC}`. `{timestamp:unix}` and `{timestamp:unix_millis}` give the unix timestamp,
+//!   and `{timestamp:relative}` how long ago it was sent, e.g. `3 minutes ago`.
+//! - `raw` - the plain text of the message
+//! - `detail` - the formatted text of the message, in the format the destination uses (e.g. markdown for Discord)
+//! - `fields` - the message's key / value fields, e.g. `env=prod job_id=123`
+//! - `alert` - the key and state of the message's alert, e.g. `disk_full: Resolved after 42 minutes`
+//! - `attachments` - the names and sizes of the message's attachments, e.g. `build.log (1.5 KiB), graph.png (20.0 KiB)`
+//! - `actions` - the message's links, one per line, e.g. `Dashboard: https://grafana.example.com/d/abc`
+//!
+//! Text fields can be given the option `inline` (newlines become `\n`), `upper` or `lower`.
+//!
+//! For example, a compact one line message:
+//! ```toml
+//! template = "[{level}] {?component}{component}: {/component}{?title}{title} - {/title}{raw:inline}"
+//! ```
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::message::Message;
+use crate::util::time_format::{self, TimeFormat};
+use crate::util::timezone::{self, Timezone};
+
+/// A parsed template, see the [module documentation](self)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(Field, FieldOption),
+    Conditional(Field, Vec<Part>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Level,
+    Title,
+    Component,
+    Author,
+    Timestamp,
+    Raw,
+    Detail,
+    Fields,
+    Attachments,
+    Actions,
+    Alert,
+}
+
+const FIELDS: [(&str, Field); 11] = [
+    ("level", Field::Level),
+    ("title", Field::Title),
+    ("component", Field::Component),
+    ("author", Field::Author),
+    ("timestamp", Field::Timestamp),
+    ("raw", Field::Raw),
+    ("detail", Field::Detail),
+    ("fields", Field::Fields),
+    ("attachments", Field::Attachments),
+    ("actions", Field::Actions),
+    ("alert", Field::Alert),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum FieldOption {
+    None,
+    Inline,
+    Upper,
+    Lower,
+    TimestampFormat(String, Option<Timezone>),
+    Unix,
+    UnixMillis,
+    Relative,
+}
+
+impl Field {
+    fn parse(name: &str) -> Result<Self, String> {
+        FIELDS.iter()
+            .find(|(field_name, _)| *field_name == name)
+            .map(|(_, field)| *field)
+            .ok_or_else(|| {
+                let names: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                format!("Unknown field '{}', expected one of: {}", name, names.join(", "))
+            })
+    }
+
+    fn parse_option(&self, option: &str) -> Result<FieldOption, String> {
+        if *self == Field::Timestamp {
+            return match option {
+                "unix" => Ok(FieldOption::Unix),
+                "unix_millis" => Ok(FieldOption::UnixMillis),
+                "relative" => Ok(FieldOption::Relative),
+                _ => {
+                    let (format, timezone) = match option.rsplit_once('|') {
+                        Some((format, timezone)) => (format, Some(timezone.parse()?)),
+                        None => (option, None),
+                    };
+                    if !timezone::is_valid_format(format) {
+                        return Err(format!("Invalid timestamp format '{}'", format));
+                    }
+                    Ok(FieldOption::TimestampFormat(format.to_owned(), timezone))
+                }
+            };
+        }
+        match option {
+            "inline" => Ok(FieldOption::Inline),
+            "upper" => Ok(FieldOption::Upper),
+            "lower" => Ok(FieldOption::Lower),
+            _ => Err(format!("Unknown option '{}', expected inline, upper or lower", option)),
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        // Each open conditional, with the parts inside it so far.
+        let mut stack: Vec<(Field, Vec<Part>)> = vec![];
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut rest = s;
+
+        while let Some(i) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..i]);
+            let c = rest.as_bytes()[i];
+            rest = &rest[i + 1..];
+            if rest.as_bytes().first() == Some(&c) {
+                // Escaped brace.
+                literal.push(c as char);
+                rest = &rest[1..];
+                continue;
+            }
+            if c == b'}' {
+                return Err("Unexpected '}', use '}}' for a literal '}'".to_owned());
+            }
+
+            let end = rest.find('}').ok_or("Unclosed '{', use '{{' for a literal '{'")?;
+            let tag = &rest[..end];
+            rest = &rest[end + 1..];
+            if !literal.is_empty() {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+
+            if let Some(name) = tag.strip_prefix('?') {
+                let field = Field::parse(name)?;
+                stack.push((field, std::mem::take(&mut parts)));
+            } else if let Some(name) = tag.strip_prefix('/') {
+                let field = Field::parse(name)?;
+                match stack.pop() {
+                    Some((open, outer)) if open == field => {
+                        let inner = std::mem::replace(&mut parts, outer);
+                        parts.push(Part::Conditional(field, inner));
+                    }
+                    _ => return Err(format!("Unexpected '{{/{}}}'", name)),
+                }
+            } else {
+                let (name, option) = match tag.split_once(':') {
+                    Some((name, option)) => (name, Some(option)),
+                    None => (tag, None),
+                };
+                let field = Field::parse(name)?;
+                let option = match option {
+                    Some(option) => field.parse_option(option)?,
+                    None => FieldOption::None,
+                };
+                parts.push(Part::Field(field, option));
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        if let Some((field, _)) = stack.last() {
+            let name = FIELDS.iter().find(|(_, f)| f == field).unwrap().0;
+            return Err(format!("Missing '{{/{}}}'", name));
+        }
+        Ok(Self {
+            source: s.to_owned(),
+            parts,
+        })
+    }
+
+    /// Renders the message using this template.
+    /// `detail` is the message's formatted text, in whatever format the destination uses,
+    /// and timestamps are shown using the given format unless the template says otherwise.
+    ///
+    /// Every other field is passed through `escape`, so that it is shown as written in formats such as HTML or markdown.
+    /// Use [`no_escape`] for plain text.
+    pub fn render(&self, message: &Message, detail: &str, time_format: &TimeFormat, escape: fn(&str) -> String) -> String {
+        let context = RenderContext { message, detail, time_format, escape };
+        let mut result = String::new();
+        render_parts(&self.parts, &context, &mut result);
+        result
+    }
+}
+
+struct RenderContext<'a> {
+    message: &'a Message,
+    detail: &'a str,
+    time_format: &'a TimeFormat,
+    escape: fn(&str) -> String,
+}
+
+/// Leaves fields as they are, for destinations that send plain text, see [`Template::render`]
+pub fn no_escape(s: &str) -> String {
+    s.to_owned()
+}
+
+fn render_parts(parts: &[Part], context: &RenderContext, result: &mut String) {
+    for part in parts {
+        match part {
+            Part::Literal(s) => result.push_str(s),
+            Part::Field(Field::Detail, _) => result.push_str(context.detail),
+            Part::Field(field, option) => {
+                if let Some(value) = field_value(*field, option, context) {
+                    result.push_str(&(context.escape)(&value));
+                }
+            }
+            Part::Conditional(field, inner) => {
+                if field_value(*field, &FieldOption::None, context).is_some_and(|v| !v.is_empty()) {
+                    render_parts(inner, context, result);
+                }
+            }
+        }
+    }
+}
+
+fn field_value(field: Field, option: &FieldOption, context: &RenderContext) -> Option<String> {
+    let message = context.message;
+    let value = match field {
+        Field::Level => format!("{:?}", message.get_level()),
+        Field::Title => message.get_title().clone()?,
+        Field::Component => message.get_component().as_ref()?.to_string(),
+        Field::Author => message.get_author().to_string(),
+        Field::Raw => message.get_message_detail().raw().to_owned(),
+        Field::Detail => context.detail.to_owned(),
+        Field::Fields => message.get_fields().iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Field::Attachments => message.get_attachments().iter()
+            .map(|attachment| attachment.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Field::Actions => message.get_actions().iter()
+            .map(|action| action.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Field::Alert => {
+            let alert = message.get_alert().as_ref()?;
+            format!("{}: {}", alert.get_key(), alert.describe(message.get_unix_timestamp_millis()))
+        }
+        Field::Timestamp => {
+            let millis = message.get_unix_timestamp_millis();
+            return Some(match option {
+                FieldOption::Unix => (millis / 1000).to_string(),
+                FieldOption::UnixMillis => millis.to_string(),
+                FieldOption::TimestampFormat(format, timezone) => {
+                    let timezone = timezone.or(context.time_format.get_timezone()).unwrap_or_default();
+                    timezone.format(millis, format)
+                }
+                FieldOption::Relative => time_format::relative(millis, chrono::Utc::now().timestamp_millis()),
+                _ => context.time_format.format(millis),
+            });
+        }
+    };
+    Some(match option {
+        FieldOption::Inline => value.lines().collect::<Vec<_>>().join("\\n"),
+        FieldOption::Upper => value.to_uppercase(),
+        FieldOption::Lower => value.to_lowercase(),
+        _ => value,
+    })
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Serialize for Template {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let s = String::deserialize(deserializer)?;
+        Template::parse(&s).map_err(|e| serde::de::Error::custom(format!("Invalid template: {}", e)))
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Template {
+    fn schema_name() -> String {
+        "Template".to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = gen.subschema_for::<String>().into_object();
+        schema.metadata().description = Some("A message template, e.g. \"[{level}] {?title}{title}: {/title}{raw:inline}\"".to_owned());
+        schema.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::attachment::Attachment;
+    use crate::message::builder::MessageBuilder;
+    use crate::message::component::Component;
+    use crate::message::Level;
+    use super::*;
+
+    fn message() -> MessageBuilder {
+        let mut builder = MessageBuilder::new();
+        builder.level(Level::Warn)
+            // 2022-07-01T12:00:00Z
+            .timestamp(1656676800000)
+            .body(|body| {
+                body.raw("Disk is\nnearly full".to_owned());
+            });
+        builder
+    }
+
+    fn render(template: &str, message: &Message) -> String {
+        let time_format = TimeFormat::new(Some(Timezone::Utc), None).unwrap();
+        Template::parse(template).expect("Should parse").render(message, "<b>detail</b>", &time_format, no_escape)
+    }
+
+    #[test]
+    fn test_render() {
+        let mut builder = message();
+        let template = "[{level:upper}] {?component}{component}: {/component}{?title}{title} - {/title}{raw:inline}";
+        assert_eq!(render(template, &builder.build_clone()), "[WARN] Disk is\\nnearly full");
+
+        builder.title("Backup").component(Component::from("db/backup"));
+        assert_eq!(render("{?fields}({fields}){/fields}", &builder.build_clone()), "");
+        builder.field("job_id", 123).field("env", "prod");
+        assert_eq!(render("{?fields}({fields}){/fields}", &builder.build_clone()), "(env=prod job_id=123)");
+        builder.attach(Attachment::from_bytes("graph.png", vec![0; 2048]));
+        assert_eq!(render("{?attachments}Attached: {attachments}{/attachments}", &builder.build_clone()), "Attached: graph.png (2.0 KiB)");
+        builder.action("Dashboard", "https://grafana.example.com/d/abc").action("Runbook", "https://wiki.example.com/backup");
+        assert_eq!(render("{actions:inline}", &builder.build_clone()), "Dashboard: https://grafana.example.com/d/abc\\nRunbook: https://wiki.example.com/backup");
+        assert_eq!(render(template, &builder.build_clone()), "[WARN] db/backup: Backup - Disk is\\nnearly full");
+
+        let m = builder.build();
+        assert_eq!(render("{timestamp:%H:%M|Europe/London} {timestamp:unix}", &m), "13:00 1656676800");
+        assert_eq!(render("{timestamp:%H:%M} {timestamp}", &m), "12:00 2022-07-01T12:00:00.000Z");
+        assert_eq!(render("{{{detail}}}", &m), "{<b>detail</b>}");
+        assert_eq!(render("@ {author}", &m), format!("@ {}", m.get_author()));
+    }
+
+    #[test]
+    fn test_render_escaped() {
+        let mut builder = message();
+        builder.title("<script> & \"co\"");
+        let time_format = TimeFormat::new(Some(Timezone::Utc), None).unwrap();
+        let template = Template::parse("<b>{title:upper}</b> {detail}").expect("Should parse");
+        let rendered = template.render(&builder.build(), "<i>detail</i>", &time_format, crate::util::html::escape_html);
+        assert_eq!(rendered, "<b>&lt;SCRIPT&gt; &amp; \"CO\"</b> <i>detail</i>");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("{nonexistent}").is_err());
+        assert!(Template::parse("{title:%Y}").is_err());
+        assert!(Template::parse("{timestamp:%Y|Middle/Earth}").is_err());
+        assert!(Template::parse("{?title}{title}").is_err());
+        assert!(Template::parse("{?title}{/component}").is_err());
+        assert!(Template::parse("{title").is_err());
+        assert!(Template::parse("title}").is_err());
+    }
+}
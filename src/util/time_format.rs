@@ -0,0 +1,162 @@
+//! How destinations display the time a message was sent.
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::util::timezone::{self, Timezone};
+
+/// The time zone and format used to display timestamps.
+///
+/// Each destination has its own, which can be set in the config file alongside its other settings,
+/// and otherwise falls back to the `timezone` and `timestamp_format` at the top of the config file:
+/// ```toml
+/// timezone = "UTC"
+///
+/// [[destinations]]
+/// type = "File"
+/// id = "log"
+/// path = "/var/log/rnotify.log"
+/// timezone = "Europe/London"
+/// timestamp_format = "%Y-%m-%d %H:%M:%S"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TimeFormat {
+    /// The time zone timestamps are shown in, defaults to the local time zone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timezone: Option<Timezone>,
+    /// A [`strftime`](chrono::format::strftime) format, defaults to RFC 3339, e.g. `2022-07-01T13:00:00.000+01:00`
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_format")]
+    timestamp_format: Option<String>,
+}
+
+impl TimeFormat {
+    /// Creates a time format, returning an error if the [`strftime`](chrono::format::strftime) format is invalid.
+    pub fn new(timezone: Option<Timezone>, timestamp_format: Option<String>) -> Result<Self, String> {
+        if let Some(format) = &timestamp_format {
+            check_format(format)?;
+        }
+        Ok(Self {
+            timezone,
+            timestamp_format,
+        })
+    }
+
+    pub fn get_timezone(&self) -> Option<Timezone> {
+        self.timezone
+    }
+
+    pub fn get_timestamp_format(&self) -> &Option<String> {
+        &self.timestamp_format
+    }
+
+    /// Fills in any settings that are not set with those from `defaults`.
+    pub fn apply_defaults(&mut self, defaults: &TimeFormat) {
+        if self.timezone.is_none() {
+            self.timezone = defaults.timezone;
+        }
+        if self.timestamp_format.is_none() {
+            self.timestamp_format = defaults.timestamp_format.clone();
+        }
+    }
+
+    /// This format, using the given time zone if one is not set.
+    pub fn or_timezone(&self, timezone: Timezone) -> Self {
+        Self {
+            timezone: Some(self.timezone.unwrap_or(timezone)),
+            timestamp_format: self.timestamp_format.clone(),
+        }
+    }
+
+    /// Formats the given unix timestamp.
+    pub fn format(&self, unix_timestamp_millis: i64) -> String {
+        let timezone = self.timezone.unwrap_or_default();
+        match &self.timestamp_format {
+            Some(format) => timezone.format(unix_timestamp_millis, format),
+            None => timezone.to_rfc3339(unix_timestamp_millis),
+        }
+    }
+}
+
+/// Describes how long ago the given unix timestamp was, relative to `now_millis`,
+/// e.g. `3 minutes ago`, or `just now` if it was less than a minute ago.
+pub fn relative(unix_timestamp_millis: i64, now_millis: i64) -> String {
+    let millis = now_millis - unix_timestamp_millis;
+    match whole_units(millis.abs() / 1000) {
+        None => "just now".to_owned(),
+        Some(amount) if millis < 0 => format!("in {}", amount),
+        Some(amount) => format!("{} ago", amount),
+    }
+}
+
+/// Describes a length of time in its largest whole unit, e.g. `42 minutes`, or `less than a minute`.
+pub fn duration(millis: i64) -> String {
+    whole_units(millis.abs() / 1000).unwrap_or_else(|| "less than a minute".to_owned())
+}
+
+fn whole_units(seconds: i64) -> Option<String> {
+    const UNITS: [(i64, &str); 4] = [
+        (60 * 60 * 24 * 7, "week"),
+        (60 * 60 * 24, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+
+    let (unit_seconds, unit) = UNITS.iter().find(|(unit_seconds, _)| seconds >= *unit_seconds)?;
+    let amount = seconds / unit_seconds;
+    let plural = if amount == 1 { "" } else { "s" };
+    Some(format!("{} {}{}", amount, unit, plural))
+}
+
+/// Whether a message sent at the given time is old enough when it is displayed that it is worth saying
+/// how long ago it was sent, e.g. because it was deferred during quiet hours.
+pub fn is_late(unix_timestamp_millis: i64, now_millis: i64) -> bool {
+    now_millis - unix_timestamp_millis >= 60 * 1000
+}
+
+fn check_format(format: &str) -> Result<(), String> {
+    if timezone::is_valid_format(format) {
+        Ok(())
+    } else {
+        Err(format!("Invalid timestamp format '{}'", format))
+    }
+}
+
+pub(crate) fn deserialize_format<'de, D>(deserializer: D) -> Result<Option<String>, D::Error> where D: Deserializer<'de> {
+    let format = Option::<String>::deserialize(deserializer)?;
+    if let Some(format) = &format {
+        check_format(format).map_err(serde::de::Error::custom)?;
+    }
+    Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        // 2022-07-01T12:00:00Z
+        let millis = 1656676800000;
+        let london = Timezone::Named(chrono_tz::Europe::London);
+
+        let mut format = TimeFormat::new(None, Some("%H:%M".to_owned())).unwrap();
+        format.apply_defaults(&TimeFormat::new(Some(london), Some("%Y".to_owned())).unwrap());
+        assert_eq!(format.format(millis), "13:00");
+
+        let format = TimeFormat::default().or_timezone(Timezone::Utc);
+        assert_eq!(format.format(millis), "2022-07-01T12:00:00.000Z");
+
+        assert!(TimeFormat::new(None, Some("%Q".to_owned())).is_err());
+        assert!(toml::from_str::<TimeFormat>("timestamp_format = \"%Q\"").is_err());
+    }
+
+    #[test]
+    fn test_relative() {
+        let now = 1656676800000;
+        assert_eq!(relative(now - 30 * 1000, now), "just now");
+        assert_eq!(relative(now - 60 * 1000, now), "1 minute ago");
+        assert_eq!(relative(now - 3 * 60 * 1000 - 5000, now), "3 minutes ago");
+        assert_eq!(relative(now - 26 * 60 * 60 * 1000, now), "1 day ago");
+        assert_eq!(relative(now + 2 * 60 * 60 * 1000, now), "in 2 hours");
+        assert_eq!(duration(42 * 60 * 1000 + 5000), "42 minutes");
+        assert_eq!(duration(5000), "less than a minute");
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono::format::{Item, StrftimeItems};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The time zone that times are interpreted / displayed in.
+///
+/// Serialized as either `"Local"`, `"UTC"` or an [IANA time zone name](https://en.wikipedia.org/wiki/List_of_tz_database_time_zones)
+/// such as `"Europe/London"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timezone {
+    /// The time zone of the machine running rnotify.
+    #[default]
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+impl Timezone {
+    /// Gets the wall clock date and time in this time zone at the given unix timestamp.
+    pub fn naive_local(&self, unix_timestamp_millis: i64) -> NaiveDateTime {
+        let utc = to_utc(unix_timestamp_millis);
+        match self {
+            Timezone::Local => utc.with_timezone(&Local).naive_local(),
+            Timezone::Utc => utc.naive_utc(),
+            Timezone::Named(tz) => utc.with_timezone(tz).naive_local(),
+        }
+    }
+
+    /// Formats the given unix timestamp in this time zone, using a [`strftime`](chrono::format::strftime) format.
+    ///
+    /// The format must be valid, see [`is_valid_format`]
+    pub fn format(&self, unix_timestamp_millis: i64, format: &str) -> String {
+        let utc = to_utc(unix_timestamp_millis);
+        match self {
+            Timezone::Local => utc.with_timezone(&Local).format(format).to_string(),
+            Timezone::Utc => utc.format(format).to_string(),
+            Timezone::Named(tz) => utc.with_timezone(tz).format(format).to_string(),
+        }
+    }
+
+    /// Formats the given unix timestamp in this time zone as an RFC 3339 timestamp with milliseconds,
+    /// e.g. `2022-07-01T13:00:00.000+01:00`
+    pub fn to_rfc3339(&self, unix_timestamp_millis: i64) -> String {
+        let utc = to_utc(unix_timestamp_millis);
+        match self {
+            Timezone::Local => utc.with_timezone(&Local).to_rfc3339_opts(SecondsFormat::Millis, true),
+            Timezone::Utc => utc.to_rfc3339_opts(SecondsFormat::Millis, true),
+            Timezone::Named(tz) => utc.with_timezone(tz).to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
+
+/// Converts the unix timestamp to a date time, clamping it to the range chrono supports.
+/// A day is left at each end, so that converting it to any time zone doesn't overflow.
+fn to_utc(unix_timestamp_millis: i64) -> DateTime<Utc> {
+    const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+    let min = DateTime::<Utc>::MIN_UTC.timestamp_millis() + DAY_MILLIS;
+    let max = DateTime::<Utc>::MAX_UTC.timestamp_millis() - DAY_MILLIS;
+    Utc.timestamp_millis_opt(unix_timestamp_millis.clamp(min, max)).single().unwrap_or_default()
+}
+
+/// Whether the given [`strftime`](chrono::format::strftime) format is valid.
+pub fn is_valid_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Local" | "local" => Ok(Timezone::Local),
+            "UTC" | "Utc" | "utc" => Ok(Timezone::Utc),
+            _ => s.parse::<Tz>()
+                .map(Timezone::Named)
+                .map_err(|_| format!("Unknown time zone '{}'", s)),
+        }
+    }
+}
+
+impl Display for Timezone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Timezone::Local => write!(f, "Local"),
+            Timezone::Utc => write!(f, "UTC"),
+            Timezone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+impl Serialize for Timezone {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Timezone {
+    fn schema_name() -> String {
+        "Timezone".to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = gen.subschema_for::<String>().into_object();
+        schema.metadata().description = Some("\"Local\", \"UTC\" or an IANA time zone name, e.g. \"Europe/London\"".to_owned());
+        schema.into()
+    }
+}
+
+impl<'de> Deserialize<'de> for Timezone {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("Local".parse(), Ok(Timezone::Local));
+        assert_eq!("UTC".parse(), Ok(Timezone::Utc));
+        assert_eq!("Europe/London".parse(), Ok(Timezone::Named(chrono_tz::Europe::London)));
+        assert!("Middle/Earth".parse::<Timezone>().is_err());
+    }
+
+    #[test]
+    fn test_naive_local() {
+        // 2022-07-01T12:00:00Z
+        let millis = 1656676800000;
+        assert_eq!(Timezone::Utc.naive_local(millis).to_string(), "2022-07-01 12:00:00");
+        // British summer time is UTC+1
+        let london = Timezone::Named(chrono_tz::Europe::London);
+        assert_eq!(london.naive_local(millis).to_string(), "2022-07-01 13:00:00");
+    }
+
+    #[test]
+    fn test_format() {
+        // 2022-07-01T12:00:00Z
+        let millis = 1656676800000;
+        let london = Timezone::Named(chrono_tz::Europe::London);
+        assert_eq!(london.format(millis, "%H:%M %Z"), "13:00 BST");
+        assert_eq!(london.to_rfc3339(millis), "2022-07-01T13:00:00.000+01:00");
+        assert_eq!(Timezone::Utc.to_rfc3339(millis), "2022-07-01T12:00:00.000Z");
+        assert!(is_valid_format("%Y-%m-%d %H:%M:%S"));
+        assert!(!is_valid_format("%Q"));
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let zones = [Timezone::Local, Timezone::Utc, Timezone::Named(chrono_tz::America::New_York), Timezone::Named(chrono_tz::Asia::Tokyo)];
+        for timezone in zones {
+            for millis in [i64::MIN, i64::MAX] {
+                timezone.naive_local(millis);
+                timezone.format(millis, "%Y-%m-%d %H:%M");
+                timezone.to_rfc3339(millis);
+            }
+        }
+        assert_eq!(Timezone::Utc.naive_local(i64::MAX), Timezone::Utc.naive_local(i64::MAX - 1), "Should be clamped");
+    }
+}